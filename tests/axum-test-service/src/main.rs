@@ -74,10 +74,6 @@ struct ServiceMetrics {
     success_count: u64,
     failure_count: u64,
     errors: u64,
-
-    // Payload sizes (in bytes)
-    request_sizes: Vec<usize>,
-    response_sizes: Vec<usize>,
 }
 
 impl ServiceMetrics {
@@ -126,24 +122,6 @@ impl ServiceMetrics {
             (self.success_count as f64 / total as f64) * 100.0
         }
     }
-
-    fn avg_request_size(&self) -> Option<f64> {
-        if self.request_sizes.is_empty() {
-            None
-        } else {
-            Some(self.request_sizes.iter().sum::<usize>() as f64 / self.request_sizes.len() as f64)
-        }
-    }
-
-    fn avg_response_size(&self) -> Option<f64> {
-        if self.response_sizes.is_empty() {
-            None
-        } else {
-            Some(
-                self.response_sizes.iter().sum::<usize>() as f64 / self.response_sizes.len() as f64,
-            )
-        }
-    }
 }
 
 async fn init_tokio_metrics() -> TaskMonitor {
@@ -207,9 +185,8 @@ fn init_metrics() {
     describe_histogram!("request_processing_ms", "Request processing time");
     describe_histogram!("middleware_overhead_ms", "Additional time added by middleware");
 
-    // Size metrics
-    describe_histogram!("request_body_size_bytes", "Request payload size in bytes");
-    describe_histogram!("response_body_size_bytes", "Response payload size in bytes");
+    // Payload counts, sizes, masking time and send latency come from the SDK itself
+    treblle_core::metrics::describe();
 
     // Tokio runtime metrics
     describe_gauge!("tokio_tasks_instrumented_total", "Total number of instrumented tasks");
@@ -230,10 +207,6 @@ async fn process_request(
 ) -> Json<ApiResponse> {
     let start = Instant::now();
     let request_type = request_type.to_string();
-    let request_size = serde_json::to_vec(&payload.0).map(|v| v.len()).unwrap_or(0);
-
-    // Record request size
-    histogram!("request_size_bytes", "type" => request_type.clone()).record(request_size as f64);
 
     // Record request start
     counter!("requests_total", "type" => request_type.clone()).increment(1);
@@ -261,10 +234,6 @@ async fn process_request(
         sensitive_data: payload.sensitive_data.clone(),
     };
 
-    // Record response size
-    let response_size = serde_json::to_vec(&response).map(|v| v.len()).unwrap_or(0);
-    histogram!("response_size_bytes", "type" => request_type.clone()).record(response_size as f64);
-
     // Update success rate
     counter!("requests_success_total", "type" => request_type.clone()).increment(1);
 
@@ -277,8 +246,6 @@ async fn process_request(
         "regular" => {
             metrics.regular_requests += 1;
             metrics.regular_processing_times.push(duration_ms);
-            metrics.request_sizes.push(request_size);
-            metrics.response_sizes.push(response_size);
             metrics.success_count += 1;
         }
         "monitored" => {
@@ -349,10 +316,6 @@ async fn get_stats(State(state): State<AppState>) -> Json<ServiceMetrics> {
         success_count: metrics.success_count,
         failure_count: metrics.failure_count,
         errors: metrics.errors,
-
-        // Payload sizes
-        request_sizes: metrics.request_sizes.clone(),
-        response_sizes: metrics.response_sizes.clone(),
     })
 }
 
@@ -387,6 +350,21 @@ async fn main() {
             &[1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0],
         )
         .unwrap()
+        .set_buckets_for_metric(
+            Matcher::Full("treblle_masking_duration_ms".to_string()),
+            &[0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0],
+        )
+        .unwrap()
+        .set_buckets_for_metric(
+            Matcher::Full("treblle_send_duration_ms".to_string()),
+            &[5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2000.0],
+        )
+        .unwrap()
+        .set_buckets_for_metric(
+            Matcher::Full("treblle_payload_size_bytes".to_string()),
+            &[256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262_144.0, 1_048_576.0],
+        )
+        .unwrap()
         .install_recorder()
         .unwrap();
 
@@ -424,7 +402,7 @@ async fn main() {
     let monitored_routes = Router::new()
         .route("/api/with-treblle/json", post(handle_monitored_json))
         .route("/api/with-treblle/error", get(handle_error))
-        .treblle(treblle);
+        .treblle(treblle.clone());
 
     let app = Router::new()
         .merge(regular_routes)
@@ -441,7 +419,39 @@ async fn main() {
 
     let listener = tokio::net::TcpListener::bind(&addr).await.expect("Failed to bind to address");
 
-    axum::serve(listener, app).await.expect("Failed to start server");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .expect("Failed to start server");
+
+    // Deliver payloads still in flight before the process exits
+    if let Err(e) = treblle.shutdown(Duration::from_secs(5)).await {
+        tracing::error!("Failed to flush Treblle payloads: {:?}", e);
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+
+    info!("Shutdown signal received, stopping server");
 }
 
 #[cfg(test)]
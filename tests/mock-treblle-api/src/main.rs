@@ -328,6 +328,7 @@ mod tests {
                     timestamp: chrono::Utc::now(),
                     ip: "127.0.0.1".to_string(),
                     url: "http://test.com".to_string(),
+                    route_path: None,
                     user_agent: "test-agent".to_string(),
                     method: "POST".to_string(),
                     headers: Default::default(),
@@ -472,6 +473,7 @@ mod util_tests {
                         timestamp: chrono::Utc::now(),
                         ip: "127.0.0.1".to_string(),
                         url: "http://test.com".to_string(),
+                        route_path: None,
                         user_agent: "test-agent".to_string(),
                         method: "POST".to_string(),
                         headers: Default::default(),
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
-use treblle_core::{Config as CoreConfig, Result};
+use std::path::Path;
+use std::time::Duration;
+use treblle_core::{CaptureRule, Config as CoreConfig, Result, RouteOverride, StatusCodes};
 
 /// Configuration for the Treblle Actix middleware
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -30,6 +32,25 @@ impl ActixConfig {
         &self.core
     }
 
+    /// Load the configuration from the `TREBLLE_*` environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is invalid or the API key is missing.
+    pub fn from_env() -> Result<Self> {
+        Self::builder().load_env()?.build()
+    }
+
+    /// Load the configuration from a TOML, YAML or JSON file, overridden by the `TREBLLE_*`
+    /// environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file or a variable is invalid, or the API key is missing.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::builder().load_file(path)?.load_env()?.build()
+    }
+
     /// Check if response buffering is enabled
     pub fn buffer_response(&self) -> bool {
         self.buffer_response
@@ -139,6 +160,159 @@ impl ActixConfigBuilder {
         Ok(self)
     }
 
+    /// Set HTTP methods of requests that aren't captured, e.g. `OPTIONS` (optional)
+    #[must_use]
+    pub fn ignored_methods<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        methods: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.ignored_methods(methods);
+        self
+    }
+
+    /// Set status codes of responses that aren't captured, e.g. `404` (optional)
+    #[must_use]
+    pub fn ignored_status_codes<T: Into<StatusCodes>, I: IntoIterator<Item = T>>(
+        mut self,
+        status_codes: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.ignored_status_codes(status_codes);
+        self
+    }
+
+    /// Capture requests slower than this regardless of status code rules (optional)
+    #[must_use]
+    pub fn always_capture_slower_than(mut self, latency: Duration) -> Self {
+        self.core_builder = self.core_builder.always_capture_slower_than(latency);
+        self
+    }
+
+    /// Add a rule restricting the status codes captured for some routes (optional)
+    #[must_use]
+    pub fn add_capture_rule(mut self, rule: CaptureRule) -> Self {
+        self.core_builder = self.core_builder.add_capture_rule(rule);
+        self
+    }
+
+    /// Add settings replacing the configuration for some routes (optional)
+    #[must_use]
+    pub fn add_route_override(mut self, route_override: RouteOverride) -> Self {
+        self.core_builder = self.core_builder.add_route_override(route_override);
+        self
+    }
+
+    /// Only capture these headers, matched case-insensitively (optional, defaults to all)
+    #[must_use]
+    pub fn allowed_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.allowed_headers(headers);
+        self
+    }
+
+    /// Set headers that aren't captured, matched case-insensitively (optional)
+    #[must_use]
+    pub fn ignored_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.ignored_headers(headers);
+        self
+    }
+
+    /// Add masked headers to the default authorization and cookie headers
+    #[must_use]
+    pub fn add_masked_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.add_masked_headers(headers);
+        self
+    }
+
+    /// Set masked headers, replacing the defaults
+    #[must_use]
+    pub fn set_masked_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.set_masked_headers(headers);
+        self
+    }
+
+    /// Only mask these cookies in `Cookie` and `Set-Cookie` headers (optional, defaults to all)
+    #[must_use]
+    pub fn masked_cookies<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        cookies: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.masked_cookies(cookies);
+        self
+    }
+
+    /// Set the largest request body captured in bytes, defaults to 10MB (optional)
+    #[must_use]
+    pub fn max_request_body_size(mut self, bytes: usize) -> Self {
+        self.core_builder = self.core_builder.max_request_body_size(bytes);
+        self
+    }
+
+    /// Set the largest response body captured in bytes, defaults to 10MB (optional)
+    #[must_use]
+    pub fn max_response_body_size(mut self, bytes: usize) -> Self {
+        self.core_builder = self.core_builder.max_response_body_size(bytes);
+        self
+    }
+
+    /// Turn Treblle on or off (optional, defaults to enabled)
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.core_builder = self.core_builder.enabled(enabled);
+        self
+    }
+
+    /// Only enable Treblle in these environments (optional, defaults to all environments)
+    #[must_use]
+    pub fn environments<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        environments: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.environments(environments);
+        self
+    }
+
+    /// Set the environment the application runs in (optional, defaults to `APP_ENV`)
+    #[must_use]
+    pub fn environment<T: Into<String>>(mut self, environment: T) -> Self {
+        self.core_builder = self.core_builder.environment(environment);
+        self
+    }
+
+    /// Load values from a TOML, YAML or JSON file, picked by its extension (optional).
+    ///
+    /// File values are used for anything neither the builder nor environment variables set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or parsed.
+    pub fn load_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        self.core_builder = self.core_builder.load_file(path)?;
+        Ok(self)
+    }
+
+    /// Load values from the `TREBLLE_*` environment variables (optional).
+    ///
+    /// Environment values are used for anything the builder doesn't set, and win over files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable has an invalid value.
+    pub fn load_env(mut self) -> Result<Self> {
+        self.core_builder = self.core_builder.load_env()?;
+        Ok(self)
+    }
+
     /// Build the configuration
     pub fn build(self) -> Result<ActixConfig> {
         Ok(ActixConfig { core: self.core_builder.build()?, buffer_response: self.buffer_response })
@@ -245,4 +419,19 @@ mod tests {
         assert!(ActixConfig::builder().build().is_err()); // Missing API key
         assert!(ActixConfig::builder().api_key("").build().is_err()); // Empty API key
     }
+
+    #[test]
+    fn test_builder_load_file() {
+        let path =
+            std::env::temp_dir().join(format!("treblle-actix-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "api_key = \"file_key\"\nproject_id = \"file_project\"\n").unwrap();
+
+        let config =
+            ActixConfig::builder().load_file(&path).unwrap().project_id("builder_project").build();
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.core.api_key, "file_key");
+        assert_eq!(config.core.project_id, "builder_project");
+    }
 }
@@ -3,7 +3,7 @@ use actix_http::body::{BodySize, MessageBody};
 use actix_http::header::HeaderMap as ActixHeaderMap;
 use actix_http::uri::PathAndQuery;
 use actix_web::{
-    dev::{ServiceRequest, ServiceResponse},
+    dev::{Extensions, ServiceRequest, ServiceResponse},
     web::Bytes,
     HttpMessage,
 };
@@ -16,6 +16,7 @@ use tracing::warn;
 use treblle_core::{
     extractors::TreblleExtractor,
     schema::{ErrorInfo, OsInfo, RequestInfo, ResponseInfo, ServerInfo},
+    CapturedBody,
 };
 
 pub struct ActixExtractor;
@@ -23,6 +24,12 @@ pub struct ActixExtractor;
 static SERVER_INFO: OnceLock<ServerInfo> = OnceLock::new();
 
 impl ActixExtractor {
+    fn captured_body(extensions: &Extensions) -> Option<CapturedBody> {
+        extensions.get::<CapturedBody>().cloned().or_else(|| {
+            extensions.get::<Bytes>().map(|b| CapturedBody::complete(b.clone(), b.len()))
+        })
+    }
+
     fn construct_full_url(req: &ServiceRequest) -> String {
         let connection_info = req.connection_info();
         let scheme = connection_info.scheme();
@@ -62,6 +69,7 @@ impl TreblleExtractor for ActixExtractor {
                 .or_else(|| Some(req.connection_info().realip_remote_addr()?.to_string()))
                 .unwrap_or_else(|| "unknown".to_string()),
             url: Self::construct_full_url(req),
+            route_path: req.match_pattern(),
             user_agent: req
                 .headers()
                 .get("User-Agent")
@@ -74,21 +82,16 @@ impl TreblleExtractor for ActixExtractor {
                 .iter()
                 .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
                 .collect(),
-            body: req.request().extensions().get::<Bytes>().and_then(|bytes| {
-                if bytes.is_empty() {
-                    None
-                } else {
-                    serde_json::from_slice(bytes).ok()
-                }
-            }),
+            body: Self::captured_body(&req.extensions()).and_then(|body| body.to_json()),
         }
     }
 
     fn extract_response_info(res: &Self::Response, duration: Duration) -> ResponseInfo {
-        let body_size = match res.response().body().size() {
-            BodySize::None => 0,
-            BodySize::Sized(size) => size,
-            Stream => 0, // Can't determine size of streaming body
+        let body = Self::captured_body(&res.response().extensions());
+        let body_size = match (&body, res.response().body().size()) {
+            (Some(body), _) => body.size().unwrap_or(body.bytes().len() as u64),
+            (None, BodySize::Sized(size)) => size,
+            (None, BodySize::None | Stream) => 0, // Can't determine size of streaming body
         };
 
         ResponseInfo {
@@ -100,32 +103,22 @@ impl TreblleExtractor for ActixExtractor {
             code: res.status().as_u16(),
             size: body_size,
             load_time: duration.as_secs_f64(),
-            body: res.request().extensions().get::<Bytes>().and_then(|bytes| {
-                if bytes.is_empty() {
-                    None
-                } else {
-                    serde_json::from_slice(bytes).ok()
-                }
-            }),
+            body: body.and_then(|body| body.to_json()),
         }
     }
 
     fn extract_error_info(res: &Self::Response) -> Option<Vec<ErrorInfo>> {
         if !res.status().is_success() {
-            res.request()
-                .extensions()
-                .get::<Bytes>()
-                .and_then(|bytes| {
-                    if bytes.is_empty() {
-                        None
-                    } else {
-                        serde_json::from_slice::<Value>(bytes)
-                            .map_err(|e| {
-                                warn!("Failed to parse error response body: {}", e);
-                                e
-                            })
-                            .ok()
-                    }
+            // Error messages are only taken from whole bodies
+            Self::captured_body(&res.response().extensions())
+                .filter(|body| !body.is_truncated() && !body.bytes().is_empty())
+                .and_then(|body| {
+                    serde_json::from_slice::<Value>(body.bytes())
+                        .map_err(|e| {
+                            warn!("Failed to parse error response body: {}", e);
+                            e
+                        })
+                        .ok()
                 })
                 .map(|value| {
                     let message = match &value {
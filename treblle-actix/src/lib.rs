@@ -9,10 +9,28 @@ use actix_web::{Error, FromRequest, HttpRequest};
 pub use config::ActixConfig;
 pub use middleware::TreblleMiddleware;
 use std::future::{ready, Ready};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::error;
+pub use treblle_core::{
+    CaptureRule, ConfigHandle, FileTransport, FileWatcher, MemoryTransport, RouteOverride, Spool,
+    SpoolingTransport, StatusCodes, StdoutTransport, Transport,
+};
+use treblle_core::{Dispatcher, TreblleClient};
+
+#[cfg(feature = "otlp")]
+pub use treblle_core::OtlpTransport;
 
 /// Treblle service for Actix
+///
+/// Clones share the same dispatcher, so the instance cloned into each worker's
+/// `App` factory can be shut down from the outside once the server stops.
+#[derive(Clone)]
 pub struct Treblle {
     pub config: ActixConfig,
+    transport: Option<Arc<dyn Transport>>,
+    dispatcher: Arc<OnceLock<Arc<Dispatcher>>>,
+    config_handle: Option<ConfigHandle<ActixConfig>>,
 }
 
 impl Treblle {
@@ -23,12 +41,94 @@ impl Treblle {
             .build()
             .expect("Failed to create Treblle configuration");
 
-        Treblle { config }
+        Self::from_config(config)
+    }
+
+    /// Create a new Treblle instance from configuration
+    pub fn from_config(config: ActixConfig) -> Self {
+        Treblle { config, transport: None, dispatcher: Arc::default(), config_handle: None }
+    }
+
+    /// Create a new Treblle instance reading its configuration from a handle on every request.
+    ///
+    /// Configurations stored in the handle apply from the next request on. The API key,
+    /// URLs and TLS settings of the Treblle HTTP client are taken from the configuration when
+    /// the first middleware is created and don't change afterwards.
+    pub fn from_config_handle(config_handle: ConfigHandle<ActixConfig>) -> Self {
+        let config = ActixConfig::clone(&config_handle.load());
+        Treblle {
+            config,
+            transport: None,
+            dispatcher: Arc::default(),
+            config_handle: Some(config_handle),
+        }
+    }
+
+    /// Send payloads through a custom transport instead of the Treblle API
+    #[must_use]
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self.dispatcher = Arc::default();
+        self
     }
 
     /// Create the Treblle middleware
+    ///
+    /// # Panics
+    ///
+    /// Panics if no custom transport is set and the Treblle HTTP client can't be created.
     pub fn middleware(self) -> TreblleMiddleware {
-        TreblleMiddleware::new(self.config)
+        let dispatcher = self.dispatcher();
+        let config_handle = self.config_handle.unwrap_or_else(|| self.config.into());
+        TreblleMiddleware::with_config_handle(config_handle, dispatcher)
+    }
+
+    /// Stop sending new payloads and wait for pending ones, up to the given timeout
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pending payloads couldn't be sent before the timeout.
+    pub async fn shutdown(&self, timeout: Duration) -> treblle_core::Result<()> {
+        match self.dispatcher.get() {
+            Some(dispatcher) => dispatcher.shutdown(timeout).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The dispatcher shared by all clones, created on first use. The Treblle HTTP client
+    /// is only built when no custom transport was set.
+    fn dispatcher(&self) -> Arc<Dispatcher> {
+        let dispatcher = self.dispatcher.get_or_init(|| {
+            let transport = self.transport.clone().unwrap_or_else(|| {
+                let config = match &self.config_handle {
+                    Some(config_handle) => config_handle.load().core.clone(),
+                    None => self.config.core.clone(),
+                };
+                Arc::new(TreblleClient::new(config).expect("Failed to create Treblle client"))
+            });
+            Arc::new(Dispatcher::new(transport))
+        });
+        Arc::clone(dispatcher)
+    }
+
+    /// Run an Actix server until it stops, e.g. on SIGTERM, then flush pending
+    /// payloads up to the given timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the server stopped with.
+    pub async fn run_until_stopped(
+        &self,
+        server: actix_web::dev::Server,
+        timeout: Duration,
+    ) -> std::io::Result<()> {
+        let result = server.await;
+
+        if let Err(e) = self.shutdown(timeout).await {
+            error!("Failed to flush pending Treblle payloads on shutdown: {:?}", e);
+        }
+
+        result
     }
 }
 
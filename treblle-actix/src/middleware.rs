@@ -1,33 +1,73 @@
 use crate::config::ActixConfig;
 use crate::extractors::ActixExtractor;
+use actix_http::{header::CONTENT_LENGTH, BoxedPayloadStream, Payload};
 use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    web::Bytes,
-    Error, HttpMessage,
+    http::Method,
+    Error, HttpMessage, HttpResponse,
 };
+use bytes::{Bytes, BytesMut};
 use futures_util::future::LocalBoxFuture;
+use futures_util::{stream, StreamExt};
 use std::{
+    error::Error as StdError,
     future::{ready, Ready},
+    pin::Pin,
+    rc::Rc,
     sync::Arc,
-    time::Instant,
+    task::{ready, Context as TaskContext, Poll},
+    time::{Duration, Instant},
 };
 use tracing::{debug, error};
-use treblle_core::{PayloadBuilder, TreblleClient};
+use treblle_core::{
+    metrics,
+    schema::{RequestInfo, TrebllePayload},
+    BodyTap, CaptureDecision, CapturedBody, ConfigHandle, Dispatcher, PayloadBuilder, RoutePolicy,
+    Transport, TreblleClient,
+};
 
 #[derive(Clone)]
 pub struct TreblleMiddleware {
-    config: Arc<ActixConfig>,
-    treblle_client: Arc<TreblleClient>,
+    config: ConfigHandle<ActixConfig>,
+    dispatcher: Arc<Dispatcher>,
 }
 
 impl TreblleMiddleware {
+    /// Create a new Treblle middleware sending payloads to the Treblle API
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Treblle HTTP client can't be created.
     pub fn new(config: ActixConfig) -> Self {
-        TreblleMiddleware {
-            treblle_client: Arc::new(
-                TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client"),
-            ),
-            config: Arc::new(config),
-        }
+        let transport =
+            TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client");
+        Self::with_transport(config, Arc::new(transport))
+    }
+
+    /// Create a new Treblle middleware sending payloads through a custom transport
+    pub fn with_transport(config: ActixConfig, transport: Arc<dyn Transport>) -> Self {
+        Self::with_dispatcher(config, Arc::new(Dispatcher::new(transport)))
+    }
+
+    /// Create a new Treblle middleware sharing an existing dispatcher
+    pub fn with_dispatcher(config: ActixConfig, dispatcher: Arc<Dispatcher>) -> Self {
+        Self::with_config_handle(config.into(), dispatcher)
+    }
+
+    /// Create a new Treblle middleware reading its configuration from a handle on every
+    /// request
+    pub fn with_config_handle(
+        config: ConfigHandle<ActixConfig>,
+        dispatcher: Arc<Dispatcher>,
+    ) -> Self {
+        config.load().core.log_diagnostics();
+        TreblleMiddleware { config, dispatcher }
+    }
+
+    /// Get the handle to replace the configuration at runtime
+    pub fn config_handle(&self) -> &ConfigHandle<ActixConfig> {
+        &self.config
     }
 }
 
@@ -43,17 +83,17 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(TreblleMiddlewareService {
-            service,
-            config: Arc::<ActixConfig>::clone(&self.config),
-            treblle_client: Arc::<TreblleClient>::clone(&self.treblle_client),
+            service: Rc::new(service),
+            config: self.config.clone(),
+            dispatcher: Arc::clone(&self.dispatcher),
         }))
     }
 }
 
 pub struct TreblleMiddlewareService<S> {
-    service: S,
-    config: Arc<ActixConfig>,
-    treblle_client: Arc<TreblleClient>,
+    service: Rc<S>,
+    config: ConfigHandle<ActixConfig>,
+    dispatcher: Arc<Dispatcher>,
 }
 
 impl<S> Service<ServiceRequest> for TreblleMiddlewareService<S>
@@ -66,60 +106,246 @@ where
 
     forward_ready!(service);
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        let config = Arc::<ActixConfig>::clone(&self.config);
-        let treblle_client = Arc::<TreblleClient>::clone(&self.treblle_client);
-        let start_time = Instant::now();
-
-        let should_process = !config.core.should_ignore_route(req.uri().path())
-            && req
-                .headers()
-                .get("Content-Type")
-                .and_then(|ct| ct.to_str().ok())
-                .map(|ct| ct.starts_with("application/json"))
-                .unwrap_or(false);
-
-        if should_process {
-            req.request().extensions_mut().insert(Bytes::new());
-
-            debug!("Processing request for Treblle: {}", req.uri().path());
-            let request_payload =
-                PayloadBuilder::build_request_payload::<ActixExtractor>(&req, &config.core);
-
-            let treblle_client_clone = Arc::<TreblleClient>::clone(&treblle_client);
-            actix_web::rt::spawn(async move {
-                if let Err(e) = treblle_client_clone.send_to_treblle(request_payload).await {
-                    error!("Failed to send request payload to Treblle: {:?}", e);
-                }
-            });
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let config = self.config.load();
+        if !config.core.is_enabled() {
+            return Box::pin(self.service.call(req));
         }
 
-        let fut = self.service.call(req);
-        let config = Arc::<ActixConfig>::clone(&self.config);
-        let treblle_client = Arc::<TreblleClient>::clone(&self.treblle_client);
+        let service = Rc::clone(&self.service);
+        let dispatcher = Arc::clone(&self.dispatcher);
 
         Box::pin(async move {
-            let res = fut.await?;
+            let start_time = Instant::now();
 
-            if should_process {
-                let duration = start_time.elapsed();
-                res.request().extensions_mut().insert(Bytes::new());
+            let route_path = req.match_pattern();
+            let policy = config.core.policy_for(req.path(), route_path.as_deref());
+            let decision =
+                config.core.capture.capture_request(req.method().as_str(), req.uri().path());
+            let mut should_process = decision != CaptureDecision::Skip
+                && !config.core.should_ignore_request(req.path(), route_path.as_deref())
+                && req
+                    .headers()
+                    .get("Content-Type")
+                    .and_then(|ct| ct.to_str().ok())
+                    .map(|ct| ct.starts_with("application/json"))
+                    .unwrap_or(false);
+
+            if !should_process {
+                metrics::record_ignored();
+            } else if !policy.is_sampled() {
+                debug!("Request to {} sampled out", req.path());
+                metrics::record_sampled_out();
+                should_process = false;
+            }
+
+            // Capture the request body for Treblle, unless the route leaves it out
+            if should_process && policy.capture_request_body() {
+                let size = req
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok()?.parse().ok());
+                let limit = policy.max_request_body_size(&config.core);
+                let (captured, payload) = capture_payload(req.take_payload(), limit, size).await;
 
-                debug!("Processing response for Treblle: {}", res.status());
-                let response_payload = PayloadBuilder::build_response_payload::<ActixExtractor>(
-                    &res,
+                req.set_payload(payload);
+                req.extensions_mut().insert(captured);
+            }
+
+            // The request payload is held back and sent together with the response, as one
+            // payload
+            let mut held = None;
+            if should_process {
+                debug!("Processing request for Treblle: {}", req.uri().path());
+                let payload = PayloadBuilder::build_request_payload::<ActixExtractor>(
+                    &req,
                     &config.core,
-                    duration,
+                    &policy,
                 );
-
-                actix_web::rt::spawn(async move {
-                    if let Err(e) = treblle_client.send_to_treblle(response_payload).await {
-                        error!("Failed to send response payload to Treblle: {:?}", e);
-                    }
+                held = Some(HeldRequest {
+                    request: payload.data.request,
+                    method: req.method().clone(),
+                    path: req.path().to_string(),
+                    route_path: route_path.clone(),
+                    deferred: decision == CaptureDecision::AfterResponse,
                 });
             }
 
-            Ok(res)
+            let res = service.call(req).await?;
+
+            let Some(held) = held else {
+                return Ok(res);
+            };
+            let duration = start_time.elapsed();
+
+            // Requests whose capture depends on the response are dropped with it
+            if held.deferred {
+                let status = res.status().as_u16();
+                let capture = &config.core.capture;
+                if !capture.should_capture_response(
+                    held.method.as_str(),
+                    &held.path,
+                    status,
+                    duration,
+                ) {
+                    debug!("Ignoring status code {} for {}", status, held.path);
+                    metrics::record_ignored();
+                    return Ok(res);
+                }
+            }
+
+            if !policy.capture_response_body() {
+                send_exchange(&dispatcher, &config, policy, &res, held.request, duration);
+                return Ok(res);
+            }
+
+            // The response body streams through to the client, the payload is sent once it
+            // went by
+            let limit = policy.max_response_body_size(&config.core);
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let size = match body.size() {
+                BodySize::Sized(size) => Some(size),
+                BodySize::None | BodySize::Stream => None,
+            };
+
+            let (head_req, status, headers) = (req.clone(), res.status(), res.headers().clone());
+            let tap = BodyTap::new(limit, size, move |captured| {
+                let mut res_head = HttpResponse::new(status);
+                *res_head.headers_mut() = headers;
+                res_head.extensions_mut().insert(captured);
+                let res_head = ServiceResponse::new(head_req, res_head);
+
+                let policy = config.core.policy_for(&held.path, held.route_path.as_deref());
+                send_exchange(&dispatcher, &config, policy, &res_head, held.request, duration);
+            });
+
+            let res = res.set_body(BoxBody::new(TapBody { inner: body, tap }));
+            Ok(ServiceResponse::new(req, res))
         })
     }
 }
+
+/// A request held back to be sent with its response
+struct HeldRequest {
+    request: RequestInfo,
+    method: Method,
+    path: String,
+    route_path: Option<String>,
+    /// Whether the capture depends on the response
+    deferred: bool,
+}
+
+/// Build the payload of a response with the request held back for it, and dispatch it
+fn send_exchange(
+    dispatcher: &Dispatcher,
+    config: &ActixConfig,
+    policy: RoutePolicy<'_>,
+    res: &ServiceResponse,
+    request: RequestInfo,
+    duration: Duration,
+) {
+    debug!("Processing response for Treblle: {}", res.status());
+    let mut payload = PayloadBuilder::build_response_payload::<ActixExtractor>(
+        res,
+        &config.core,
+        &policy,
+        duration,
+    );
+    payload.data.request = request;
+    send_payload(dispatcher, payload);
+}
+
+/// Read a request payload for Treblle up to `limit` bytes.
+///
+/// Returns the captured bytes, and a payload replaying what was read before the rest of the
+/// original payload, so the application still receives all of it.
+async fn capture_payload(
+    mut payload: Payload,
+    limit: usize,
+    size: Option<u64>,
+) -> (CapturedBody, Payload) {
+    let mut read = Vec::new();
+    let mut captured = BytesMut::new();
+
+    let complete = loop {
+        if captured.len() > limit {
+            break false;
+        }
+        match payload.next().await {
+            Some(Ok(chunk)) => {
+                let take = chunk.len().min(limit + 1 - captured.len());
+                captured.extend_from_slice(&chunk[..take]);
+                read.push(Ok(chunk));
+            }
+            Some(Err(e)) => {
+                read.push(Err(e));
+                break false;
+            }
+            None => break true,
+        }
+    };
+
+    let captured = finish_capture(captured, limit, complete, size);
+    let replay: BoxedPayloadStream = Box::pin(stream::iter(read).chain(payload));
+    (captured, Payload::from(replay))
+}
+
+fn finish_capture(
+    captured: BytesMut,
+    limit: usize,
+    complete: bool,
+    size: Option<u64>,
+) -> CapturedBody {
+    let mut captured = captured.freeze();
+    if complete {
+        CapturedBody::complete(captured, limit)
+    } else {
+        captured.truncate(limit);
+        debug!("Captured {} bytes of a body over the {} byte limit", captured.len(), limit);
+        CapturedBody::partial(captured, size)
+    }
+}
+
+/// A body passing the chunks of a response through, copying its start for the payload
+struct TapBody<F: FnOnce(CapturedBody)> {
+    inner: BoxBody,
+    tap: BodyTap<F>,
+}
+
+impl<F> MessageBody for TapBody<F>
+where
+    F: FnOnce(CapturedBody) + Unpin,
+{
+    type Error = Box<dyn StdError>;
+
+    fn size(&self) -> BodySize {
+        self.inner.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = &mut *self;
+        let chunk = ready!(Pin::new(&mut this.inner).poll_next(cx));
+        match &chunk {
+            Some(Ok(chunk)) => this.tap.copy(chunk),
+            Some(Err(_)) => this.tap.finish(false),
+            None => this.tap.finish(true),
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+/// Dispatch a payload, logging failures to send it in the background
+fn send_payload(dispatcher: &Dispatcher, payload: TrebllePayload) {
+    let transport = dispatcher.transport().name();
+    if let Some(send) = dispatcher.dispatch(payload) {
+        actix_web::rt::spawn(async move {
+            if let Err(e) = send.await {
+                error!("Failed to send payload via {} transport: {:?}", transport, e);
+            }
+        });
+    }
+}
@@ -1,4 +1,4 @@
-use actix_http::{HttpMessage, StatusCode};
+use actix_http::StatusCode;
 use actix_web::dev::ServiceResponse;
 use actix_web::{test, HttpResponse};
 use bytes::Bytes;
@@ -14,12 +14,11 @@ async fn test_extract_error_info() {
     });
 
     let req = test::TestRequest::default().to_http_request();
-    req.extensions_mut().insert(Bytes::from(error_body.to_string()));
+    let mut res =
+        HttpResponse::NotFound().content_type("application/json").body(error_body.to_string());
+    res.extensions_mut().insert(Bytes::from(error_body.to_string()));
 
-    let resp = ServiceResponse::new(
-        req,
-        HttpResponse::NotFound().content_type("application/json").body(error_body.to_string()),
-    );
+    let resp = ServiceResponse::new(req, res);
 
     let errors = ActixExtractor::extract_error_info(&resp).unwrap();
     assert_eq!(errors.len(), 1);
@@ -55,14 +54,12 @@ async fn test_error_message_handling() {
 
     for (status, error_body, expected_message) in test_cases {
         let req = test::TestRequest::default().to_http_request();
-        req.extensions_mut().insert(Bytes::from(error_body.to_string()));
+        let mut res = HttpResponse::build(status)
+            .content_type("application/json")
+            .body(error_body.to_string());
+        res.extensions_mut().insert(Bytes::from(error_body.to_string()));
 
-        let resp = ServiceResponse::new(
-            req,
-            HttpResponse::build(status)
-                .content_type("application/json")
-                .body(error_body.to_string()),
-        );
+        let resp = ServiceResponse::new(req, res);
 
         let errors = ActixExtractor::extract_error_info(&resp).unwrap();
         assert_eq!(errors[0].source, "actix");
@@ -4,7 +4,7 @@ use bytes::Bytes;
 use serde_json::{json, Value};
 use treblle_actix::extractors::ActixExtractor;
 use treblle_actix::{ActixConfig, TreblleMiddleware};
-use treblle_core::PayloadBuilder;
+use treblle_core::{PayloadBuilder, RoutePolicy};
 
 fn find_field_value<'a>(json: &'a Value, field: &str) -> Option<&'a str> {
     match json {
@@ -107,8 +107,11 @@ async fn test_data_masking_patterns() {
         // Add the body to the request extensions for the middleware to process
         req.extensions_mut().insert(Bytes::from(payload_bytes.clone()));
 
-        let treblle_payload =
-            PayloadBuilder::build_request_payload::<ActixExtractor>(&req, &config.core);
+        let treblle_payload = PayloadBuilder::build_request_payload::<ActixExtractor>(
+            &req,
+            &config.core,
+            &RoutePolicy::default(),
+        );
 
         // Verify masking in Treblle payload
         if let Some(body) = treblle_payload.data.request.body {
@@ -189,8 +192,11 @@ async fn test_partial_field_masking() {
     // Add body to request extensions for middleware processing
     req.extensions_mut().insert(Bytes::from(payload_bytes.clone()));
 
-    let treblle_payload =
-        PayloadBuilder::build_request_payload::<ActixExtractor>(&req, &config.core);
+    let treblle_payload = PayloadBuilder::build_request_payload::<ActixExtractor>(
+        &req,
+        &config.core,
+        &RoutePolicy::default(),
+    );
 
     // Verify masking in Treblle payload
     if let Some(body) = treblle_payload.data.request.body {
@@ -351,11 +357,390 @@ async fn test_treblle_payload_creation() {
     let req = test::TestRequest::default().to_srv_request();
     req.extensions_mut().insert(Bytes::from(test_data.to_string()));
 
-    let treblle_payload =
-        PayloadBuilder::build_request_payload::<ActixExtractor>(&req, &config.core);
+    let treblle_payload = PayloadBuilder::build_request_payload::<ActixExtractor>(
+        &req,
+        &config.core,
+        &RoutePolicy::default(),
+    );
 
     if let Some(body) = &treblle_payload.data.request.body {
         assert_eq!(body["username"], "test_user");
         assert_eq!(body["password"], "*****");
     }
 }
+
+#[actix_web::test]
+async fn test_middleware_sends_payloads_through_custom_transport() {
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let mut treblle = Treblle::new("test_key").with_transport(transport.clone());
+    treblle.config.core.project_id = "test_project".to_string();
+
+    let app = test::init_service(
+        App::new().wrap(treblle.middleware()).route("/echo", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/echo")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(json!({"password": "secret123"}).to_string())
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    test::read_body(resp).await;
+
+    // Payloads are sent from spawned tasks
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    // The request and its response are sent as one payload
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0].project_id, "test_project");
+    assert_eq!(payloads[0].data.request.method, "POST");
+    assert_eq!(payloads[0].data.response.code, 200);
+}
+
+#[actix_web::test]
+async fn test_shutdown_flushes_pending_payloads() {
+    use std::time::Duration;
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let treblle = Treblle::new("test_key").with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new().wrap(treblle.clone().middleware()).route("/echo", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let request = || {
+        test::TestRequest::post()
+            .uri("/echo")
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(json!({"username": "test_user"}).to_string())
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request()).await;
+    assert!(resp.status().is_success());
+    test::read_body(resp).await;
+
+    treblle.shutdown(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(transport.len(), 1);
+
+    let resp = test::call_service(&app, request()).await;
+    assert!(resp.status().is_success());
+    test::read_body(resp).await;
+    treblle.shutdown(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(transport.len(), 1);
+}
+
+#[actix_web::test]
+async fn test_config_handle_applies_replaced_config() {
+    use treblle_actix::{ConfigHandle, MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let handle = ConfigHandle::new(ActixConfig::builder().api_key("test_key").build().unwrap());
+    let treblle = Treblle::from_config_handle(handle.clone()).with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new().wrap(treblle.middleware()).route("/echo", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let request = || {
+        test::TestRequest::post()
+            .uri("/echo")
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(json!({"username": "test_user"}).to_string())
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request()).await;
+    assert!(resp.status().is_success());
+    test::read_body(resp).await;
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(transport.take().len(), 1);
+
+    // Ignoring the route applies to the next request without restarting the server
+    let mut config = ActixConfig::clone(&handle.load());
+    config.core.ignored_routes.insert("/echo".to_string());
+    handle.store(config);
+
+    let resp = test::call_service(&app, request()).await;
+    assert!(resp.status().is_success());
+    test::read_body(resp).await;
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+}
+
+#[actix_web::test]
+async fn test_disabled_middleware_passes_requests_through() {
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let config = ActixConfig::builder().enabled(false).build().unwrap();
+    let treblle = Treblle::from_config(config).with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new().wrap(treblle.middleware()).route("/echo", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/echo")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(json!({"username": "test_user"}).to_string())
+        .to_request();
+
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["username"], "test_user");
+
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+}
+
+#[actix_web::test]
+async fn test_capture_filters_skip_ignored_methods_and_status_codes() {
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let config = ActixConfig::builder()
+        .api_key("test_key")
+        .ignored_methods(["PUT"])
+        .ignored_status_codes(["4xx".parse::<treblle_actix::StatusCodes>().unwrap()])
+        .build()
+        .unwrap();
+    let treblle = Treblle::from_config(config).with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new()
+            .wrap(treblle.middleware())
+            .route("/echo", web::post().to(echo_handler))
+            .route("/echo", web::put().to(echo_handler))
+            .route("/missing", web::post().to(HttpResponse::NotFound)),
+    )
+    .await;
+
+    let request = |req: test::TestRequest, uri: &str| {
+        req.uri(uri)
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(json!({"username": "test_user"}).to_string())
+            .to_request()
+    };
+
+    let res = test::call_service(&app, request(test::TestRequest::put(), "/echo")).await;
+    assert!(res.status().is_success());
+    test::read_body(res).await;
+    let res = test::call_service(&app, request(test::TestRequest::post(), "/missing")).await;
+    assert_eq!(res.status(), 404);
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    // Held back requests are sent once their response is known to be captured
+    let res = test::call_service(&app, request(test::TestRequest::post(), "/echo")).await;
+    assert!(res.status().is_success());
+    test::read_body(res).await;
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads.iter().any(|p| p.data.response.code == 200));
+}
+
+#[actix_web::test]
+async fn test_route_template_is_reported_and_ignored() {
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let config = ActixConfig::builder()
+        .api_key("test_key")
+        .add_ignored_routes(["/internal/{job}"])
+        .build()
+        .unwrap();
+    let treblle = Treblle::from_config(config).with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new()
+            .wrap(treblle.middleware())
+            .route("/users/{id}/orders/{order}", web::post().to(echo_handler))
+            .route("/internal/{job}", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let request = |uri: &str| {
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(json!({"username": "test_user"}).to_string())
+            .to_request()
+    };
+
+    test::call_service(&app, request("/internal/reindex")).await;
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    test::call_service(&app, request("/users/8271/orders/99")).await;
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    let payloads = transport.payloads();
+    let request_payload = payloads.iter().find(|p| !p.data.request.method.is_empty()).unwrap();
+    assert_eq!(
+        request_payload.data.request.route_path.as_deref(),
+        Some("/users/{id}/orders/{order}")
+    );
+}
+
+#[actix_web::test]
+async fn test_route_overrides_apply_to_matching_requests() {
+    use treblle_actix::{MemoryTransport, RouteOverride, Treblle};
+
+    let transport = MemoryTransport::new();
+    let config = ActixConfig::builder()
+        .api_key("test_key")
+        .add_route_override(
+            RouteOverride::new("^/payments/\\{id\\}$").unwrap().capture_response_body(false),
+        )
+        .add_route_override(RouteOverride::new("^/search$").unwrap().sample_rate(0.0))
+        .build()
+        .unwrap();
+    let treblle = Treblle::from_config(config).with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new()
+            .wrap(treblle.middleware())
+            .route("/payments/{id}", web::post().to(echo_handler))
+            .route("/search", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let request = |uri: &str| {
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(json!({"username": "test_user"}).to_string())
+            .to_request()
+    };
+
+    let res = test::call_service(&app, request("/search")).await;
+    assert!(res.status().is_success());
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    // Overrides match the route template too
+    let res = test::call_service(&app, request("/payments/1")).await;
+    assert!(res.status().is_success());
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads.iter().all(|p| p.data.response.body.is_none()));
+}
+
+#[actix_web::test]
+async fn test_oversized_bodies_are_truncated_but_passed_through() {
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let transport = MemoryTransport::new();
+    let config = ActixConfig::builder()
+        .api_key("test_key")
+        .max_request_body_size(64)
+        .max_response_body_size(32)
+        .build()
+        .unwrap();
+    let treblle = Treblle::from_config(config).with_transport(transport.clone());
+
+    let app = test::init_service(
+        App::new().wrap(treblle.middleware()).route("/echo", web::post().to(echo_handler)),
+    )
+    .await;
+
+    let items: Vec<u32> = (0..50).collect();
+    let body = format!(r#"{{"password":"secret","items":{}}}"#, json!(items));
+    let req = test::TestRequest::post()
+        .uri("/echo")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(body.clone())
+        .to_request();
+
+    // The handler and the client still get the whole body
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_success());
+    let echoed = test::read_body(res).await;
+    assert_eq!(serde_json::from_slice::<Value>(&echoed).unwrap()["items"], json!(items));
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    let request_payload = payloads.iter().find(|p| p.data.request.body.is_some()).unwrap();
+    let captured = request_payload.data.request.body.as_ref().unwrap();
+    assert_eq!(captured["truncated"], true);
+    assert_eq!(captured["original_size"], body.len());
+    assert_eq!(captured["body"]["password"], "*****");
+    assert!(captured["body"]["items"].as_array().unwrap().len() < items.len());
+
+    let response_payload = payloads.iter().find(|p| p.data.response.body.is_some()).unwrap();
+    assert_eq!(response_payload.data.response.body.as_ref().unwrap()["truncated"], true);
+    assert_eq!(response_payload.data.response.size, echoed.len() as u64);
+}
+
+#[actix_web::test]
+async fn test_streamed_responses_reach_the_client_before_they_end() {
+    use actix_web::body::MessageBody;
+    use actix_web::rt::time::timeout;
+    use futures_util::stream;
+    use std::future::poll_fn;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use treblle_actix::{MemoryTransport, Treblle};
+
+    let (tx, rx) = mpsc::channel::<Bytes>(1);
+    let rx = web::Data::new(Mutex::new(Some(rx)));
+    let transport = MemoryTransport::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(rx)
+            .wrap(Treblle::new("test_key").with_transport(transport.clone()).middleware())
+            .route(
+                "/stream",
+                web::post().to(|rx: web::Data<Mutex<Option<mpsc::Receiver<Bytes>>>>| async move {
+                    let rx = rx.lock().unwrap().take().unwrap();
+                    let chunks = stream::unfold(rx, |mut rx| async move {
+                        let chunk = rx.recv().await?;
+                        Some((Ok::<_, actix_web::Error>(chunk), rx))
+                    });
+                    HttpResponse::Ok().content_type("application/json").streaming(chunks)
+                }),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/stream")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(json!({"username": "test_user"}).to_string())
+        .to_request();
+    let res = timeout(Duration::from_secs(1), test::call_service(&app, req)).await.unwrap();
+    let mut body = res.into_body();
+
+    // The first chunk reaches the client while the stream is still open
+    tx.send(Bytes::from_static(br#"{"items":[1,"#)).await.unwrap();
+    let next = poll_fn(|cx| std::pin::Pin::new(&mut body).poll_next(cx));
+    let chunk = timeout(Duration::from_secs(1), next).await.unwrap().unwrap().unwrap();
+    assert_eq!(chunk, br#"{"items":[1,"#.as_slice());
+    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+    assert!(transport.is_empty());
+
+    // The payload is sent once the stream ended
+    tx.send(Bytes::from_static(b"2]}")).await.unwrap();
+    drop(tx);
+    let rest = actix_web::body::to_bytes(body).await.unwrap();
+    assert_eq!(rest, b"2]}".as_slice());
+    actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0].data.response.body, Some(json!({"items": [1, 2]})));
+    assert_eq!(payloads[0].data.request.body, Some(json!({"username": "test_user"})));
+}
@@ -1,4 +1,4 @@
-use actix_http::{header, HttpMessage};
+use actix_http::header;
 use actix_web::dev::ServiceResponse;
 use actix_web::{test, HttpResponse};
 use bytes::Bytes;
@@ -20,12 +20,10 @@ async fn test_empty_body_handling() {
 #[actix_web::test]
 async fn test_invalid_json_body() {
     let req = test::TestRequest::default().to_http_request();
-    req.extensions_mut().insert(Bytes::from("invalid json"));
+    let mut res = HttpResponse::BadRequest().content_type("application/json").body("invalid json");
+    res.extensions_mut().insert(Bytes::from("invalid json"));
 
-    let resp = ServiceResponse::new(
-        req,
-        HttpResponse::BadRequest().content_type("application/json").body("invalid json"),
-    );
+    let resp = ServiceResponse::new(req, res);
 
     let info = ActixExtractor::extract_response_info(&resp, Duration::from_secs(1));
     assert!(info.body.is_none());
@@ -35,12 +33,10 @@ async fn test_invalid_json_body() {
 async fn test_extract_response_info() {
     let json_body = json!({"result": "success"});
     let req = test::TestRequest::default().to_http_request();
-    req.extensions_mut().insert(Bytes::from(json_body.to_string()));
+    let mut res = HttpResponse::Ok().content_type("application/json").body(json_body.to_string());
+    res.extensions_mut().insert(Bytes::from(json_body.to_string()));
 
-    let resp = ServiceResponse::new(
-        req,
-        HttpResponse::Ok().content_type("application/json").body(json_body.to_string()),
-    );
+    let resp = ServiceResponse::new(req, res);
 
     let info = ActixExtractor::extract_response_info(&resp, Duration::from_secs(1));
 
@@ -1,5 +1,6 @@
 use axum::body::Body;
-use axum::http::{Request, Response};
+use axum::extract::MatchedPath;
+use axum::http::{Extensions, Request, Response};
 use http::uri::PathAndQuery;
 use hyper::body::Bytes;
 use serde_json::Value;
@@ -9,6 +10,7 @@ use treblle_core::{
     extractors::TreblleExtractor,
     schema::{ErrorInfo, OsInfo, RequestInfo, ResponseInfo, ServerInfo},
     utils::extract_ip_from_headers,
+    CapturedBody,
 };
 
 pub struct AxumExtractor;
@@ -23,6 +25,13 @@ impl AxumExtractor {
 
         format!("{scheme}://{host}{path_and_query}")
     }
+
+    /// The body captured by the middleware, or stored as plain `Bytes` in the extensions
+    fn captured_body(extensions: &Extensions) -> Option<CapturedBody> {
+        extensions.get::<CapturedBody>().cloned().or_else(|| {
+            extensions.get::<Bytes>().map(|b| CapturedBody::complete(b.clone(), b.len()))
+        })
+    }
 }
 
 impl TreblleExtractor for AxumExtractor {
@@ -34,6 +43,7 @@ impl TreblleExtractor for AxumExtractor {
             timestamp: chrono::Utc::now(),
             ip: extract_ip_from_headers(req.headers()).unwrap_or_else(|| "unknown".to_string()),
             url: Self::construct_full_url(req),
+            route_path: req.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string()),
             user_agent: req
                 .headers()
                 .get("User-Agent")
@@ -46,15 +56,13 @@ impl TreblleExtractor for AxumExtractor {
                 .iter()
                 .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
                 .collect(),
-            body: req
-                .extensions()
-                .get::<Bytes>()
-                .and_then(|bytes| String::from_utf8_lossy(bytes).as_ref().parse().ok()),
+            body: Self::captured_body(req.extensions()).and_then(|body| body.to_json()),
         }
     }
 
     fn extract_response_info(res: &Self::Response, duration: Duration) -> ResponseInfo {
-        let body_size = res.extensions().get::<Bytes>().map(|b| b.len() as u64).unwrap_or(0);
+        let body = Self::captured_body(res.extensions());
+        let body_size = body.as_ref().map_or(0, |b| b.size().unwrap_or(b.bytes().len() as u64));
 
         ResponseInfo {
             headers: res
@@ -65,21 +73,16 @@ impl TreblleExtractor for AxumExtractor {
             code: res.status().as_u16(),
             size: body_size,
             load_time: duration.as_secs_f64(),
-            body: res
-                .extensions()
-                .get::<Bytes>()
-                .and_then(|bytes| String::from_utf8_lossy(bytes).as_ref().parse().ok()),
+            body: body.and_then(|body| body.to_json()),
         }
     }
 
     fn extract_error_info(res: &Self::Response) -> Option<Vec<ErrorInfo>> {
         if !res.status().is_success() {
-            res.extensions()
-                .get::<Bytes>()
-                .and_then(|bytes| {
-                    let body_str = String::from_utf8_lossy(bytes);
-                    serde_json::from_str::<Value>(&body_str).ok()
-                })
+            // Error messages are only taken from whole bodies
+            Self::captured_body(res.extensions())
+                .filter(|body| !body.is_truncated())
+                .and_then(|body| body.to_json())
                 .map(|value| {
                     let message = match &value {
                         Value::Object(map) => map
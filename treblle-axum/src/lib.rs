@@ -5,15 +5,30 @@ pub mod extractors;
 mod middleware;
 
 use axum::{middleware::from_fn_with_state, Router};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use treblle_core::{Dispatcher, TreblleClient};
 
 pub use config::AxumConfig;
 pub use middleware::{treblle_middleware, TreblleLayer};
+pub use treblle_core::{
+    CaptureRule, ConfigHandle, FileTransport, FileWatcher, MemoryTransport, RouteOverride, Spool,
+    SpoolingTransport, StatusCodes, StdoutTransport, Transport,
+};
+
+#[cfg(feature = "otlp")]
+pub use treblle_core::OtlpTransport;
 
 /// Treblle service for Axum
+///
+/// Clones share the same dispatcher, so a clone kept outside the router can be
+/// used to [`shutdown`](Treblle::shutdown) once the server has stopped.
 #[derive(Clone)]
 pub struct Treblle {
     pub config: Arc<AxumConfig>,
+    transport: Option<Arc<dyn Transport>>,
+    dispatcher: Arc<OnceLock<Arc<Dispatcher>>>,
+    config_handle: Option<ConfigHandle<AxumConfig>>,
 }
 
 impl Treblle {
@@ -24,17 +39,82 @@ impl Treblle {
             .build()
             .expect("Failed to create Treblle configuration");
 
-        Treblle { config: Arc::new(config) }
+        Self::from_config(config)
     }
 
     /// Create a new Treblle instance from configuration
     pub fn from_config(config: AxumConfig) -> Self {
-        Treblle { config: Arc::new(config) }
+        Treblle {
+            config: Arc::new(config),
+            transport: None,
+            dispatcher: Arc::default(),
+            config_handle: None,
+        }
+    }
+
+    /// Create a new Treblle instance reading its configuration from a handle on every request.
+    ///
+    /// Configurations stored in the handle apply from the next request on. The API key,
+    /// URLs and TLS settings of the Treblle HTTP client are taken from the configuration when
+    /// the first middleware is created and don't change afterwards.
+    pub fn from_config_handle(config_handle: ConfigHandle<AxumConfig>) -> Self {
+        let config = config_handle.load();
+        Treblle {
+            config,
+            transport: None,
+            dispatcher: Arc::default(),
+            config_handle: Some(config_handle),
+        }
+    }
+
+    /// Send payloads through a custom transport instead of the Treblle API
+    #[must_use]
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self.dispatcher = Arc::default();
+        self
     }
 
     /// Create the Treblle middleware layer
+    ///
+    /// # Panics
+    ///
+    /// Panics if no custom transport is set and the Treblle HTTP client can't be created.
     pub fn layer(self) -> TreblleLayer {
-        TreblleLayer::new(self.config)
+        let dispatcher = self.dispatcher();
+        let config_handle = self.config_handle.unwrap_or_else(|| self.config.into());
+        TreblleLayer::with_config_handle(config_handle, dispatcher)
+    }
+
+    /// Stop sending new payloads and wait for pending ones, up to the given timeout.
+    ///
+    /// Call this after the server has shut down, e.g. once
+    /// `axum::serve(..).with_graceful_shutdown(..)` has returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pending payloads couldn't be sent before the timeout.
+    pub async fn shutdown(&self, timeout: Duration) -> treblle_core::Result<()> {
+        match self.dispatcher.get() {
+            Some(dispatcher) => dispatcher.shutdown(timeout).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The dispatcher shared by all clones, created on first use. The Treblle HTTP client
+    /// is only built when no custom transport was set.
+    fn dispatcher(&self) -> Arc<Dispatcher> {
+        let dispatcher = self.dispatcher.get_or_init(|| {
+            let transport = self.transport.clone().unwrap_or_else(|| {
+                let config = match &self.config_handle {
+                    Some(config_handle) => config_handle.load().core.clone(),
+                    None => self.config.core.clone(),
+                };
+                Arc::new(TreblleClient::new(config).expect("Failed to create Treblle client"))
+            });
+            Arc::new(Dispatcher::new(transport))
+        });
+        Arc::clone(dispatcher)
     }
 }
 
@@ -48,8 +128,7 @@ where
     S: Clone + Send + Sync + 'static,
 {
     fn treblle(self, treblle: Treblle) -> Self {
-        let layer = TreblleLayer::new(treblle.config);
-        let layer = Arc::new(layer);
+        let layer = Arc::new(treblle.layer());
         self.layer(from_fn_with_state(layer, treblle_middleware))
     }
 }
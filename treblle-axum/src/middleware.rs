@@ -2,38 +2,85 @@ use crate::config::AxumConfig;
 use crate::extractors::AxumExtractor;
 use axum::{
     body::Body,
-    extract::State,
-    http::{Request, Response},
+    extract::{MatchedPath, State},
+    http::{Method, Request, Response},
     middleware::Next,
 };
+use bytes::{Bytes, BytesMut};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use http_body_util::BodyExt;
+use std::collections::VecDeque;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{ready, Context as TaskContext, Poll};
+use std::time::Duration;
 use std::time::Instant;
 use tracing::{debug, error};
-use treblle_core::constants::MAX_BODY_SIZE;
-use treblle_core::{payload::PayloadBuilder, TreblleClient};
+use treblle_core::{
+    metrics,
+    payload::PayloadBuilder,
+    schema::{RequestInfo, TrebllePayload},
+    BodyTap, CaptureDecision, CapturedBody, ConfigHandle, Dispatcher, RoutePolicy, Transport,
+    TreblleClient,
+};
 
 /// Treblle middleware layer for Axum
 #[derive(Clone)]
 pub struct TreblleLayer {
-    config: Arc<AxumConfig>,
-    treblle_client: Arc<TreblleClient>,
+    config: ConfigHandle<AxumConfig>,
+    dispatcher: Arc<Dispatcher>,
 }
 
 impl TreblleLayer {
-    /// Create a new Treblle middleware layer
+    /// Create a new Treblle middleware layer sending payloads to the Treblle API
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Treblle HTTP client can't be created.
     pub fn new(config: Arc<AxumConfig>) -> Self {
-        TreblleLayer {
-            treblle_client: Arc::new(
-                TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client"),
-            ),
-            config,
-        }
+        let transport =
+            TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client");
+        Self::with_transport(config, Arc::new(transport))
     }
 
-    /// Get a reference to the configuration
-    pub fn config(&self) -> &AxumConfig {
+    /// Create a new Treblle middleware layer sending payloads through a custom transport
+    pub fn with_transport(config: Arc<AxumConfig>, transport: Arc<dyn Transport>) -> Self {
+        Self::with_dispatcher(config, Arc::new(Dispatcher::new(transport)))
+    }
+
+    /// Create a new Treblle middleware layer sharing an existing dispatcher
+    pub fn with_dispatcher(config: Arc<AxumConfig>, dispatcher: Arc<Dispatcher>) -> Self {
+        Self::with_config_handle(config.into(), dispatcher)
+    }
+
+    /// Create a new Treblle middleware layer reading its configuration from a handle on
+    /// every request
+    pub fn with_config_handle(
+        config: ConfigHandle<AxumConfig>,
+        dispatcher: Arc<Dispatcher>,
+    ) -> Self {
+        config.load().core.log_diagnostics();
+        TreblleLayer { config, dispatcher }
+    }
+
+    /// Get the current configuration
+    pub fn config(&self) -> Arc<AxumConfig> {
+        self.config.load()
+    }
+
+    /// Get the handle to replace the configuration at runtime
+    pub fn config_handle(&self) -> &ConfigHandle<AxumConfig> {
         &self.config
     }
+
+    /// Stop sending new payloads and wait for pending ones, up to the given timeout
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pending payloads couldn't be sent before the timeout.
+    pub async fn shutdown(&self, timeout: Duration) -> treblle_core::Result<()> {
+        self.dispatcher.shutdown(timeout).await
+    }
 }
 
 /// Axum middleware function that processes requests and responses for Treblle
@@ -42,9 +89,18 @@ pub async fn treblle_middleware(
     req: Request<Body>,
     next: Next,
 ) -> Response<Body> {
+    let config = layer.config.load();
+    if !config.core.is_enabled() {
+        return next.run(req).await;
+    }
+
     let start_time = Instant::now();
 
-    let should_process = !layer.config.core.should_ignore_route(req.uri().path())
+    let route_path = req.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+    let policy = config.core.policy_for(req.uri().path(), route_path);
+    let decision = config.core.capture.capture_request(req.method().as_str(), req.uri().path());
+    let mut should_process = decision != CaptureDecision::Skip
+        && !config.core.should_ignore_request(req.uri().path(), route_path)
         && req
             .headers()
             .get("Content-Type")
@@ -52,58 +108,243 @@ pub async fn treblle_middleware(
             .map(|ct| ct.starts_with("application/json"))
             .unwrap_or(false);
 
-    // Process request for Treblle
-    let req = if should_process {
+    if !should_process {
+        metrics::record_ignored();
+    } else if !policy.is_sampled() {
+        debug!("Request to {} sampled out", req.uri().path());
+        metrics::record_sampled_out();
+        should_process = false;
+    }
+
+    // Capture the request body for Treblle, unless the route leaves it out
+    let req = if should_process && policy.capture_request_body() {
         let (parts, body) = req.into_parts();
-        let bytes = axum::body::to_bytes(body, MAX_BODY_SIZE).await.unwrap_or_default();
+        let limit = policy.max_request_body_size(&config.core);
+        let (captured, body) = capture_body(body, limit).await;
 
-        // Store original body for Treblle processing
-        let mut new_req = Request::from_parts(parts, Body::from(bytes.clone()));
-        new_req.extensions_mut().insert(bytes);
-        new_req
+        let mut req = Request::from_parts(parts, body);
+        req.extensions_mut().insert(captured);
+        req
     } else {
         req
     };
 
+    // The request payload is held back and sent together with the response, as one payload
+    let mut held = None;
     if should_process {
         debug!("Processing request for Treblle: {}", req.uri().path());
-        let request_payload =
-            PayloadBuilder::build_request_payload::<AxumExtractor>(&req, &layer.config.core);
+        let payload =
+            PayloadBuilder::build_request_payload::<AxumExtractor>(&req, &config.core, &policy);
+        held = Some(HeldRequest {
+            request: payload.data.request,
+            method: req.method().clone(),
+            path: req.uri().path().to_string(),
+            route_path: req.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string()),
+            deferred: decision == CaptureDecision::AfterResponse,
+        });
+    }
 
-        let treblle_client = layer.treblle_client.clone();
-        tokio::spawn(async move {
-            if let Err(e) = treblle_client.send_to_treblle(request_payload).await {
-                error!("Failed to send request payload to Treblle: {:?}", e);
+    let response = next.run(req).await;
+
+    let Some(held) = held else {
+        return response;
+    };
+    let duration = start_time.elapsed();
+
+    // Requests whose capture depends on the response are dropped with it
+    if held.deferred {
+        let status = response.status().as_u16();
+        let capture = &config.core.capture;
+        if !capture.should_capture_response(held.method.as_str(), &held.path, status, duration) {
+            debug!("Ignoring status code {} for {}", status, held.path);
+            metrics::record_ignored();
+            return response;
+        }
+    }
+
+    if !policy.capture_response_body() {
+        send_exchange(&layer.dispatcher, &config, policy, &response, held.request, duration);
+        return response;
+    }
+
+    // The response body streams through to the client, the payload is sent once it went by
+    let limit = policy.max_response_body_size(&config.core);
+    let (parts, body) = response.into_parts();
+    let (status, headers) = (parts.status, parts.headers.clone());
+    let tap = BodyTap::new(limit, body.size_hint().exact(), move |captured| {
+        let mut response_head = Response::new(Body::empty());
+        *response_head.status_mut() = status;
+        *response_head.headers_mut() = headers;
+        response_head.extensions_mut().insert(captured);
+
+        let policy = config.core.policy_for(&held.path, held.route_path.as_deref());
+        send_exchange(&layer.dispatcher, &config, policy, &response_head, held.request, duration);
+    });
+
+    Response::from_parts(parts, Body::new(TapBody { inner: body, tap }))
+}
+
+/// A request held back to be sent with its response
+struct HeldRequest {
+    request: RequestInfo,
+    method: Method,
+    path: String,
+    route_path: Option<String>,
+    /// Whether the capture depends on the response
+    deferred: bool,
+}
+
+/// Build the payload of a response with the request held back for it, and dispatch it
+fn send_exchange(
+    dispatcher: &Dispatcher,
+    config: &AxumConfig,
+    policy: RoutePolicy<'_>,
+    response: &Response<Body>,
+    request: RequestInfo,
+    duration: Duration,
+) {
+    debug!("Processing response for Treblle: {}", response.status());
+    let mut payload = PayloadBuilder::build_response_payload::<AxumExtractor>(
+        response,
+        &config.core,
+        &policy,
+        duration,
+    );
+    payload.data.request = request;
+    send_payload(dispatcher, payload);
+}
+
+/// Read a body for Treblle up to `limit` bytes.
+///
+/// Returns the captured bytes, and a body replaying what was read before the rest of the
+/// original body, so the application still receives all of it.
+async fn capture_body(mut body: Body, limit: usize) -> (CapturedBody, Body) {
+    let size = body.size_hint().exact();
+    let mut read = VecDeque::new();
+    let mut captured = BytesMut::new();
+
+    let complete = loop {
+        if captured.len() > limit {
+            break false;
+        }
+        match body.frame().await {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    let take = data.len().min(limit + 1 - captured.len());
+                    captured.extend_from_slice(&data[..take]);
+                }
+                read.push_back(Ok(frame));
             }
-        });
+            Some(Err(e)) => {
+                read.push_back(Err(e));
+                break false;
+            }
+            None => break true,
+        }
+    };
+
+    let mut captured = captured.freeze();
+    let captured = if complete {
+        CapturedBody::complete(captured, limit)
+    } else {
+        captured.truncate(limit);
+        debug!("Captured {} bytes of a body over the {} byte limit", captured.len(), limit);
+        CapturedBody::partial(captured, size)
+    };
+
+    (captured, Body::new(ReplayBody { read, rest: body }))
+}
+
+/// A body yielding the frames read for capture, then the rest of the original body
+struct ReplayBody {
+    read: VecDeque<Result<Frame<Bytes>, axum::Error>>,
+    rest: Body,
+}
+
+impl HttpBody for ReplayBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        match self.read.pop_front() {
+            Some(frame) => Poll::Ready(Some(frame)),
+            None => Pin::new(&mut self.rest).poll_frame(cx),
+        }
     }
 
-    let mut response = next.run(req).await;
+    fn is_end_stream(&self) -> bool {
+        self.read.is_empty() && self.rest.is_end_stream()
+    }
 
-    if should_process {
-        let duration = start_time.elapsed();
+    fn size_hint(&self) -> SizeHint {
+        let read: u64 = self
+            .read
+            .iter()
+            .filter_map(|frame| frame.as_ref().ok()?.data_ref())
+            .map(|data| data.len() as u64)
+            .sum();
+        let rest = self.rest.size_hint();
 
-        let (parts, body) = response.into_parts();
-        let bytes = axum::body::to_bytes(body, MAX_BODY_SIZE).await.unwrap_or_default();
+        let mut hint = SizeHint::new();
+        hint.set_lower(rest.lower() + read);
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(upper + read);
+        }
+        hint
+    }
+}
 
-        // Store original body for Treblle processing
-        response = Response::from_parts(parts, Body::from(bytes.clone()));
-        response.extensions_mut().insert(bytes);
+/// A body passing the frames of a response through, copying its start for the payload
+struct TapBody<F: FnOnce(CapturedBody)> {
+    inner: Body,
+    tap: BodyTap<F>,
+}
 
-        debug!("Processing response for Treblle: {}", response.status());
-        let response_payload = PayloadBuilder::build_response_payload::<AxumExtractor>(
-            &response,
-            &layer.config.core,
-            duration,
-        );
+impl<F> HttpBody for TapBody<F>
+where
+    F: FnOnce(CapturedBody) + Unpin,
+{
+    type Data = Bytes;
+    type Error = axum::Error;
 
-        let treblle_client = layer.treblle_client.clone();
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = &mut *self;
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    this.tap.copy(data);
+                }
+            }
+            Some(Err(_)) => this.tap.finish(false),
+            None => this.tap.finish(true),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Dispatch a payload, logging failures to send it in the background
+fn send_payload(dispatcher: &Dispatcher, payload: TrebllePayload) {
+    let transport = dispatcher.transport().name();
+    if let Some(send) = dispatcher.dispatch(payload) {
         tokio::spawn(async move {
-            if let Err(e) = treblle_client.send_to_treblle(response_payload).await {
-                error!("Failed to send response payload to Treblle: {:?}", e);
+            if let Err(e) = send.await {
+                error!("Failed to send payload via {} transport: {:?}", transport, e);
             }
         });
     }
-
-    response
 }
@@ -13,7 +13,7 @@ use tower_http::timeout::TimeoutLayer;
 use treblle_axum::extractors::AxumExtractor;
 use treblle_axum::{AxumConfig, TreblleLayer};
 use treblle_core::constants::MAX_BODY_SIZE;
-use treblle_core::PayloadBuilder;
+use treblle_core::{PayloadBuilder, RoutePolicy};
 
 pub fn create_test_request(headers: Vec<(&str, &str)>) -> http::Request<Body> {
     let builder = http::Request::builder().uri("https://api.example.com/test").method("POST");
@@ -198,7 +198,11 @@ async fn test_treblle_payload_creation() {
     *req.body_mut() = Body::from(test_data.to_string());
     req.extensions_mut().insert(Bytes::from(test_data.to_string()));
 
-    let payload = PayloadBuilder::build_request_payload::<AxumExtractor>(&req, &config.core);
+    let payload = PayloadBuilder::build_request_payload::<AxumExtractor>(
+        &req,
+        &config.core,
+        &RoutePolicy::default(),
+    );
 
     if let Some(body) = &payload.data.request.body {
         if let Some(password) = body.get("password") {
@@ -287,8 +291,11 @@ async fn test_data_masking_patterns() {
             .build()
             .unwrap();
 
-        let treblle_payload =
-            PayloadBuilder::build_request_payload::<AxumExtractor>(&req, &config.core);
+        let treblle_payload = PayloadBuilder::build_request_payload::<AxumExtractor>(
+            &req,
+            &config.core,
+            &RoutePolicy::default(),
+        );
 
         // Verify the payload has masked sensitive data
         if let Some(body) = treblle_payload.data.request.body {
@@ -366,8 +373,11 @@ async fn test_partial_field_masking() {
         .build()
         .unwrap();
 
-    let treblle_payload =
-        PayloadBuilder::build_request_payload::<AxumExtractor>(&req, &config.core);
+    let treblle_payload = PayloadBuilder::build_request_payload::<AxumExtractor>(
+        &req,
+        &config.core,
+        &RoutePolicy::default(),
+    );
 
     // Verify masking in Treblle payload
     if let Some(body) = treblle_payload.data.request.body {
@@ -400,3 +410,484 @@ async fn test_partial_field_masking() {
     assert_eq!(body["user"]["credit_card"]["cvv"], "123");
     assert_eq!(body["user"]["shipping_address"]["street"], "123 Main St");
 }
+
+#[tokio::test]
+async fn test_middleware_sends_masked_payloads_through_custom_transport() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let config = AxumConfig::builder()
+        .api_key("test_key")
+        .add_masked_fields(vec!["password"])
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/echo", post(echo_handler))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let request = http::Request::builder()
+        .uri("/echo")
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"password": "secret123"}).to_string()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+
+    // Payloads are sent from spawned tasks, once the response body went through
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The request and its response are sent as one payload
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0].data.request.body.as_ref().unwrap()["password"], "*****");
+    assert_eq!(payloads[0].data.response.code, 200);
+}
+
+#[tokio::test]
+async fn test_shutdown_flushes_pending_payloads() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let treblle = Treblle::new("test_key").with_transport(transport.clone());
+
+    let app = Router::new().route("/echo", post(echo_handler)).treblle(treblle.clone());
+
+    let request = || {
+        http::Request::builder()
+            .uri("/echo")
+            .method(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"username": "test_user"}).to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+
+    // No sleep needed, shutdown waits for the spawned sends
+    treblle.shutdown(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(transport.len(), 1);
+
+    // Requests are still served after shutdown, but no longer reported
+    let response = app.oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+    treblle.shutdown(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(transport.len(), 1);
+}
+
+#[tokio::test]
+async fn test_config_handle_applies_replaced_config() {
+    use treblle_axum::{ConfigHandle, MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let handle = ConfigHandle::new(AxumConfig::builder().api_key("test_key").build().unwrap());
+
+    let app = Router::new()
+        .route("/echo", post(echo_handler))
+        .treblle(Treblle::from_config_handle(handle.clone()).with_transport(transport.clone()));
+
+    let request = || {
+        http::Request::builder()
+            .uri("/echo")
+            .method(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"customer_ref": "c-42"}).to_string()))
+            .unwrap()
+    };
+
+    app.clone().oneshot(request()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let request_payload = transport.payloads().into_iter().find(|p| p.data.request.body.is_some());
+    assert_eq!(request_payload.unwrap().data.request.body.unwrap()["customer_ref"], "c-42");
+
+    // Newly masked fields apply to the next request without rebuilding the router
+    handle
+        .update(|config| {
+            let mut config = config.clone();
+            config.core.masked_fields.insert("customer_ref".to_string());
+            Ok(config)
+        })
+        .unwrap();
+    transport.take();
+
+    app.oneshot(request()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let request_payload = transport.payloads().into_iter().find(|p| p.data.request.body.is_some());
+    assert_eq!(request_payload.unwrap().data.request.body.unwrap()["customer_ref"], "*****");
+}
+
+#[tokio::test]
+async fn test_disabled_middleware_passes_requests_through() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    // No API key is needed outside the allowed environments
+    let config = AxumConfig::builder()
+        .environments(["production"])
+        .environment("development")
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/echo", post(echo_handler))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let request = http::Request::builder()
+        .uri("/echo")
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"password": "secret123"}).to_string()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["password"], "secret123");
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+}
+
+#[tokio::test]
+async fn test_capture_filters_skip_ignored_methods_and_status_codes() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let config = AxumConfig::builder()
+        .api_key("test_key")
+        .ignored_methods(["PUT"])
+        .ignored_status_codes([404])
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/echo", post(echo_handler).put(echo_handler))
+        .route("/missing", post(|| async { (StatusCode::NOT_FOUND, Json(json!({"ok": false}))) }))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let request = |method: Method, uri: &str| {
+        http::Request::builder()
+            .uri(uri)
+            .method(method)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"username": "test_user"}).to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request(Method::PUT, "/echo")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.clone().oneshot(request(Method::POST, "/missing")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    // Held back requests are sent once their response is known to be captured
+    let response = app.oneshot(request(Method::POST, "/echo")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads.iter().any(|p| p.data.request.body.is_some()));
+    assert!(payloads.iter().any(|p| p.data.response.code == 200));
+}
+
+#[tokio::test]
+async fn test_route_template_is_reported_and_ignored() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let config = AxumConfig::builder()
+        .api_key("test_key")
+        .add_ignored_routes(["/internal/:job"])
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/users/:id/orders/:order", post(echo_handler))
+        .route("/internal/:job", post(echo_handler))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let request = |uri: &str| {
+        http::Request::builder()
+            .uri(uri)
+            .method(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"username": "test_user"}).to_string()))
+            .unwrap()
+    };
+
+    app.clone().oneshot(request("/internal/reindex")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    app.oneshot(request("/users/8271/orders/99")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let request_payload = transport.payloads().into_iter().find(|p| p.data.request.body.is_some());
+    let request_info = request_payload.unwrap().data.request;
+    assert_eq!(request_info.route_path.as_deref(), Some("/users/:id/orders/:order"));
+    assert!(request_info.url.ends_with("/users/8271/orders/99"));
+}
+
+#[tokio::test]
+async fn test_route_overrides_apply_to_matching_requests() {
+    use treblle_axum::{MemoryTransport, RouteOverride, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let config = AxumConfig::builder()
+        .api_key("test_key")
+        .add_route_override(
+            RouteOverride::new("^/payments/")
+                .unwrap()
+                .masked_fields(["username"])
+                .capture_response_body(false),
+        )
+        .add_route_override(RouteOverride::new("^/search$").unwrap().sample_rate(0.0))
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/payments/:id", post(echo_handler))
+        .route("/search", post(echo_handler))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let request = |uri: &str| {
+        http::Request::builder()
+            .uri(uri)
+            .method(Method::POST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"username": "test_user"}).to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request("/search")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    // The response body still reaches the client when it isn't captured
+    let response = app.oneshot(request("/payments/1")).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["username"], "test_user");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    let request_payload = payloads.iter().find(|p| p.data.request.body.is_some()).unwrap();
+    assert_eq!(request_payload.data.request.body.as_ref().unwrap()["username"], "*****");
+    assert!(payloads.iter().all(|p| p.data.response.body.is_none()));
+}
+
+#[tokio::test]
+async fn test_oversized_bodies_are_truncated_but_passed_through() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let config = AxumConfig::builder()
+        .api_key("test_key")
+        .max_request_body_size(64)
+        .max_response_body_size(32)
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/echo", post(echo_handler))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let items: Vec<u32> = (0..50).collect();
+    let body = format!(r#"{{"password":"secret","items":{}}}"#, json!(items));
+    let request = http::Request::builder()
+        .uri("/echo")
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.clone()))
+        .unwrap();
+
+    // The handler and the client still get the whole body
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let echoed = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&echoed).unwrap()["items"], json!(items));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    let request_payload = payloads.iter().find(|p| p.data.request.body.is_some()).unwrap();
+    let captured = request_payload.data.request.body.as_ref().unwrap();
+    assert_eq!(captured["truncated"], true);
+    assert_eq!(captured["original_size"], body.len());
+    assert_eq!(captured["body"]["password"], "*****");
+    assert!(captured["body"]["items"].as_array().unwrap().len() < items.len());
+
+    let response_payload = payloads.iter().find(|p| p.data.response.body.is_some()).unwrap();
+    assert_eq!(response_payload.data.response.body.as_ref().unwrap()["truncated"], true);
+    assert_eq!(response_payload.data.response.size, echoed.len() as u64);
+}
+
+#[tokio::test]
+async fn test_headers_are_filtered_and_masked() {
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let transport = MemoryTransport::new();
+    let config = AxumConfig::builder()
+        .api_key("test_key")
+        .ignored_headers(["x-internal"])
+        .masked_cookies(["sid"])
+        .build()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/echo", post(echo_handler))
+        .treblle(Treblle::from_config(config).with_transport(transport.clone()));
+
+    let request = http::Request::builder()
+        .uri("/echo")
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .header("authorization", "Bearer abc.def")
+        .header("cookie", "sid=abc; theme=dark")
+        .header("x-internal", "1")
+        .body(Body::from(json!({"name": "Ada"}).to_string()))
+        .unwrap();
+
+    // The application still sees the original headers
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    let headers =
+        &payloads.iter().find(|p| p.data.request.body.is_some()).unwrap().data.request.headers;
+    assert_eq!(headers["authorization"], "Bearer *****");
+    assert_eq!(headers["cookie"], "sid=*****; theme=dark");
+    assert!(!headers.contains_key("x-internal"));
+    assert_eq!(headers["content-type"], "application/json");
+}
+
+#[tokio::test]
+async fn test_otlp_exports_one_span_per_request() {
+    use treblle_axum::{Treblle, TreblleExt};
+    use treblle_core::OtlpTransport;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let collector = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/traces"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+
+    let transport = OtlpTransport::new(collector.uri()).unwrap();
+    let app = Router::new()
+        .route("/users/:id", post(echo_handler))
+        .treblle(Treblle::new("test_key").with_transport(transport));
+
+    let request = http::Request::builder()
+        .uri("/users/42")
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"username": "test_user"}).to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), MAX_BODY_SIZE).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let spans: Vec<Value> = collector
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .flat_map(|request| {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            body["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap().clone()
+        })
+        .collect();
+    assert_eq!(spans.len(), 1);
+
+    let span = &spans[0];
+    let attribute = |key: &str| {
+        span["attributes"].as_array().unwrap().iter().find(|a| a["key"] == key).unwrap()["value"]
+            .clone()
+    };
+    assert_eq!(span["name"], "POST /users/:id");
+    assert_eq!(attribute("http.request.method")["stringValue"], "POST");
+    assert_eq!(attribute("http.route")["stringValue"], "/users/:id");
+    assert_eq!(attribute("http.response.status_code")["intValue"], "200");
+}
+
+/// A response body yielding the chunks sent through a channel until it's closed
+struct ChannelBody(tokio::sync::mpsc::Receiver<Bytes>);
+
+impl http_body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = std::convert::Infallible;
+
+    fn poll_frame(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Result<http_body::Frame<Bytes>, Self::Error>>> {
+        self.0.poll_recv(cx).map(|chunk| chunk.map(|chunk| Ok(http_body::Frame::data(chunk))))
+    }
+}
+
+#[tokio::test]
+async fn test_streamed_responses_reach_the_client_before_they_end() {
+    use http_body_util::BodyExt;
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+    use treblle_axum::{MemoryTransport, Treblle, TreblleExt};
+
+    let (tx, rx) = mpsc::channel(1);
+    let rx = Arc::new(Mutex::new(Some(rx)));
+    let transport = MemoryTransport::new();
+    let app = Router::new()
+        .route(
+            "/stream",
+            post(move || async move {
+                let rx = rx.lock().unwrap().take().unwrap();
+                Response::builder()
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::new(ChannelBody(rx)))
+                    .unwrap()
+            }),
+        )
+        .treblle(Treblle::new("test_key").with_transport(transport.clone()));
+
+    let request = http::Request::builder()
+        .uri("/stream")
+        .method(Method::POST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"username": "test_user"}).to_string()))
+        .unwrap();
+    let response = timeout(Duration::from_secs(1), app.oneshot(request)).await.unwrap().unwrap();
+    let mut body = response.into_body();
+
+    // The first chunk reaches the client while the stream is still open
+    tx.send(Bytes::from_static(br#"{"items":[1,"#)).await.unwrap();
+    let frame = timeout(Duration::from_secs(1), body.frame()).await.unwrap().unwrap().unwrap();
+    assert_eq!(frame.into_data().unwrap(), br#"{"items":[1,"#.as_slice());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(transport.is_empty());
+
+    // The payload is sent once the stream ended
+    tx.send(Bytes::from_static(b"2]}")).await.unwrap();
+    drop(tx);
+    let rest = body.collect().await.unwrap().to_bytes();
+    assert_eq!(rest, b"2]}".as_slice());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0].data.response.body, Some(json!({"items": [1, 2]})));
+    assert_eq!(payloads[0].data.request.body, Some(json!({"username": "test_user"})));
+}
@@ -0,0 +1,256 @@
+//! Bodies captured for payloads, up to a size limit.
+//!
+//! Integrations capture at most the configured number of bytes of a body, while the
+//! application still receives all of it. A body cut at the limit is reported as
+//!
+//! ```json
+//! { "truncated": true, "original_size": 52428800, "body": { "items": [1, 2] } }
+//! ```
+//!
+//! where `body` holds the complete JSON values of the captured bytes, closed where they were
+//! cut, so it can still be masked. `original_size` is left out when the size isn't known.
+//!
+//! Response bodies are captured with a [`BodyTap`] as they stream to the client, so they
+//! aren't held back.
+
+use bytes::{Bytes, BytesMut};
+use serde_json::{json, Value};
+
+/// A request or response body captured for a payload
+#[derive(Clone, Debug, Default)]
+pub struct CapturedBody {
+    bytes: Bytes,
+    size: Option<u64>,
+    truncated: bool,
+}
+
+impl CapturedBody {
+    /// Capture a whole body, keeping at most `limit` bytes of it
+    pub fn complete(bytes: Bytes, limit: usize) -> Self {
+        let size = Some(bytes.len() as u64);
+        if bytes.len() > limit {
+            Self { bytes: bytes.slice(..limit), size, truncated: true }
+        } else {
+            Self { bytes, size, truncated: false }
+        }
+    }
+
+    /// Capture the first bytes of a body that goes on past them, whose size may be unknown
+    pub fn partial(prefix: Bytes, size: Option<u64>) -> Self {
+        Self { bytes: prefix, size, truncated: true }
+    }
+
+    /// The captured bytes
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// Size of the whole body, if known
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Check if the body was cut at the limit
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The body as JSON for a payload, `None` if it isn't JSON.
+    ///
+    /// Truncated bodies are wrapped in a marker object, see the [module docs](self).
+    pub fn to_json(&self) -> Option<Value> {
+        if !self.truncated {
+            return serde_json::from_slice(&self.bytes).ok();
+        }
+
+        let mut marker = json!({ "truncated": true });
+        if let Some(size) = self.size {
+            marker["original_size"] = size.into();
+        }
+        if let Some(body) = close_json(&self.bytes) {
+            marker["body"] = body;
+        }
+        Some(marker)
+    }
+}
+
+/// Copies the start of a body while it streams through to its receiver.
+///
+/// Up to `limit` bytes of the chunks passed to [`copy`](BodyTap::copy) are kept. Once the
+/// body ends, or the tap is dropped before, e.g. when the client goes away, the captured body
+/// is handed to the callback, exactly once.
+pub struct BodyTap<F: FnOnce(CapturedBody)> {
+    captured: BytesMut,
+    limit: usize,
+    seen: u64,
+    size: Option<u64>,
+    on_finish: Option<F>,
+}
+
+impl<F: FnOnce(CapturedBody)> BodyTap<F> {
+    /// Tap a body of `size` bytes, if known, keeping at most `limit` bytes of it
+    pub fn new(limit: usize, size: Option<u64>, on_finish: F) -> Self {
+        Self { captured: BytesMut::new(), limit, seen: 0, size, on_finish: Some(on_finish) }
+    }
+
+    /// Copy what fits under the limit of a chunk passing through
+    pub fn copy(&mut self, chunk: &[u8]) {
+        let take = chunk.len().min(self.limit.saturating_sub(self.captured.len()));
+        self.captured.extend_from_slice(&chunk[..take]);
+        self.seen += chunk.len() as u64;
+    }
+
+    /// Hand the captured body to the callback, unless it already was.
+    ///
+    /// `ended` tells whether the body went through to its end. A body of known size also
+    /// counts as whole once that many bytes went through.
+    pub fn finish(&mut self, ended: bool) {
+        let Some(on_finish) = self.on_finish.take() else {
+            return;
+        };
+
+        let whole = ended || self.size == Some(self.seen);
+        let captured = std::mem::take(&mut self.captured).freeze();
+        on_finish(if whole && self.seen <= self.limit as u64 {
+            CapturedBody::complete(captured, self.limit)
+        } else {
+            CapturedBody::partial(captured, if whole { Some(self.seen) } else { self.size })
+        });
+    }
+}
+
+impl<F: FnOnce(CapturedBody)> Drop for BodyTap<F> {
+    fn drop(&mut self) {
+        self.finish(false);
+    }
+}
+
+/// Parse the complete values of a JSON document cut short, closing the open containers.
+///
+/// The document is cut back to the last point where a value ended or a container opened,
+/// so strings and numbers are never cut in the middle.
+fn close_json(prefix: &[u8]) -> Option<Value> {
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    // Where the prefix can be cut, and how many containers are open there
+    let mut cut = None;
+
+    for (i, &byte) in prefix.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                closers.push(if byte == b'{' { b'}' } else { b']' });
+                cut = Some((i + 1, closers.len()));
+            }
+            b'}' | b']' => {
+                closers.pop();
+                cut = Some((i + 1, closers.len()));
+            }
+            b',' => cut = Some((i, closers.len())),
+            _ => {}
+        }
+    }
+
+    let (end, depth) = cut?;
+    let mut closed = prefix[..end].to_vec();
+    closed.extend(closers[..depth].iter().rev());
+    serde_json::from_slice(&closed).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truncated(body: &str, limit: usize) -> Value {
+        CapturedBody::complete(Bytes::copy_from_slice(body.as_bytes()), limit).to_json().unwrap()
+    }
+
+    #[test]
+    fn test_complete_body() {
+        let body = CapturedBody::complete(Bytes::from_static(br#"{"a":1}"#), 64);
+        assert!(!body.is_truncated());
+        assert_eq!(body.size(), Some(7));
+        assert_eq!(body.to_json(), Some(json!({ "a": 1 })));
+
+        let body = CapturedBody::complete(Bytes::from_static(b"not json"), 64);
+        assert_eq!(body.to_json(), None);
+    }
+
+    #[test]
+    fn test_truncated_body_keeps_complete_values() {
+        let body = r#"{"user":{"name":"Ada","password":"hunter2"},"items":[1,22,333]}"#;
+
+        assert_eq!(
+            truncated(body, 30),
+            json!({
+                "truncated": true,
+                "original_size": body.len(),
+                "body": { "user": { "name": "Ada" } }
+            })
+        );
+        assert_eq!(
+            truncated(body, body.len() - 3)["body"],
+            json!({ "user": { "name": "Ada", "password": "hunter2" }, "items": [1, 22] })
+        );
+        // Commas and brackets inside strings aren't structure
+        assert_eq!(truncated(r#"["a,]\"b", "cdefgh"]"#, 12)["body"], json!(["a,]\"b"]));
+        assert_eq!(truncated(r#"[{"a":[1]},{"b":2}]"#, 14)["body"], json!([{ "a": [1] }, {}]));
+    }
+
+    #[test]
+    fn test_truncated_body_without_values() {
+        assert_eq!(
+            truncated(r#""a long string""#, 4),
+            json!({ "truncated": true, "original_size": 15 })
+        );
+
+        let body = CapturedBody::partial(Bytes::from_static(b"[1,2"), None);
+        assert_eq!(body.to_json(), Some(json!({ "truncated": true, "body": [1] })));
+    }
+
+    #[test]
+    fn test_body_tap_captures_once() {
+        let tapped = |limit, size, chunks: &[&str], ended| {
+            let mut captured = None;
+            let mut tap = BodyTap::new(limit, size, |body| captured = Some(body));
+            for chunk in chunks {
+                tap.copy(chunk.as_bytes());
+            }
+            if ended {
+                tap.finish(true);
+            }
+            drop(tap);
+            captured.unwrap()
+        };
+
+        let body = tapped(64, None, &[r#"{"a":"#, "1}"], true);
+        assert!(!body.is_truncated());
+        assert_eq!(body.to_json(), Some(json!({ "a": 1 })));
+
+        // The whole size is known once the body ended, even past the limit
+        let body = tapped(8, None, &[r#"{"a":1,"#, r#""b":2}"#], true);
+        assert_eq!(body.bytes().as_ref(), br#"{"a":1,""#);
+        assert_eq!(
+            body.to_json(),
+            Some(json!({ "truncated": true, "original_size": 13, "body": { "a": 1 } }))
+        );
+
+        // Dropped early, the body is whole only if all of its known size went through
+        let body = tapped(64, Some(7), &[r#"{"a":1}"#], false);
+        assert!(!body.is_truncated());
+        let body = tapped(64, None, &[r#"{"a":1,"#], false);
+        assert!(body.is_truncated());
+        assert_eq!(body.size(), None);
+    }
+}
@@ -0,0 +1,316 @@
+//! Rules deciding which requests are captured.
+//!
+//! Method and route rules are checked before a request is handled. Status code rules need
+//! the response, so requests they may exclude are held back until it is known and dropped
+//! together with it. Requests slower than
+//! [`always_capture_slower_than_ms`](CaptureConfig::always_capture_slower_than_ms) are
+//! captured regardless of the status code rules.
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::regex_serde;
+use crate::error::{Result, TreblleError};
+
+/// Capture filters applied on top of the ignored routes
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureConfig {
+    /// HTTP methods of requests that aren't captured, e.g. `OPTIONS` (optional)
+    #[serde(default)]
+    pub ignored_methods: Vec<String>,
+
+    /// Status codes of responses that aren't captured, e.g. `404` or `"3xx"` (optional)
+    #[serde(default)]
+    pub ignored_status_codes: Vec<StatusCodes>,
+
+    /// Capture requests slower than this regardless of status codes (optional)
+    #[serde(default)]
+    pub always_capture_slower_than_ms: Option<u64>,
+
+    /// Status codes to capture per route, the first matching rule applies (optional)
+    #[serde(default)]
+    pub capture_rules: Vec<CaptureRule>,
+}
+
+/// Status codes to capture for the routes matching a pattern
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureRule {
+    /// Regex pattern of the request paths the rule applies to
+    #[serde(
+        serialize_with = "regex_serde::serialize_pattern",
+        deserialize_with = "regex_serde::deserialize_route"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub route: Regex,
+
+    /// HTTP methods the rule applies to; all methods if empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+
+    /// Status codes captured for matching requests; all codes if empty
+    #[serde(default)]
+    pub status_codes: Vec<StatusCodes>,
+}
+
+/// A status code, or a class of them like `4xx`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusCodes {
+    Exact(u16),
+    Class(u16),
+}
+
+/// What to do with a request before its response is known
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureDecision {
+    /// Don't capture the request
+    Skip,
+    /// Capture the request right away
+    Capture,
+    /// Hold the request back until [`CaptureConfig::should_capture_response`] decides
+    AfterResponse,
+}
+
+impl CaptureConfig {
+    /// Check if a request should be captured, before it is handled
+    pub fn capture_request(&self, method: &str, path: &str) -> CaptureDecision {
+        if self.should_ignore_method(method) {
+            return CaptureDecision::Skip;
+        }
+
+        let filters_status = !self.ignored_status_codes.is_empty()
+            || self.rule_for(method, path).is_some_and(|rule| !rule.status_codes.is_empty());
+
+        if filters_status {
+            CaptureDecision::AfterResponse
+        } else {
+            CaptureDecision::Capture
+        }
+    }
+
+    /// Check if requests with the given method should be ignored
+    pub fn should_ignore_method(&self, method: &str) -> bool {
+        self.ignored_methods.iter().any(|ignored| ignored.trim().eq_ignore_ascii_case(method))
+    }
+
+    /// Check if a request held back by [`capture_request`](Self::capture_request) should be
+    /// captured once its response is known
+    pub fn should_capture_response(
+        &self,
+        method: &str,
+        path: &str,
+        status: u16,
+        latency: Duration,
+    ) -> bool {
+        if self.always_capture_slower_than_ms.is_some_and(|ms| latency > Duration::from_millis(ms))
+        {
+            return true;
+        }
+
+        if self.ignored_status_codes.iter().any(|codes| codes.contains(status)) {
+            return false;
+        }
+
+        self.rule_for(method, path).is_none_or(|rule| {
+            rule.status_codes.is_empty() || rule.status_codes.iter().any(|c| c.contains(status))
+        })
+    }
+
+    fn rule_for(&self, method: &str, path: &str) -> Option<&CaptureRule> {
+        self.capture_rules.iter().find(|rule| rule.matches(method, path))
+    }
+}
+
+impl CaptureRule {
+    /// Create a rule for the request paths matching a regex pattern
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the pattern isn't a valid regex.
+    pub fn new(route: &str) -> Result<Self> {
+        let route = Regex::new(route)
+            .map_err(|e| TreblleError::Config(format!("Invalid capture rule route: {e}")))?;
+
+        Ok(Self { route, methods: Vec::new(), status_codes: Vec::new() })
+    }
+
+    /// Only apply the rule to these HTTP methods
+    #[must_use]
+    pub fn methods<T: Into<String>, I: IntoIterator<Item = T>>(mut self, methods: I) -> Self {
+        self.methods = methods.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only capture these status codes for matching requests
+    #[must_use]
+    pub fn status_codes<T: Into<StatusCodes>, I: IntoIterator<Item = T>>(
+        mut self,
+        status_codes: I,
+    ) -> Self {
+        self.status_codes = status_codes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn matches(&self, method: &str, path: &str) -> bool {
+        (self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+            && self.route.is_match(path)
+    }
+}
+
+impl StatusCodes {
+    /// Check if a status code is one of these
+    pub fn contains(self, status: u16) -> bool {
+        match self {
+            Self::Exact(code) => code == status,
+            Self::Class(class) => status / 100 == class,
+        }
+    }
+}
+
+impl From<u16> for StatusCodes {
+    fn from(code: u16) -> Self {
+        Self::Exact(code)
+    }
+}
+
+impl FromStr for StatusCodes {
+    type Err = TreblleError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || TreblleError::Config(format!("Invalid status code: {s}"));
+
+        let codes = match s.to_ascii_lowercase().strip_suffix("xx") {
+            Some(class) => Self::Class(class.parse().map_err(|_| invalid())?),
+            None => Self::Exact(s.parse().map_err(|_| invalid())?),
+        };
+
+        match codes {
+            Self::Exact(100..=599) | Self::Class(1..=5) => Ok(codes),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for StatusCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(code) => write!(f, "{code}"),
+            Self::Class(class) => write!(f, "{class}xx"),
+        }
+    }
+}
+
+impl Serialize for StatusCodes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Exact(code) => serializer.serialize_u16(*code),
+            Self::Class(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusCodes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Code(u16),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Code(code) => code.to_string().parse(),
+            Raw::Text(text) => text.parse(),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for StatusCodes {
+    fn schema_name() -> String {
+        "StatusCodes".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, Metadata, SchemaObject};
+
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("A status code like 404, or a class like \"4xx\"".to_string()),
+                ..Metadata::default()
+            })),
+            instance_type: Some(vec![InstanceType::Integer, InstanceType::String].into()),
+            ..SchemaObject::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FAST: Duration = Duration::from_millis(5);
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!("404".parse::<StatusCodes>().unwrap(), StatusCodes::Exact(404));
+        assert_eq!(" 5XX".parse::<StatusCodes>().unwrap(), StatusCodes::Class(5));
+        assert!("600".parse::<StatusCodes>().is_err());
+        assert!("9xx".parse::<StatusCodes>().is_err());
+        assert!("abc".parse::<StatusCodes>().is_err());
+
+        assert!(StatusCodes::Class(4).contains(429));
+        assert!(!StatusCodes::Class(4).contains(500));
+
+        let codes: Vec<StatusCodes> = serde_json::from_value(json!([404, "5xx"])).unwrap();
+        assert_eq!(codes, [StatusCodes::Exact(404), StatusCodes::Class(5)]);
+        assert_eq!(serde_json::to_value(&codes).unwrap(), json!([404, "5xx"]));
+        assert!(serde_json::from_value::<Vec<StatusCodes>>(json!([1000])).is_err());
+    }
+
+    #[test]
+    fn test_capture_rules() {
+        let capture: CaptureConfig = serde_json::from_value(json!({
+            "ignoredMethods": ["OPTIONS", "head"],
+            "ignoredStatusCodes": [404],
+            "alwaysCaptureSlowerThanMs": 1000,
+            "captureRules": [
+                { "route": "^/catalog/", "methods": ["GET"], "statusCodes": ["4xx", "5xx"] }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(capture.capture_request("HEAD", "/users"), CaptureDecision::Skip);
+        assert_eq!(capture.capture_request("GET", "/catalog/1"), CaptureDecision::AfterResponse);
+        // 404s are ignored everywhere, so every request waits for its response
+        assert_eq!(capture.capture_request("POST", "/users"), CaptureDecision::AfterResponse);
+
+        assert!(!capture.should_capture_response("GET", "/catalog/1", 200, FAST));
+        assert!(capture.should_capture_response("GET", "/catalog/1", 503, FAST));
+        assert!(capture.should_capture_response("POST", "/catalog/1", 200, FAST));
+        assert!(!capture.should_capture_response("POST", "/users", 404, FAST));
+
+        // Slow requests are captured regardless of their status
+        let slow = Duration::from_millis(1500);
+        assert!(capture.should_capture_response("GET", "/catalog/1", 200, slow));
+        assert!(capture.should_capture_response("POST", "/users", 404, slow));
+
+        let rules = CaptureConfig {
+            capture_rules: vec![CaptureRule::new("^/api/").unwrap().status_codes([500])],
+            ..CaptureConfig::default()
+        };
+        assert_eq!(rules.capture_request("GET", "/health"), CaptureDecision::Capture);
+        assert!(CaptureRule::new("(").is_err());
+        assert!(serde_json::from_value::<CaptureRule>(json!({ "route": "(" })).is_err());
+    }
+}
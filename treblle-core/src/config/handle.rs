@@ -0,0 +1,303 @@
+//! Shared handle to a configuration that can be replaced at runtime.
+//!
+//! Middlewares read the configuration through a [`ConfigHandle`] on every request, so a
+//! replaced configuration applies from the next request on, e.g. to mask a newly discovered
+//! field without redeploying. Clones of a handle share the same configuration.
+//!
+//! Only settings read per request are affected. The transport, e.g. the
+//! [`TreblleClient`](crate::TreblleClient) with its API key, URLs and TLS settings, keeps the
+//! configuration it was created with.
+
+use std::fmt;
+use std::sync::{Arc, PoisonError, RwLock};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use super::Config;
+use crate::error::Result;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{error::TreblleError, metrics};
+
+/// Shared, atomically replaceable configuration
+pub struct ConfigHandle<T = Config> {
+    current: Arc<RwLock<Arc<T>>>,
+}
+
+impl<T> ConfigHandle<T> {
+    /// Create a handle to a configuration
+    pub fn new(config: T) -> Self {
+        Self::from(Arc::new(config))
+    }
+
+    /// Get the current configuration.
+    ///
+    /// The returned configuration stays the same while in use, a request keeps seeing the
+    /// configuration it started with even if it is replaced in the meantime.
+    pub fn load(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Replace the configuration, returning the previous one
+    pub fn store(&self, config: T) -> Arc<T> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, Arc::new(config))
+    }
+
+    /// Replace the configuration with one derived from the current one.
+    ///
+    /// Concurrent updates are applied one after the other, so none of them is lost.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `update`, the configuration is left unchanged then.
+    pub fn update<F>(&self, update: F) -> Result<Arc<T>>
+    where
+        F: FnOnce(&T) -> Result<T>,
+    {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let config = update(&current)?;
+        Ok(std::mem::replace(&mut *current, Arc::new(config)))
+    }
+
+    /// Reload the configuration whenever the contents of a file change.
+    ///
+    /// The file is checked every `interval` and `load` builds the new configuration from it,
+    /// e.g. `AxumConfig::from_file`. A file that fails to load keeps the current
+    /// configuration and the error is printed to stderr. Watching stops when the returned
+    /// [`FileWatcher`] is dropped.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch_file<P, F>(&self, path: P, interval: Duration, load: F) -> FileWatcher
+    where
+        T: Send + Sync + 'static,
+        P: Into<PathBuf>,
+        F: Fn(&Path) -> Result<T> + Send + 'static,
+    {
+        self.watch_file_with_errors(path, interval, load, |path, e| {
+            let _ = writeln!(
+                std::io::stderr(),
+                "Treblle failed to reload {}, keeping the current configuration: {e}",
+                path.display()
+            );
+        })
+    }
+
+    /// Like [`watch_file`](Self::watch_file), but reports errors to `on_error` instead of
+    /// stderr, e.g. to log them with the application's logger.
+    ///
+    /// `on_error` is called when the file can't be read, once until it is readable again,
+    /// and when a changed file fails to load.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch_file_with_errors<P, F, E>(
+        &self,
+        path: P,
+        interval: Duration,
+        load: F,
+        on_error: E,
+    ) -> FileWatcher
+    where
+        T: Send + Sync + 'static,
+        P: Into<PathBuf>,
+        F: Fn(&Path) -> Result<T> + Send + 'static,
+        E: Fn(&Path, &TreblleError) + Send + 'static,
+    {
+        let path = path.into();
+        let handle = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let mut contents = std::fs::read(&path).ok();
+        let thread = thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut readable = true;
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let current = match std::fs::read(&path) {
+                        Ok(current) => current,
+                        Err(e) => {
+                            if std::mem::replace(&mut readable, false) {
+                                on_error(&path, &e.into());
+                            }
+                            continue;
+                        }
+                    };
+                    readable = true;
+                    if contents.as_ref() == Some(&current) {
+                        continue;
+                    }
+                    contents = Some(current);
+
+                    match load(&path) {
+                        Ok(config) => {
+                            handle.store(config);
+                            metrics::record_config_reload("applied");
+                        }
+                        Err(e) => {
+                            on_error(&path, &e);
+                            metrics::record_config_reload("failed");
+                        }
+                    }
+                }
+            }
+        });
+
+        FileWatcher { path, stop: Some(stop), thread: Some(thread) }
+    }
+}
+
+impl<T> Clone for ConfigHandle<T> {
+    fn clone(&self) -> Self {
+        Self { current: Arc::clone(&self.current) }
+    }
+}
+
+impl<T> From<T> for ConfigHandle<T> {
+    fn from(config: T) -> Self {
+        Self::new(config)
+    }
+}
+
+impl<T> From<Arc<T>> for ConfigHandle<T> {
+    fn from(config: Arc<T>) -> Self {
+        Self { current: Arc::new(RwLock::new(config)) }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ConfigHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConfigHandle").field(&self.load()).finish()
+    }
+}
+
+/// Background reload of a configuration file, stopped when dropped
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct FileWatcher {
+    path: PathBuf,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileWatcher {
+    /// Path of the watched file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreblleError;
+
+    fn config(api_key: &str) -> Config {
+        Config::builder().api_key(api_key).build().unwrap()
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let handle = ConfigHandle::new(config("first"));
+        let shared = handle.clone();
+        let in_flight = handle.load();
+
+        let previous = shared.store(config("second"));
+        assert_eq!(previous.api_key, "first");
+        assert_eq!(handle.load().api_key, "second");
+        // Configurations already loaded are unaffected
+        assert_eq!(in_flight.api_key, "first");
+    }
+
+    #[test]
+    fn test_update() {
+        let handle = ConfigHandle::new(config("test_key"));
+
+        handle
+            .update(|current| {
+                let mut config = current.clone();
+                config.masked_fields.insert("customer_ref".to_string());
+                Ok(config)
+            })
+            .unwrap();
+        assert!(handle.load().should_mask_field("customer_ref"));
+
+        let result = handle.update(|_| Err(TreblleError::Config("invalid".into())));
+        assert!(result.is_err());
+        assert!(handle.load().should_mask_field("customer_ref"));
+    }
+
+    #[test]
+    fn test_watch_file_reports_errors() {
+        let path =
+            std::env::temp_dir().join(format!("treblle-watch-errors-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"apiKey": "file_key"}"#).unwrap();
+
+        let handle = ConfigHandle::new(Config::from_file(&path).unwrap());
+        let (errors, reported) = mpsc::channel();
+        let _watcher = handle.watch_file_with_errors(
+            &path,
+            Duration::from_millis(10),
+            |path| Config::from_file(path),
+            move |_, e| {
+                let _ = errors.send(e.to_string());
+            },
+        );
+
+        std::fs::write(&path, r#"{"apiKey": ""#).unwrap();
+        let error = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(error.contains("Config error"), "{error}");
+        assert_eq!(handle.load().api_key, "file_key");
+
+        // A missing file is reported once
+        std::fs::remove_file(&path).unwrap();
+        assert!(reported.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(reported.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn test_watch_file() {
+        let path = std::env::temp_dir().join(format!("treblle-watch-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"apiKey": "file_key"}"#).unwrap();
+
+        let handle = ConfigHandle::new(Config::from_file(&path).unwrap());
+        let watcher =
+            handle.watch_file(&path, Duration::from_millis(10), |path| Config::from_file(path));
+        assert_eq!(watcher.path(), path);
+
+        let wait_for = |api_key: &str| {
+            (0..500).any(|_| {
+                thread::sleep(Duration::from_millis(10));
+                handle.load().api_key == api_key
+            })
+        };
+
+        std::fs::write(&path, r#"{"apiKey": "reloaded_key"}"#).unwrap();
+        assert!(wait_for("reloaded_key"));
+
+        // An invalid file keeps the current configuration
+        std::fs::write(&path, r#"{"apiKey": ""#).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(handle.load().api_key, "reloaded_key");
+
+        drop(watcher);
+        std::fs::write(&path, r#"{"apiKey": "ignored_key"}"#).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(handle.load().api_key, "reloaded_key");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
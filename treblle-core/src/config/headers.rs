@@ -0,0 +1,225 @@
+//! Which headers are captured, and how their values are masked.
+//!
+//! Header names are matched case-insensitively. `allowed_headers` switches to allow-list mode,
+//! where only the listed headers are captured, and `ignored_headers` drops headers in either
+//! mode. Masked headers keep enough of their value to stay useful:
+//!
+//! - `Authorization` and `Proxy-Authorization` keep their scheme, e.g. `Bearer *****`
+//! - `Cookie` and `Set-Cookie` keep the cookie names and attributes, e.g. `sid=*****; Path=/`.
+//!   Several `Set-Cookie` headers folded into one value with `, ` are masked one by one.
+//! - other headers are replaced with `*****`
+//!
+//! Headers matching the masked fields are masked too, as they always were.
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::defaults::DEFAULT_MASKED_HEADERS;
+
+const MASK: &str = "*****";
+
+/// Filters and masking rules for request and response headers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct HeaderConfig {
+    /// Only capture these headers; all headers if empty (optional)
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+
+    /// Headers that aren't captured (optional)
+    #[serde(default)]
+    pub ignored_headers: Vec<String>,
+
+    /// Headers whose values are masked, defaults to the authorization and cookie headers
+    #[serde(default = "default_masked_headers")]
+    pub masked_headers: Vec<String>,
+
+    /// Cookies masked in `Cookie` and `Set-Cookie` headers; all cookies if empty (optional)
+    #[serde(default)]
+    pub masked_cookies: Vec<String>,
+}
+
+impl Default for HeaderConfig {
+    fn default() -> Self {
+        Self {
+            allowed_headers: Vec::new(),
+            ignored_headers: Vec::new(),
+            masked_headers: default_masked_headers(),
+            masked_cookies: Vec::new(),
+        }
+    }
+}
+
+impl HeaderConfig {
+    /// Check if a header is captured at all
+    pub fn should_capture(&self, name: &str) -> bool {
+        (self.allowed_headers.is_empty() || contains(&self.allowed_headers, name))
+            && !contains(&self.ignored_headers, name)
+    }
+
+    /// Mask a header value, `None` if the header isn't masked.
+    ///
+    /// Cookie headers are masked cookie by cookie, so setting `masked_cookies` masks those
+    /// cookies only, even if the cookie headers aren't masked.
+    pub fn mask(&self, name: &str, value: &str) -> Option<String> {
+        let masked = contains(&self.masked_headers, name);
+
+        if name.eq_ignore_ascii_case("cookie") || name.eq_ignore_ascii_case("set-cookie") {
+            if !masked && self.masked_cookies.is_empty() {
+                return None;
+            }
+            return Some(if name.eq_ignore_ascii_case("cookie") {
+                value.split(';').map(|c| self.mask_cookie(c.trim())).collect::<Vec<_>>().join("; ")
+            } else {
+                split_set_cookies(value)
+                    .into_iter()
+                    .map(|c| self.mask_set_cookie(c))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            });
+        }
+
+        if !masked {
+            return None;
+        }
+
+        if name.eq_ignore_ascii_case("authorization")
+            || name.eq_ignore_ascii_case("proxy-authorization")
+        {
+            if let Some((scheme, _)) = value.trim().split_once(' ') {
+                return Some(format!("{scheme} {MASK}"));
+            }
+        }
+        Some(MASK.to_string())
+    }
+
+    /// Mask the cookie of a single `Set-Cookie` value, keeping its attributes
+    fn mask_set_cookie(&self, value: &str) -> String {
+        // Only the first pair is the cookie, the rest are its attributes
+        match value.split_once(';') {
+            Some((cookie, attributes)) => {
+                format!("{};{attributes}", self.mask_cookie(cookie.trim()))
+            }
+            None => self.mask_cookie(value.trim()),
+        }
+    }
+
+    /// Mask the value of a `name=value` cookie pair
+    ///
+    /// A cookie without `=` is a bare value, so it's masked whole when all cookies are masked.
+    fn mask_cookie(&self, cookie: &str) -> String {
+        match cookie.split_once('=') {
+            Some((name, _))
+                if self.masked_cookies.is_empty()
+                    || self.masked_cookies.iter().any(|c| c == name.trim()) =>
+            {
+                format!("{name}={MASK}")
+            }
+            None if self.masked_cookies.is_empty() && !cookie.is_empty() => MASK.to_string(),
+            _ => cookie.to_string(),
+        }
+    }
+}
+
+/// Split a `Set-Cookie` value folded from several headers into its cookies.
+///
+/// `Expires` dates contain `, ` too, so the value is only split where a `name=value` pair
+/// follows.
+fn split_set_cookies(value: &str) -> Vec<&str> {
+    let mut cookies = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+
+    for part in value.split(", ") {
+        let pair = part.split(';').next().unwrap_or_default();
+        let starts_cookie =
+            pair.split_once('=').is_some_and(|(name, _)| !name.is_empty() && !name.contains(' '));
+        if offset > start && starts_cookie {
+            cookies.push(&value[start..offset - 2]);
+            start = offset;
+        }
+        offset += part.len() + 2;
+    }
+    cookies.push(&value[start..]);
+    cookies
+}
+
+fn contains(names: &[String], name: &str) -> bool {
+    names.iter().any(|n| n.trim().eq_ignore_ascii_case(name))
+}
+
+pub(super) fn default_masked_headers() -> Vec<String> {
+    DEFAULT_MASKED_HEADERS.iter().map(ToString::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_filters() {
+        let headers = HeaderConfig::default();
+        assert!(headers.should_capture("X-Custom"));
+
+        let headers = HeaderConfig {
+            allowed_headers: vec!["Content-Type".into(), "X-Request-Id".into()],
+            ignored_headers: vec!["x-request-id".into()],
+            ..HeaderConfig::default()
+        };
+        assert!(headers.should_capture("content-type"));
+        assert!(!headers.should_capture("X-Request-Id"));
+        assert!(!headers.should_capture("X-Custom"));
+    }
+
+    #[test]
+    fn test_default_masking() {
+        let headers = HeaderConfig::default();
+
+        assert_eq!(headers.mask("Authorization", "Bearer abc.def").unwrap(), "Bearer *****");
+        assert_eq!(headers.mask("proxy-authorization", "opaque").unwrap(), "*****");
+        assert_eq!(
+            headers.mask("Cookie", "sid=abc; theme=dark").unwrap(),
+            "sid=*****; theme=*****"
+        );
+        assert_eq!(
+            headers.mask("Set-Cookie", "sid=abc; Path=/; HttpOnly").unwrap(),
+            "sid=*****; Path=/; HttpOnly"
+        );
+        assert_eq!(headers.mask("Cookie", "sid=abc; bare-token").unwrap(), "sid=*****; *****");
+        assert_eq!(headers.mask("Set-Cookie", "bare-token; HttpOnly").unwrap(), "*****; HttpOnly");
+        assert_eq!(headers.mask("Content-Type", "application/json"), None);
+    }
+
+    #[test]
+    fn test_masked_cookies() {
+        let headers =
+            HeaderConfig { masked_cookies: vec!["sid".into()], ..HeaderConfig::default() };
+        assert_eq!(headers.mask("cookie", "sid=abc; theme=dark").unwrap(), "sid=*****; theme=dark");
+        assert_eq!(headers.mask("set-cookie", "theme=dark; Path=/").unwrap(), "theme=dark; Path=/");
+
+        // Set-Cookie headers folded into one value are masked cookie by cookie
+        assert_eq!(
+            headers.mask("Set-Cookie", "a=1; Path=/, sid=secret; HttpOnly").unwrap(),
+            "a=1; Path=/, sid=*****; HttpOnly"
+        );
+        assert_eq!(
+            headers
+                .mask("Set-Cookie", "a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT, sid=secret")
+                .unwrap(),
+            "a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT, sid=*****"
+        );
+
+        // Bare cookies have no name to match, so only masking all cookies masks them
+        assert_eq!(headers.mask("Cookie", "sid=abc; bare-token").unwrap(), "sid=*****; bare-token");
+
+        // Named cookies are masked even when the cookie headers aren't
+        let headers = HeaderConfig {
+            masked_headers: vec!["X-Api-Key".into()],
+            masked_cookies: vec!["sid".into()],
+            ..HeaderConfig::default()
+        };
+        assert_eq!(headers.mask("Cookie", "sid=abc").unwrap(), "sid=*****");
+        assert_eq!(headers.mask("x-api-key", "abc").unwrap(), "*****");
+        assert_eq!(headers.mask("Authorization", "Bearer abc"), None);
+    }
+}
@@ -0,0 +1,1247 @@
+mod capture;
+mod handle;
+mod headers;
+mod overrides;
+mod regex_serde;
+mod source;
+mod validation;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::constants::defaults::{
+    API_URLS, DEFAULT_IGNORED_ROUTES, DEFAULT_IGNORED_ROUTES_REGEX, DEFAULT_MASKED_FIELDS,
+    DEFAULT_MASKED_FIELDS_REGEX,
+};
+use crate::constants::env::APP_ENV;
+use crate::constants::MAX_BODY_SIZE;
+use crate::error::{Result, TreblleError};
+use crate::tls::TlsConfig;
+
+pub use capture::{CaptureConfig, CaptureDecision, CaptureRule, StatusCodes};
+pub use handle::ConfigHandle;
+#[cfg(not(target_arch = "wasm32"))]
+pub use handle::FileWatcher;
+pub use headers::HeaderConfig;
+pub use overrides::{RouteOverride, RoutePolicy};
+use source::ConfigLayer;
+pub use source::FileFormat;
+pub use validation::{Diagnostic, Severity, ValidationReport};
+
+/// Configuration builder for Treblle integrations
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    api_key: Option<String>,
+    project_id: Option<String>,
+    api_urls: Option<Vec<String>>,
+    masked_fields: Option<HashSet<String>>,
+    masked_fields_regex: Option<Vec<Regex>>,
+    ignored_routes: Option<HashSet<String>>,
+    ignored_routes_regex: Option<Vec<Regex>>,
+    tls: TlsConfig,
+    pinned_spki_sha256: Option<Vec<String>>,
+    strict_tls: Option<bool>,
+    ignored_methods: Option<Vec<String>>,
+    ignored_status_codes: Option<Vec<StatusCodes>>,
+    always_capture_slower_than_ms: Option<u64>,
+    capture_rules: Option<Vec<CaptureRule>>,
+    allowed_headers: Option<Vec<String>>,
+    ignored_headers: Option<Vec<String>>,
+    masked_headers: Option<Vec<String>>,
+    masked_cookies: Option<Vec<String>>,
+    route_overrides: Option<Vec<RouteOverride>>,
+    max_request_body_size: Option<usize>,
+    max_response_body_size: Option<usize>,
+    enabled: Option<bool>,
+    environments: Option<Vec<String>>,
+    environment: Option<String>,
+    file: Option<ConfigLayer>,
+    env: Option<ConfigLayer>,
+}
+
+impl ConfigBuilder {
+    /// Create a new configuration builder
+    pub fn new() -> Self {
+        Self {
+            api_key: None,
+            project_id: None,
+            api_urls: None,
+            masked_fields: None,
+            masked_fields_regex: None,
+            ignored_routes: None,
+            ignored_routes_regex: None,
+            tls: TlsConfig::default(),
+            pinned_spki_sha256: None,
+            strict_tls: None,
+            ignored_methods: None,
+            ignored_status_codes: None,
+            always_capture_slower_than_ms: None,
+            capture_rules: None,
+            allowed_headers: None,
+            ignored_headers: None,
+            masked_headers: None,
+            masked_cookies: None,
+            route_overrides: None,
+            max_request_body_size: None,
+            max_response_body_size: None,
+            enabled: None,
+            environments: None,
+            environment: None,
+            file: None,
+            env: None,
+        }
+    }
+
+    /// Set the API key (required unless Treblle is disabled)
+    pub fn api_key<T: Into<String>>(mut self, key: T) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Set the project ID (optional, defaults to empty string)
+    pub fn project_id<T: Into<String>>(mut self, id: T) -> Self {
+        self.project_id = Some(id.into());
+        self
+    }
+
+    /// Set custom API URLs (optional, uses default URLs if not set)
+    pub fn set_api_urls<T: Into<String>, I: IntoIterator<Item = T>>(mut self, urls: I) -> Self {
+        self.api_urls = Some(urls.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Add additional API URLs to the default set
+    pub fn add_api_urls<T: Into<String>, I: IntoIterator<Item = T>>(mut self, urls: I) -> Self {
+        if let Some(existing) = &mut self.api_urls {
+            existing.extend(urls.into_iter().map(Into::into));
+        } else {
+            let mut defaults: Vec<_> = API_URLS.iter().map(ToString::to_string).collect();
+            defaults.extend(urls.into_iter().map(Into::into));
+            self.api_urls = Some(defaults);
+        }
+        self
+    }
+
+    /// Add masked fields to the default set
+    pub fn add_masked_fields<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        fields: I,
+    ) -> Self {
+        let fields: HashSet<String> = fields.into_iter().map(Into::into).collect();
+
+        if let Some(existing) = &mut self.masked_fields {
+            existing.extend(fields);
+        } else {
+            let mut defaults: HashSet<_> =
+                DEFAULT_MASKED_FIELDS.iter().map(ToString::to_string).collect();
+            defaults.extend(fields);
+            self.masked_fields = Some(defaults);
+        }
+        self
+    }
+
+    /// Set masked fields, replacing the defaults
+    pub fn set_masked_fields<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        fields: I,
+    ) -> Self {
+        let new_fields = fields.into_iter().map(Into::into).collect();
+        self.masked_fields = Some(new_fields);
+        self
+    }
+
+    /// Add regex patterns for masked fields to the default set
+    pub fn add_masked_fields_regex<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self> {
+        let new_patterns: Result<Vec<Regex>> = patterns
+            .into_iter()
+            .map(|p| {
+                Regex::new(&p.into()).map_err(|e| {
+                    TreblleError::Config(format!("Invalid masked field regex pattern: {e}"))
+                })
+            })
+            .collect();
+
+        if let Some(existing) = &mut self.masked_fields_regex {
+            existing.extend(new_patterns?);
+        } else {
+            let mut defaults = vec![Regex::new(DEFAULT_MASKED_FIELDS_REGEX)
+                .expect("Default masked fields regex is invalid")];
+            defaults.extend(new_patterns?);
+            self.masked_fields_regex = Some(defaults);
+        }
+        Ok(self)
+    }
+
+    /// Set regex patterns for masked fields, replacing the defaults
+    pub fn set_masked_fields_regex<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self> {
+        let compiled: Result<Vec<Regex>> = patterns
+            .into_iter()
+            .map(|p| {
+                Regex::new(&p.into()).map_err(|e| {
+                    TreblleError::Config(format!("Invalid masked field regex pattern: {e}"))
+                })
+            })
+            .collect();
+        self.masked_fields_regex = Some(compiled?);
+        Ok(self)
+    }
+
+    /// Add ignored routes to the default set
+    pub fn add_ignored_routes<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        routes: I,
+    ) -> Self {
+        let routes: HashSet<String> = routes.into_iter().map(Into::into).collect();
+
+        if let Some(existing) = &mut self.ignored_routes {
+            existing.extend(routes);
+        } else {
+            let mut defaults: HashSet<_> =
+                DEFAULT_IGNORED_ROUTES.iter().map(ToString::to_string).collect();
+            defaults.extend(routes);
+            self.ignored_routes = Some(defaults);
+        }
+        self
+    }
+
+    /// Set ignored routes, replacing the defaults
+    pub fn set_ignored_routes<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        routes: I,
+    ) -> Self {
+        let new_routes = routes.into_iter().map(Into::into).collect();
+        self.ignored_routes = Some(new_routes);
+        self
+    }
+
+    /// Add regex patterns for ignored routes to the default set
+    pub fn add_ignored_routes_regex<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self> {
+        let new_patterns: Result<Vec<Regex>> = patterns
+            .into_iter()
+            .map(|p| {
+                Regex::new(&p.into()).map_err(|e| {
+                    TreblleError::Config(format!("Invalid ignored route regex pattern: {e}"))
+                })
+            })
+            .collect();
+
+        if let Some(existing) = &mut self.ignored_routes_regex {
+            existing.extend(new_patterns?);
+        } else {
+            let mut defaults = vec![Regex::new(DEFAULT_IGNORED_ROUTES_REGEX)
+                .expect("Default ignored routes regex is invalid")];
+            defaults.extend(new_patterns?);
+            self.ignored_routes_regex = Some(defaults);
+        }
+        Ok(self)
+    }
+
+    /// Set regex patterns for ignored routes, replacing the defaults
+    pub fn set_ignored_routes_regex<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self> {
+        let compiled: Result<Vec<Regex>> = patterns
+            .into_iter()
+            .map(|p| {
+                Regex::new(&p.into()).map_err(|e| {
+                    TreblleError::Config(format!("Invalid ignored route regex pattern: {e}"))
+                })
+            })
+            .collect();
+        self.ignored_routes_regex = Some(compiled?);
+        Ok(self)
+    }
+
+    /// Set a PEM file with the root CAs to trust instead of the bundled ones (optional)
+    #[must_use]
+    pub fn root_ca_path<T: Into<String>>(mut self, path: T) -> Self {
+        self.tls.root_ca_path = Some(path.into());
+        self
+    }
+
+    /// Set PEM files with the client certificate chain and key for mutual TLS (optional)
+    #[must_use]
+    pub fn client_certificate<C: Into<String>, K: Into<String>>(
+        mut self,
+        cert_path: C,
+        key_path: K,
+    ) -> Self {
+        self.tls.client_cert_path = Some(cert_path.into());
+        self.tls.client_key_path = Some(key_path.into());
+        self
+    }
+
+    /// Add base64-encoded SHA-256 hashes of pinned SubjectPublicKeyInfos (optional)
+    #[must_use]
+    pub fn add_pinned_spki_sha256<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        pins: I,
+    ) -> Self {
+        self.pinned_spki_sha256
+            .get_or_insert_with(Vec::new)
+            .extend(pins.into_iter().map(Into::into));
+        self
+    }
+
+    /// Fail when the root CA can't be loaded instead of falling back to the bundled roots (optional)
+    #[must_use]
+    pub fn strict_tls(mut self, strict: bool) -> Self {
+        self.strict_tls = Some(strict);
+        self
+    }
+
+    /// Set HTTP methods of requests that aren't captured, e.g. `OPTIONS` (optional)
+    #[must_use]
+    pub fn ignored_methods<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        methods: I,
+    ) -> Self {
+        self.ignored_methods = Some(methods.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set status codes of responses that aren't captured, e.g. `404` (optional)
+    #[must_use]
+    pub fn ignored_status_codes<T: Into<StatusCodes>, I: IntoIterator<Item = T>>(
+        mut self,
+        status_codes: I,
+    ) -> Self {
+        self.ignored_status_codes = Some(status_codes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Capture requests slower than this regardless of status code rules (optional)
+    #[must_use]
+    pub fn always_capture_slower_than(mut self, latency: Duration) -> Self {
+        self.always_capture_slower_than_ms =
+            Some(u64::try_from(latency.as_millis()).unwrap_or(u64::MAX));
+        self
+    }
+
+    /// Add a rule restricting the status codes captured for some routes (optional).
+    ///
+    /// Rules are checked in the order they are added, the first matching one applies.
+    #[must_use]
+    pub fn add_capture_rule(mut self, rule: CaptureRule) -> Self {
+        self.capture_rules.get_or_insert_with(Vec::new).push(rule);
+        self
+    }
+
+    /// Add settings replacing the configuration for some routes (optional).
+    ///
+    /// Overrides are checked in the order they are added, the first matching one applies.
+    #[must_use]
+    pub fn add_route_override(mut self, route_override: RouteOverride) -> Self {
+        self.route_overrides.get_or_insert_with(Vec::new).push(route_override);
+        self
+    }
+
+    /// Only capture these headers, matched case-insensitively (optional, defaults to all)
+    #[must_use]
+    pub fn allowed_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.allowed_headers = Some(headers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set headers that aren't captured, matched case-insensitively (optional)
+    #[must_use]
+    pub fn ignored_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.ignored_headers = Some(headers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Add masked headers to the default authorization and cookie headers
+    #[must_use]
+    pub fn add_masked_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.masked_headers
+            .get_or_insert_with(headers::default_masked_headers)
+            .extend(headers.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set masked headers, replacing the defaults
+    #[must_use]
+    pub fn set_masked_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.masked_headers = Some(headers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only mask these cookies in `Cookie` and `Set-Cookie` headers (optional, defaults to all)
+    #[must_use]
+    pub fn masked_cookies<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        cookies: I,
+    ) -> Self {
+        self.masked_cookies = Some(cookies.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the largest request body captured in bytes, defaults to 10MB (optional).
+    ///
+    /// Larger bodies are captured up to the limit and marked as truncated. The application
+    /// still receives the whole body.
+    #[must_use]
+    pub fn max_request_body_size(mut self, bytes: usize) -> Self {
+        self.max_request_body_size = Some(bytes);
+        self
+    }
+
+    /// Set the largest response body captured in bytes, defaults to 10MB (optional)
+    #[must_use]
+    pub fn max_response_body_size(mut self, bytes: usize) -> Self {
+        self.max_response_body_size = Some(bytes);
+        self
+    }
+
+    /// Turn Treblle on or off (optional, defaults to enabled).
+    ///
+    /// While disabled the middlewares pass requests through untouched and no API key is
+    /// required.
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    /// Only enable Treblle in these environments (optional, defaults to all environments)
+    #[must_use]
+    pub fn environments<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        environments: I,
+    ) -> Self {
+        self.environments = Some(environments.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the environment the application runs in (optional, defaults to `APP_ENV`)
+    #[must_use]
+    pub fn environment<T: Into<String>>(mut self, environment: T) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Load values from a TOML, YAML or JSON file, picked by its extension (optional).
+    ///
+    /// File values are used for anything neither the builder nor environment variables set.
+    /// Later files win over earlier ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the file can't be read or parsed.
+    pub fn load_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let layer = ConfigLayer::from_file(path.as_ref())?;
+        self.file = Some(layer.or(self.file.take().unwrap_or_default()));
+        Ok(self)
+    }
+
+    /// Load values from the contents of a configuration file (optional).
+    ///
+    /// The values take the same precedence as those of [`load_file`](Self::load_file).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the contents can't be parsed.
+    pub fn load_str(mut self, contents: &str, format: FileFormat) -> Result<Self> {
+        let layer = ConfigLayer::parse(contents, format)?;
+        self.file = Some(layer.or(self.file.take().unwrap_or_default()));
+        Ok(self)
+    }
+
+    /// Load values from the `TREBLLE_*` environment variables (optional).
+    ///
+    /// Environment values are used for anything the builder doesn't set, and win over files.
+    /// Lists are comma-separated, or JSON arrays when items contain commas. The variables are
+    /// listed in [`constants::env`](crate::constants::env).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if a variable has an invalid value.
+    pub fn load_env(mut self) -> Result<Self> {
+        self.env = Some(ConfigLayer::from_env()?);
+        Ok(self)
+    }
+
+    /// Fill the values the builder doesn't set from the loaded environment and files
+    fn apply_layers(&mut self) -> Result<()> {
+        let env = self.env.take().unwrap_or_default();
+        let layer = env.or(self.file.take().unwrap_or_default());
+
+        self.api_key = self.api_key.take().or(layer.api_key);
+        self.project_id = self.project_id.take().or(layer.project_id);
+        self.api_urls = self.api_urls.take().or(layer.api_urls);
+        self.masked_fields =
+            self.masked_fields.take().or_else(|| layer.masked_fields.map(HashSet::from_iter));
+        self.ignored_routes =
+            self.ignored_routes.take().or_else(|| layer.ignored_routes.map(HashSet::from_iter));
+
+        if let (None, Some(patterns)) = (&self.masked_fields_regex, layer.masked_fields_regex) {
+            *self = std::mem::take(self).set_masked_fields_regex(patterns)?;
+        }
+        if let (None, Some(patterns)) = (&self.ignored_routes_regex, layer.ignored_routes_regex) {
+            *self = std::mem::take(self).set_ignored_routes_regex(patterns)?;
+        }
+
+        let tls = &mut self.tls;
+        tls.root_ca_path = tls.root_ca_path.take().or(layer.root_ca_path);
+        tls.client_cert_path = tls.client_cert_path.take().or(layer.client_cert_path);
+        tls.client_key_path = tls.client_key_path.take().or(layer.client_key_path);
+        self.pinned_spki_sha256 = self.pinned_spki_sha256.take().or(layer.pinned_spki_sha256);
+        self.strict_tls = self.strict_tls.or(layer.strict_tls);
+
+        self.ignored_methods = self.ignored_methods.take().or(layer.ignored_methods);
+        self.ignored_status_codes = self.ignored_status_codes.take().or(layer.ignored_status_codes);
+        self.always_capture_slower_than_ms =
+            self.always_capture_slower_than_ms.or(layer.always_capture_slower_than_ms);
+        self.capture_rules = self.capture_rules.take().or(layer.capture_rules);
+
+        self.allowed_headers = self.allowed_headers.take().or(layer.allowed_headers);
+        self.ignored_headers = self.ignored_headers.take().or(layer.ignored_headers);
+        self.masked_headers = self.masked_headers.take().or(layer.masked_headers);
+        self.masked_cookies = self.masked_cookies.take().or(layer.masked_cookies);
+
+        self.route_overrides = self.route_overrides.take().or(layer.route_overrides);
+        self.max_request_body_size = self.max_request_body_size.or(layer.max_request_body_size);
+        self.max_response_body_size = self.max_response_body_size.or(layer.max_response_body_size);
+
+        self.enabled = self.enabled.or(layer.enabled);
+        self.environments = self.environments.take().or(layer.environments);
+        self.environment = self.environment.take().or(layer.environment);
+
+        Ok(())
+    }
+
+    /// Build the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the API key is missing while Treblle is enabled,
+    /// a route override's sample rate is outside `0.0..=1.0`, a regex loaded from the
+    /// environment or a file is invalid, or the TLS settings are inconsistent.
+    pub fn build(mut self) -> Result<Config> {
+        self.apply_layers()?;
+
+        let key_error = match self.api_key.as_deref() {
+            None => Some("API key is required"),
+            Some("") => Some("API key cannot be empty"),
+            Some(_) => None,
+        };
+
+        let config = Config {
+            api_key: self.api_key.unwrap_or_default(),
+            project_id: self.project_id.unwrap_or_default(),
+            api_urls: self
+                .api_urls
+                .unwrap_or_else(|| API_URLS.iter().map(ToString::to_string).collect()),
+            masked_fields: self
+                .masked_fields
+                .unwrap_or_else(|| DEFAULT_MASKED_FIELDS.iter().map(ToString::to_string).collect()),
+            masked_fields_regex: self
+                .masked_fields_regex
+                .unwrap_or_else(default_masked_fields_regex),
+            ignored_routes: self.ignored_routes.unwrap_or_else(|| {
+                DEFAULT_IGNORED_ROUTES.iter().map(ToString::to_string).collect()
+            }),
+            ignored_routes_regex: self
+                .ignored_routes_regex
+                .unwrap_or_else(default_ignored_routes_regex),
+            tls: TlsConfig {
+                pinned_spki_sha256: self.pinned_spki_sha256.unwrap_or_default(),
+                strict: self.strict_tls.unwrap_or_default(),
+                ..self.tls
+            },
+            capture: CaptureConfig {
+                ignored_methods: self.ignored_methods.unwrap_or_default(),
+                ignored_status_codes: self.ignored_status_codes.unwrap_or_default(),
+                always_capture_slower_than_ms: self.always_capture_slower_than_ms,
+                capture_rules: self.capture_rules.unwrap_or_default(),
+            },
+            headers: HeaderConfig {
+                allowed_headers: self.allowed_headers.unwrap_or_default(),
+                ignored_headers: self.ignored_headers.unwrap_or_default(),
+                masked_headers: self.masked_headers.unwrap_or_else(headers::default_masked_headers),
+                masked_cookies: self.masked_cookies.unwrap_or_default(),
+            },
+            route_overrides: self.route_overrides.unwrap_or_default(),
+            max_request_body_size: self.max_request_body_size.unwrap_or(MAX_BODY_SIZE),
+            max_response_body_size: self.max_response_body_size.unwrap_or(MAX_BODY_SIZE),
+            enabled: self.enabled.unwrap_or(true),
+            environments: self.environments.unwrap_or_default(),
+            environment: self.environment,
+        };
+
+        // Disabled configurations don't need a key, e.g. in local development and CI
+        if let Some(error) = key_error.filter(|_| config.is_enabled()) {
+            return Err(TreblleError::Config(error.into()));
+        }
+
+        if let Some(rate) = config
+            .route_overrides
+            .iter()
+            .filter_map(|o| o.sample_rate)
+            .find(|rate| !(0.0..=1.0).contains(rate))
+        {
+            return Err(TreblleError::Config(format!(
+                "Route override sample rate must be between 0.0 and 1.0, got {rate}"
+            )));
+        }
+
+        config.tls.validate()?;
+        Ok(config)
+    }
+}
+
+/// Configuration for Treblle integrations
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// The Treblle API key (required unless Treblle is disabled)
+    #[serde(default, alias = "api_key")]
+    pub api_key: String,
+
+    /// The Treblle project ID (optional)
+    #[serde(default, alias = "project_id")]
+    pub project_id: String,
+
+    /// The base URLs for the Treblle API (optional, defaults to predefined URLs)
+    #[serde(default = "default_api_urls", alias = "api_urls")]
+    pub api_urls: Vec<String>,
+
+    /// Fields to mask in request/response data (exact matches)
+    #[serde(default = "default_masked_fields", alias = "masked_fields")]
+    pub masked_fields: HashSet<String>,
+
+    /// Regex patterns for fields to mask
+    #[serde(
+        default = "default_masked_fields_regex",
+        alias = "masked_fields_regex",
+        serialize_with = "regex_serde::serialize_patterns",
+        deserialize_with = "regex_serde::deserialize_masked_fields_regex"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+    pub masked_fields_regex: Vec<Regex>,
+
+    /// Routes to ignore (exact matches)
+    #[serde(default = "default_ignored_routes", alias = "ignored_routes")]
+    pub ignored_routes: HashSet<String>,
+
+    /// Regex patterns for routes to ignore
+    #[serde(
+        default = "default_ignored_routes_regex",
+        alias = "ignored_routes_regex",
+        serialize_with = "regex_serde::serialize_patterns",
+        deserialize_with = "regex_serde::deserialize_ignored_routes_regex"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+    pub ignored_routes_regex: Vec<Regex>,
+
+    /// TLS settings for connections to the Treblle API
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+
+    /// Method, status code and latency rules deciding which requests are captured
+    #[serde(flatten)]
+    pub capture: CaptureConfig,
+
+    /// Filters and masking rules for headers
+    #[serde(flatten)]
+    pub headers: HeaderConfig,
+
+    /// Settings replacing these for some routes, the first matching override applies
+    #[serde(default)]
+    pub route_overrides: Vec<RouteOverride>,
+
+    /// Largest request body captured in bytes, larger ones are truncated (optional)
+    #[serde(default = "default_max_body_size")]
+    pub max_request_body_size: usize,
+
+    /// Largest response body captured in bytes, larger ones are truncated (optional)
+    #[serde(default = "default_max_body_size")]
+    pub max_response_body_size: usize,
+
+    /// Whether Treblle is on (optional, defaults to true)
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Environments to enable Treblle in (optional, defaults to all environments)
+    #[serde(default)]
+    pub environments: Vec<String>,
+
+    /// Environment the application runs in (optional, defaults to the `APP_ENV` variable)
+    #[serde(default)]
+    pub environment: Option<String>,
+}
+
+// Default functions for serde
+fn default_api_urls() -> Vec<String> {
+    API_URLS.iter().map(ToString::to_string).collect()
+}
+
+fn default_masked_fields() -> HashSet<String> {
+    DEFAULT_MASKED_FIELDS.iter().map(ToString::to_string).collect()
+}
+
+fn default_ignored_routes() -> HashSet<String> {
+    DEFAULT_IGNORED_ROUTES.iter().map(ToString::to_string).collect()
+}
+
+fn default_max_body_size() -> usize {
+    MAX_BODY_SIZE
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Environment from the `APP_ENV` variable, read once
+fn app_env() -> Option<&'static str> {
+    static APP_ENV_VALUE: OnceLock<Option<String>> = OnceLock::new();
+    APP_ENV_VALUE
+        .get_or_init(|| std::env::var(APP_ENV).ok().filter(|value| !value.trim().is_empty()))
+        .as_deref()
+}
+
+fn default_masked_fields_regex() -> Vec<Regex> {
+    vec![Regex::new(DEFAULT_MASKED_FIELDS_REGEX).expect("Default masked fields regex is invalid")]
+}
+
+fn default_ignored_routes_regex() -> Vec<Regex> {
+    vec![Regex::new(DEFAULT_IGNORED_ROUTES_REGEX).expect("Default ignored routes regex is invalid")]
+}
+
+impl Config {
+    /// Create a new configuration builder
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Load the configuration from the `TREBLLE_*` environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if a variable is invalid or the API key is missing.
+    pub fn from_env() -> Result<Config> {
+        ConfigBuilder::new().load_env()?.build()
+    }
+
+    /// Load the configuration from a TOML, YAML or JSON file, overridden by the `TREBLLE_*`
+    /// environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the file or a variable is invalid, or the API key
+    /// is missing from both.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config> {
+        ConfigBuilder::new().load_file(path)?.load_env()?.build()
+    }
+
+    /// Check if Treblle is enabled and allowed in the current environment.
+    ///
+    /// Middlewares pass requests through untouched when this is false.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+            && (self.environments.is_empty()
+                || self.environment.as_deref().or(app_env()).is_some_and(|current| {
+                    self.environments.iter().any(|allowed| allowed.eq_ignore_ascii_case(current))
+                }))
+    }
+
+    /// Check if a field should be masked
+    pub fn should_mask_field(&self, field: &str) -> bool {
+        self.masked_fields.contains(field)
+            || self.masked_fields_regex.iter().any(|re| re.is_match(field))
+    }
+
+    /// Check if a route should be ignored
+    pub fn should_ignore_route(&self, route: &str) -> bool {
+        self.ignored_routes.contains(route)
+            || self.ignored_routes_regex.iter().any(|re| re.is_match(route))
+    }
+
+    /// Check if a request should be ignored, by its path or the route template it matched.
+    ///
+    /// Matching the template lets one pattern like `/users/:id` cover every user.
+    pub fn should_ignore_request(&self, path: &str, route_path: Option<&str>) -> bool {
+        self.should_ignore_route(path) || route_path.is_some_and(|r| self.should_ignore_route(r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_builder_defaults() {
+        let config = Config::builder().api_key("test_key").build().unwrap();
+
+        assert_eq!(config.api_key, "test_key");
+        assert_eq!(config.project_id, "");
+        assert_eq!(config.api_urls.len(), API_URLS.len());
+        assert!(config.masked_fields.contains("password"));
+        assert!(config.ignored_routes.contains("/health"));
+        assert_eq!(config.max_request_body_size, MAX_BODY_SIZE);
+        assert_eq!(config.max_response_body_size, MAX_BODY_SIZE);
+    }
+
+    #[test]
+    fn test_builder_custom_values() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .set_api_urls(vec!["https://custom.api"])
+            .set_masked_fields(vec!["custom_field"])
+            .set_ignored_routes(vec!["/custom"])
+            .max_request_body_size(1024)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.max_request_body_size, 1024);
+        assert_eq!(config.max_response_body_size, MAX_BODY_SIZE);
+        assert_eq!(config.api_key, "test_key");
+        assert_eq!(config.project_id, "test_project");
+        assert_eq!(config.api_urls, vec!["https://custom.api"]);
+        assert!(config.masked_fields.contains("custom_field"));
+        assert!(!config.masked_fields.contains("password")); // Default should be gone
+        assert!(config.ignored_routes.contains("/custom"));
+        assert!(!config.ignored_routes.contains("/health")); // Default should be gone
+    }
+
+    #[test]
+    fn test_serialization() {
+        let original =
+            Config::builder().api_key("test_key").project_id("test_project").build().unwrap();
+
+        let serialized = serde_json::to_string(&original).unwrap();
+        let deserialized: Config = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized.api_key, "test_key");
+        assert_eq!(deserialized.project_id, "test_project");
+        assert_eq!(deserialized.api_urls, original.api_urls);
+        assert_eq!(deserialized.masked_fields, original.masked_fields);
+    }
+
+    #[test]
+    fn test_ignore_request_by_route_template() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .add_ignored_routes(["/users/:id"])
+            .build()
+            .unwrap();
+
+        assert!(config.should_ignore_request("/users/42", Some("/users/:id")));
+        assert!(!config.should_ignore_request("/users/42", None));
+        assert!(!config.should_ignore_request("/orders/42", Some("/orders/:id")));
+        // The concrete path still matches on its own
+        assert!(config.should_ignore_request("/health", Some("/:page")));
+    }
+
+    #[test]
+    fn test_regex_serialization() {
+        // Deserializing applies the default patterns like the builder does
+        let config: Config = serde_json::from_value(json!({ "apiKey": "test_key" })).unwrap();
+        assert!(config.should_mask_field("auth_token"));
+        assert!(config.should_ignore_route("/health/check"));
+
+        let original = Config::builder()
+            .api_key("test_key")
+            .set_masked_fields_regex(vec!["^x_"])
+            .unwrap()
+            .add_ignored_routes_regex(vec!["^/internal/"])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let value = serde_json::to_value(&original).unwrap();
+        assert_eq!(value["maskedFieldsRegex"], json!(["^x_"]));
+        assert_eq!(value["ignoredRoutesRegex"][1], json!("^/internal/"));
+
+        let deserialized: Config = serde_json::from_str(&value.to_string()).unwrap();
+        assert!(deserialized.should_mask_field("x_secret"));
+        assert!(!deserialized.should_mask_field("auth_token"));
+        assert!(deserialized.should_ignore_route("/internal/jobs"));
+        assert!(deserialized.should_ignore_route("/health/check"));
+
+        let empty: Config = serde_json::from_value(json!({
+            "apiKey": "test_key",
+            "masked_fields_regex": []
+        }))
+        .unwrap();
+        assert!(empty.masked_fields_regex.is_empty());
+
+        let error = serde_json::from_value::<Config>(json!({
+            "apiKey": "test_key",
+            "ignoredRoutesRegex": ["("]
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("Invalid ignored route regex pattern"));
+    }
+
+    #[test]
+    fn test_camel_case_deserialization() {
+        let json = json!({
+            "apiKey": "test_key",
+            "projectId": "test_project",
+            "apiUrls": ["https://custom.api"],
+            "maskedFields": ["custom_field"],
+            "ignoredRoutes": ["/custom"],
+            "maxResponseBodySize": 65536
+        });
+
+        let config: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(config.max_response_body_size, 65536);
+        assert_eq!(config.api_key, "test_key");
+        assert_eq!(config.project_id, "test_project");
+        assert_eq!(config.api_urls, vec!["https://custom.api"]);
+        assert!(config.masked_fields.contains("custom_field"));
+        assert!(config.ignored_routes.contains("/custom"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(Config::builder().build().is_err()); // Missing API key
+        assert!(Config::builder().api_key("").build().is_err()); // Empty API key
+    }
+
+    #[test]
+    fn test_header_options() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .add_masked_headers(["X-Api-Key"])
+            .ignored_headers(["X-Internal"])
+            .build()
+            .unwrap();
+
+        let headers = HashMap::from([
+            ("Authorization".to_string(), "Bearer abc".to_string()),
+            ("X-Api-Key".to_string(), "abc".to_string()),
+            ("X-Internal".to_string(), "1".to_string()),
+            ("X-Password".to_string(), "hunter2".to_string()),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ]);
+        let masked = config.policy_for("/", None).mask_headers(&config, &headers);
+        assert_eq!(masked["Authorization"], "Bearer *****");
+        assert_eq!(masked["X-Api-Key"], "*****");
+        // Masked fields still apply to header names
+        assert_eq!(masked["X-Password"], "*****");
+        assert_eq!(masked["Content-Type"], "application/json");
+        assert!(!masked.contains_key("X-Internal"));
+
+        let config: Config = serde_json::from_value(json!({
+            "apiKey": "test_key",
+            "allowedHeaders": ["cookie", "authorization"],
+            "maskedHeaders": [],
+            "maskedCookies": ["sid"]
+        }))
+        .unwrap();
+        let headers = HashMap::from([
+            ("Cookie".to_string(), "sid=abc; theme=dark".to_string()),
+            ("Authorization".to_string(), "Basic abc".to_string()),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ]);
+        let masked = config.policy_for("/", None).mask_headers(&config, &headers);
+        assert_eq!(masked.len(), 2);
+        assert_eq!(masked["Cookie"], "sid=*****; theme=dark");
+        assert_eq!(masked["Authorization"], "Basic abc");
+    }
+
+    #[test]
+    fn test_capture_filters() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .ignored_methods(["OPTIONS"])
+            .ignored_status_codes([404])
+            .always_capture_slower_than(Duration::from_secs(1))
+            .add_capture_rule(CaptureRule::new("^/catalog/").unwrap().status_codes([500]))
+            .build()
+            .unwrap();
+
+        let capture = &config.capture;
+        assert_eq!(capture.capture_request("OPTIONS", "/users"), CaptureDecision::Skip);
+        assert_eq!(capture.always_capture_slower_than_ms, Some(1000));
+        assert!(!capture.should_capture_response("GET", "/catalog/1", 200, Duration::ZERO));
+
+        // Flattened into the serialized configuration
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["ignoredStatusCodes"], json!([404]));
+        assert_eq!(json["captureRules"][0]["route"], "^/catalog/");
+        let config: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(config.capture.capture_rules.len(), 1);
+
+        // Environment variables apply when the builder doesn't set the filters
+        let layer = ConfigLayer::from_vars(|name| match name {
+            crate::constants::env::IGNORED_STATUS_CODES => Some("5xx".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let mut builder = Config::builder().api_key("test_key");
+        builder.env = Some(layer);
+        let config = builder.build().unwrap();
+        assert_eq!(config.capture.ignored_status_codes, [StatusCodes::Class(5)]);
+    }
+
+    #[test]
+    fn test_enabled_and_environments() {
+        let config = Config::builder().api_key("test_key").build().unwrap();
+        assert!(config.is_enabled());
+
+        // Disabled configurations don't need an API key
+        let config = Config::builder().enabled(false).build().unwrap();
+        assert!(!config.is_enabled());
+        assert_eq!(config.api_key, "");
+
+        let builder = || Config::builder().environments(["production", "staging"]);
+        assert!(builder().api_key("key").environment("Production").build().unwrap().is_enabled());
+        assert!(!builder().api_key("key").environment("local").build().unwrap().is_enabled());
+        assert!(builder().environment("ci").build().is_ok());
+        assert!(builder().environment("staging").build().is_err()); // Missing API key
+
+        let config: Config = serde_json::from_value(json!({
+            "enabled": true,
+            "environments": ["production"],
+            "environment": "development"
+        }))
+        .unwrap();
+        assert!(!config.is_enabled());
+
+        let layer = ConfigLayer::from_vars(|name| match name {
+            crate::constants::env::ENABLED => Some("false".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let mut builder = Config::builder();
+        builder.env = Some(layer);
+        assert!(!builder.build().unwrap().is_enabled());
+    }
+
+    #[test]
+    fn test_tls_options() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .root_ca_path("/etc/treblle/ca.pem")
+            .client_certificate("/etc/treblle/client.pem", "/etc/treblle/client.key")
+            .add_pinned_spki_sha256(["wiJAXrLitmfnWaZ3TdevoR15kozyFmDxdbfnlt+ScyQ="])
+            .strict_tls(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.tls.root_ca_path.as_deref(), Some("/etc/treblle/ca.pem"));
+        assert_eq!(config.tls.client_key_path.as_deref(), Some("/etc/treblle/client.key"));
+        assert_eq!(config.tls.pins().unwrap().len(), 1);
+        assert!(config.tls.strict);
+
+        let config: Config = serde_json::from_value(json!({
+            "apiKey": "test_key",
+            "clientCertPath": "/etc/treblle/client.pem",
+            "clientKeyPath": "/etc/treblle/client.key",
+            "strictTls": true
+        }))
+        .unwrap();
+        assert_eq!(config.tls.client_cert_path.as_deref(), Some("/etc/treblle/client.pem"));
+        assert!(config.tls.strict);
+
+        assert!(Config::builder()
+            .api_key("test_key")
+            .add_pinned_spki_sha256(["not a hash"])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_add_methods() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .add_api_urls(vec!["https://custom.api"])
+            .add_masked_fields(vec!["custom_field"])
+            .add_ignored_routes(vec!["/custom"])
+            .build()
+            .unwrap();
+
+        // Should have both defaults and custom values
+        assert!(config.api_urls.contains(&"https://custom.api".to_string()));
+        assert!(config.api_urls.iter().any(|url| url.contains("rocknrolla.treblle.com")));
+        assert!(config.masked_fields.contains("custom_field"));
+        assert!(config.masked_fields.contains("password"));
+        assert!(config.ignored_routes.contains("/custom"));
+        assert!(config.ignored_routes.contains("/health"));
+    }
+
+    #[test]
+    fn test_multiple_adds() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .add_masked_fields(vec!["field1"])
+            .add_masked_fields(vec!["field2"])
+            .build()
+            .unwrap();
+
+        assert!(config.masked_fields.contains("field1"));
+        assert!(config.masked_fields.contains("field2"));
+        assert!(config.masked_fields.contains("password")); // Default
+    }
+
+    #[test]
+    fn test_empty_values() {
+        let config = Config::builder().api_key("test_key").project_id("").build().unwrap();
+
+        assert_eq!(config.project_id, "");
+        assert!(!config.api_urls.is_empty());
+    }
+
+    #[test]
+    fn test_set_methods() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .set_masked_fields(vec!["custom_field"])
+            .set_masked_fields_regex(vec!["custom_.*"])
+            .unwrap()
+            .set_ignored_routes(vec!["/custom"])
+            .build()
+            .unwrap();
+
+        // Test that set_masked_fields completely replaces defaults
+        assert!(config.should_mask_field("custom_field"));
+        assert!(!config.should_mask_field("password")); // Default should be gone
+        assert!(!config.should_mask_field("credit_card")); // Default should be gone
+
+        // Test that set_ignored_routes completely replaces defaults
+        assert!(config.should_ignore_route("/custom"));
+        assert!(!config.should_ignore_route("/health")); // Default should be gone
+        assert!(!config.should_ignore_route("/metrics")); // Default should be gone
+
+        // Verify the collections directly
+        assert!(config.masked_fields.contains("custom_field"));
+        assert!(!config.masked_fields.contains("password"));
+        assert_eq!(config.masked_fields.len(), 1);
+
+        assert!(config.ignored_routes.contains("/custom"));
+        assert!(!config.ignored_routes.contains("/health"));
+        assert_eq!(config.ignored_routes.len(), 1);
+    }
+
+    #[test]
+    fn test_set_regex_patterns() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .set_masked_fields_regex(vec!["custom_.*"])
+            .unwrap()
+            .set_ignored_routes_regex(vec!["/test/.*"])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // Test that set_masked_fields_regex completely replaces defaults
+        assert!(config.should_mask_field("custom_field"));
+        assert!(!config.should_mask_field("auth_token")); // Default pattern should be gone
+
+        // Test that set_ignored_routes_regex completely replaces defaults
+        assert!(config.should_ignore_route("/test/route"));
+        assert!(!config.should_ignore_route("/internal/test")); // Default pattern should be gone
+
+        // Verify the collections directly
+        assert_eq!(config.masked_fields_regex.len(), 1);
+        assert_eq!(config.ignored_routes_regex.len(), 1);
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let mut builder = Config::builder()
+            .load_str(
+                r#"{"apiKey": "file_key", "projectId": "file_project", "maskedFieldsRegex": ["^x_"]}"#,
+                FileFormat::Json,
+            )
+            .unwrap();
+        builder.env = Some(
+            ConfigLayer::from_vars(|name| {
+                (name == crate::constants::env::PROJECT_ID).then(|| "env_project".to_string())
+            })
+            .unwrap(),
+        );
+
+        let config = builder.project_id("builder_project").build().unwrap();
+        assert_eq!(config.api_key, "file_key");
+        assert_eq!(config.project_id, "builder_project");
+        assert!(config.should_mask_field("x_secret"));
+        assert!(!config.should_mask_field("auth_token"));
+
+        // Without a builder value, the environment wins over the file
+        let mut builder = Config::builder().load_str(r#"{"apiKey": "file_key"}"#, FileFormat::Json);
+        builder.as_mut().unwrap().env =
+            Some(ConfigLayer { api_key: Some("env_key".into()), ..ConfigLayer::default() });
+        assert_eq!(builder.unwrap().build().unwrap().api_key, "env_key");
+    }
+
+    #[test]
+    fn test_builder_can_unset_layer_values() {
+        let file = r#"{
+            "apiKey": "file_key",
+            "strictTls": true,
+            "allowedHeaders": ["Content-Type"],
+            "ignoredMethods": ["OPTIONS"]
+        }"#;
+
+        let config = Config::builder().load_str(file, FileFormat::Json).unwrap().build().unwrap();
+        assert!(config.tls.strict);
+        assert!(!config.headers.should_capture("X-Custom"));
+
+        // `false` and empty lists set on the builder win over the file
+        let config = Config::builder()
+            .load_str(file, FileFormat::Json)
+            .unwrap()
+            .strict_tls(false)
+            .allowed_headers(Vec::<String>::new())
+            .ignored_methods(Vec::<String>::new())
+            .build()
+            .unwrap();
+        assert!(!config.tls.strict);
+        assert!(config.headers.should_capture("X-Custom"));
+        assert!(config.capture.ignored_methods.is_empty());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = std::env::temp_dir().join(format!("treblle-config-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"apiKey": "file_key", "ignoredRoutes": ["/internal"]}"#).unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.api_key, "file_key");
+        assert!(config.should_ignore_route("/internal"));
+        assert!(!config.should_ignore_route("/health"));
+
+        assert!(Config::from_file(&path).is_err());
+
+        let invalid = Config::builder()
+            .load_str(r#"{"apiKey": "key", "maskedFieldsRegex": ["("]}"#, FileFormat::Json)
+            .unwrap()
+            .build();
+        assert!(invalid.is_err());
+    }
+}
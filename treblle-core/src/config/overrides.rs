@@ -0,0 +1,317 @@
+//! Settings overridden for some routes.
+//!
+//! Each [`RouteOverride`] applies to the requests whose path or route template matches its
+//! pattern, like [`ignored_routes_regex`](super::Config::ignored_routes_regex). The first
+//! matching override applies. [`Config::policy_for`] resolves it once per request into a
+//! [`RoutePolicy`], which payload building and the middlewares read from.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{regex_serde, Config};
+use crate::error::{Result, TreblleError};
+use crate::utils::{mask_fields, random_fraction};
+
+/// Settings replacing the configuration for the routes matching a pattern
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct RouteOverride {
+    /// Regex pattern of the request paths or route templates the override applies to
+    #[serde(
+        serialize_with = "regex_serde::serialize_pattern",
+        deserialize_with = "regex_serde::deserialize_route"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub route: Regex,
+
+    /// Fields to mask in addition to the configured ones (exact matches)
+    #[serde(default)]
+    pub masked_fields: Vec<String>,
+
+    /// Regex patterns for fields to mask in addition to the configured ones
+    #[serde(
+        default,
+        serialize_with = "regex_serde::serialize_patterns",
+        deserialize_with = "regex_serde::deserialize_masked_fields_regex"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+    pub masked_fields_regex: Vec<Regex>,
+
+    /// Whether request bodies are captured (optional, defaults to true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_request_body: Option<bool>,
+
+    /// Whether response bodies are captured (optional, defaults to true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_response_body: Option<bool>,
+
+    /// Share of matching requests captured, from 0.0 to 1.0 (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+
+    /// Largest request and response body captured in bytes, larger ones are truncated
+    /// (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<usize>,
+}
+
+/// The settings applying to one request, resolved by [`Config::policy_for`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RoutePolicy<'a> {
+    route_override: Option<&'a RouteOverride>,
+}
+
+impl RouteOverride {
+    /// Create an override for the request paths or route templates matching a regex pattern
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the pattern isn't a valid regex.
+    pub fn new(route: &str) -> Result<Self> {
+        let route = Regex::new(route)
+            .map_err(|e| TreblleError::Config(format!("Invalid route override route: {e}")))?;
+
+        Ok(Self {
+            route,
+            masked_fields: Vec::new(),
+            masked_fields_regex: Vec::new(),
+            capture_request_body: None,
+            capture_response_body: None,
+            sample_rate: None,
+            max_body_size: None,
+        })
+    }
+
+    /// Mask these fields in addition to the configured ones
+    #[must_use]
+    pub fn masked_fields<T: Into<String>, I: IntoIterator<Item = T>>(mut self, fields: I) -> Self {
+        self.masked_fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Mask the fields matching these regex patterns in addition to the configured ones
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if a pattern isn't a valid regex.
+    pub fn masked_fields_regex<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self> {
+        self.masked_fields_regex = patterns
+            .into_iter()
+            .map(|p| {
+                Regex::new(&p.into()).map_err(|e| {
+                    TreblleError::Config(format!("Invalid masked field regex pattern: {e}"))
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    /// Set whether request bodies are captured
+    #[must_use]
+    pub fn capture_request_body(mut self, capture: bool) -> Self {
+        self.capture_request_body = Some(capture);
+        self
+    }
+
+    /// Set whether response bodies are captured
+    #[must_use]
+    pub fn capture_response_body(mut self, capture: bool) -> Self {
+        self.capture_response_body = Some(capture);
+        self
+    }
+
+    /// Set the share of matching requests captured, from 0.0 to 1.0
+    #[must_use]
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// Set the largest body captured, in bytes
+    #[must_use]
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    fn matches(&self, path: &str, route_path: Option<&str>) -> bool {
+        self.route.is_match(path) || route_path.is_some_and(|r| self.route.is_match(r))
+    }
+}
+
+impl<'a> RoutePolicy<'a> {
+    /// The override applying to the request, if any
+    pub fn route_override(&self) -> Option<&'a RouteOverride> {
+        self.route_override
+    }
+
+    /// Check if the request body is captured
+    pub fn capture_request_body(&self) -> bool {
+        self.route_override.and_then(|o| o.capture_request_body).unwrap_or(true)
+    }
+
+    /// Check if the response body is captured
+    pub fn capture_response_body(&self) -> bool {
+        self.route_override.and_then(|o| o.capture_response_body).unwrap_or(true)
+    }
+
+    /// Share of requests captured, if the override sets one
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.route_override.and_then(|o| o.sample_rate)
+    }
+
+    /// Largest request body captured, in bytes
+    pub fn max_request_body_size(&self, config: &Config) -> usize {
+        self.route_override
+            .as_ref()
+            .and_then(|o| o.max_body_size)
+            .unwrap_or(config.max_request_body_size)
+    }
+
+    /// Largest response body captured, in bytes
+    pub fn max_response_body_size(&self, config: &Config) -> usize {
+        self.route_override
+            .as_ref()
+            .and_then(|o| o.max_body_size)
+            .unwrap_or(config.max_response_body_size)
+    }
+
+    /// Decide at random whether the request is sampled, by the override's sample rate
+    pub fn is_sampled(&self) -> bool {
+        self.sample_rate().is_none_or(|rate| random_fraction() < rate)
+    }
+
+    /// Check if a field should be masked, by the configuration or the override
+    pub fn should_mask_field(&self, config: &Config, field: &str) -> bool {
+        config.should_mask_field(field)
+            || self.route_override.is_some_and(|o| {
+                o.masked_fields.iter().any(|f| f == field)
+                    || o.masked_fields_regex.iter().any(|re| re.is_match(field))
+            })
+    }
+
+    /// Mask the sensitive fields of a JSON value
+    pub fn mask(&self, config: &Config, data: &Value) -> Value {
+        mask_fields(data, &|field| self.should_mask_field(config, field))
+    }
+
+    /// Drop the headers that aren't captured and mask the rest, by the header rules and the
+    /// masked fields
+    pub fn mask_headers(
+        &self,
+        config: &Config,
+        headers: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        headers
+            .iter()
+            .filter(|(name, _)| config.headers.should_capture(name))
+            .map(|(name, value)| {
+                let value = config.headers.mask(name, value).unwrap_or_else(|| {
+                    if self.should_mask_field(config, name) {
+                        "*****".to_string()
+                    } else {
+                        value.clone()
+                    }
+                });
+                (name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+impl Config {
+    /// Resolve the settings applying to a request, by its path or the route template it
+    /// matched
+    pub fn policy_for(&self, path: &str, route_path: Option<&str>) -> RoutePolicy<'_> {
+        RoutePolicy {
+            route_override: self.route_overrides.iter().find(|o| o.matches(path, route_path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Config {
+        Config::builder()
+            .api_key("test_key")
+            .add_route_override(
+                RouteOverride::new("^/payments")
+                    .unwrap()
+                    .masked_fields(["iban"])
+                    .capture_response_body(false),
+            )
+            .add_route_override(RouteOverride::new("^/search$").unwrap().sample_rate(0.0))
+            .add_route_override(RouteOverride::new("^/users/:id$").unwrap().max_body_size(16))
+            .max_response_body_size(1024)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_policy_for_route() {
+        let config = config();
+
+        let payments = config.policy_for("/payments/42", None);
+        assert!(payments.capture_request_body());
+        assert!(!payments.capture_response_body());
+        assert!(payments.should_mask_field(&config, "iban"));
+        assert!(payments.should_mask_field(&config, "password"));
+
+        let masked = payments.mask(&config, &json!({ "iban": "DE89", "amount": 10 }));
+        assert_eq!(masked, json!({ "iban": "*****", "amount": 10 }));
+
+        let search = config.policy_for("/search", None);
+        assert!(!search.is_sampled());
+        assert!(search.capture_response_body());
+        assert!(!search.should_mask_field(&config, "iban"));
+
+        // Overrides match route templates too
+        let users = config.policy_for("/users/42", Some("/users/:id"));
+        assert_eq!(users.max_request_body_size(&config), 16);
+        assert_eq!(users.max_response_body_size(&config), 16);
+        assert_eq!(config.policy_for("/users/42", None).max_response_body_size(&config), 1024);
+
+        let default = config.policy_for("/orders", None);
+        assert!(default.route_override().is_none());
+        assert!(default.is_sampled());
+    }
+
+    #[test]
+    fn test_route_override_serialization() {
+        let config: Config = serde_json::from_value(json!({
+            "apiKey": "test_key",
+            "routeOverrides": [
+                {
+                    "route": "^/payments",
+                    "maskedFieldsRegex": ["(?i)^iban$"],
+                    "captureResponseBody": false,
+                    "sampleRate": 0.5,
+                    "maxBodySize": 1024
+                }
+            ]
+        }))
+        .unwrap();
+
+        let policy = config.policy_for("/payments", None);
+        assert!(policy.should_mask_field(&config, "IBAN"));
+        assert_eq!(policy.sample_rate(), Some(0.5));
+        assert_eq!(policy.max_request_body_size(&config), 1024);
+
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["routeOverrides"][0]["route"], json!("^/payments"));
+        assert_eq!(value["routeOverrides"][0]["maskedFieldsRegex"], json!(["(?i)^iban$"]));
+        assert!(value["routeOverrides"][0].get("captureRequestBody").is_none());
+
+        assert!(RouteOverride::new("(").is_err());
+        assert!(serde_json::from_value::<RouteOverride>(json!({ "route": "(" })).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! Regex rules are (de)serialized as their pattern strings.
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+pub(super) fn serialize_pattern<S>(pattern: &Regex, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(pattern.as_str())
+}
+
+pub(super) fn serialize_patterns<S>(patterns: &[Regex], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(patterns.iter().map(Regex::as_str))
+}
+
+pub(super) fn deserialize_route<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse::<D::Error>(&String::deserialize(deserializer)?, "route")
+}
+
+pub(super) fn deserialize_masked_fields_regex<'de, D>(
+    deserializer: D,
+) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_patterns(deserializer, "masked field")
+}
+
+pub(super) fn deserialize_ignored_routes_regex<'de, D>(
+    deserializer: D,
+) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_patterns(deserializer, "ignored route")
+}
+
+fn deserialize_patterns<'de, D>(deserializer: D, kind: &str) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?.iter().map(|pattern| parse(pattern, kind)).collect()
+}
+
+fn parse<E: de::Error>(pattern: &str, kind: &str) -> Result<Regex, E> {
+    Regex::new(pattern).map_err(|e| E::custom(format!("Invalid {kind} regex pattern: {e}")))
+}
@@ -0,0 +1,355 @@
+//! Configuration loaded from files and environment variables.
+//!
+//! Each source is a [`ConfigLayer`] of optional values. [`ConfigBuilder::build`] falls back
+//! to them for anything the builder doesn't set, so values set on the builder win over
+//! environment variables, which win over files.
+//!
+//! [`ConfigBuilder::build`]: super::ConfigBuilder::build
+
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{CaptureRule, RouteOverride, StatusCodes};
+use crate::constants::env;
+use crate::error::{Result, TreblleError};
+
+/// Format of a configuration file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl FileFormat {
+    /// Detect the format of a file from its extension
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the extension isn't a supported format.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Ok(Self::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => Err(TreblleError::Config(format!(
+                "Unsupported configuration file format: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Configuration values from a single source, unset values fall through to the next one
+///
+/// Keys are camelCase like the serialized [`Config`](super::Config), snake_case is accepted
+/// too. Lists replace the defaults, like the builder's `set_*` methods. Other keys are left
+/// to the integrations, which may have options of their own.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConfigLayer {
+    #[serde(alias = "api_key")]
+    pub(crate) api_key: Option<String>,
+    #[serde(alias = "project_id")]
+    pub(crate) project_id: Option<String>,
+    #[serde(alias = "api_urls")]
+    pub(crate) api_urls: Option<Vec<String>>,
+    #[serde(alias = "masked_fields")]
+    pub(crate) masked_fields: Option<Vec<String>>,
+    #[serde(alias = "masked_fields_regex")]
+    pub(crate) masked_fields_regex: Option<Vec<String>>,
+    #[serde(alias = "ignored_routes")]
+    pub(crate) ignored_routes: Option<Vec<String>>,
+    #[serde(alias = "ignored_routes_regex")]
+    pub(crate) ignored_routes_regex: Option<Vec<String>>,
+    #[serde(alias = "root_ca_path")]
+    pub(crate) root_ca_path: Option<String>,
+    #[serde(alias = "client_cert_path")]
+    pub(crate) client_cert_path: Option<String>,
+    #[serde(alias = "client_key_path")]
+    pub(crate) client_key_path: Option<String>,
+    #[serde(alias = "pinned_spki_sha256")]
+    pub(crate) pinned_spki_sha256: Option<Vec<String>>,
+    #[serde(alias = "strict_tls")]
+    pub(crate) strict_tls: Option<bool>,
+    #[serde(alias = "ignored_methods")]
+    pub(crate) ignored_methods: Option<Vec<String>>,
+    #[serde(alias = "ignored_status_codes")]
+    pub(crate) ignored_status_codes: Option<Vec<StatusCodes>>,
+    #[serde(alias = "always_capture_slower_than_ms")]
+    pub(crate) always_capture_slower_than_ms: Option<u64>,
+    #[serde(alias = "capture_rules")]
+    pub(crate) capture_rules: Option<Vec<CaptureRule>>,
+    #[serde(alias = "route_overrides")]
+    pub(crate) route_overrides: Option<Vec<RouteOverride>>,
+    #[serde(alias = "max_request_body_size")]
+    pub(crate) max_request_body_size: Option<usize>,
+    #[serde(alias = "max_response_body_size")]
+    pub(crate) max_response_body_size: Option<usize>,
+    #[serde(alias = "allowed_headers")]
+    pub(crate) allowed_headers: Option<Vec<String>>,
+    #[serde(alias = "ignored_headers")]
+    pub(crate) ignored_headers: Option<Vec<String>>,
+    #[serde(alias = "masked_headers")]
+    pub(crate) masked_headers: Option<Vec<String>>,
+    #[serde(alias = "masked_cookies")]
+    pub(crate) masked_cookies: Option<Vec<String>>,
+    pub(crate) enabled: Option<bool>,
+    pub(crate) environments: Option<Vec<String>>,
+    pub(crate) environment: Option<String>,
+}
+
+impl ConfigLayer {
+    /// Read a layer from a file, in the format given by its extension
+    pub(crate) fn from_file(path: &Path) -> Result<Self> {
+        let format = FileFormat::from_path(path)?;
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| TreblleError::Config(format!("Failed to read {}: {e}", path.display())))?;
+
+        Self::parse(&contents, format)
+    }
+
+    /// Parse a layer from the contents of a configuration file
+    pub(crate) fn parse(contents: &str, format: FileFormat) -> Result<Self> {
+        let layer = match format {
+            FileFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+            #[cfg(feature = "toml")]
+            FileFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+            #[cfg(feature = "yaml")]
+            FileFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        };
+
+        layer.map_err(|e| TreblleError::Config(format!("Invalid {format:?} configuration: {e}")))
+    }
+
+    /// Read a layer from the `TREBLLE_*` environment variables
+    pub(crate) fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Read a layer from variables looked up by name, empty values count as unset
+    pub(crate) fn from_vars<F>(lookup: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+        let list = |name: &str| var(name).map(|value| parse_list(name, &value)).transpose();
+        let size = |name: &str| var(name).map(|value| parse_number(name, &value)).transpose();
+
+        Ok(Self {
+            api_key: var(env::API_KEY),
+            project_id: var(env::PROJECT_ID),
+            api_urls: list(env::API_URLS)?,
+            masked_fields: list(env::MASKED_FIELDS)?,
+            masked_fields_regex: list(env::MASKED_FIELDS_REGEX)?,
+            ignored_routes: list(env::IGNORED_ROUTES)?,
+            ignored_routes_regex: list(env::IGNORED_ROUTES_REGEX)?,
+            root_ca_path: var(env::ROOT_CA_PATH),
+            client_cert_path: var(env::CLIENT_CERT_PATH),
+            client_key_path: var(env::CLIENT_KEY_PATH),
+            pinned_spki_sha256: list(env::PINNED_SPKI_SHA256)?,
+            strict_tls: var(env::STRICT_TLS)
+                .map(|value| parse_bool(env::STRICT_TLS, &value))
+                .transpose()?,
+            ignored_methods: list(env::IGNORED_METHODS)?,
+            ignored_status_codes: list(env::IGNORED_STATUS_CODES)?
+                .map(|codes| codes.iter().map(|code| code.parse()).collect())
+                .transpose()?,
+            always_capture_slower_than_ms: var(env::ALWAYS_CAPTURE_SLOWER_THAN_MS)
+                .map(|value| parse_number(env::ALWAYS_CAPTURE_SLOWER_THAN_MS, &value))
+                .transpose()?,
+            capture_rules: None,
+            route_overrides: None,
+            max_request_body_size: size(env::MAX_REQUEST_BODY_SIZE)?,
+            max_response_body_size: size(env::MAX_RESPONSE_BODY_SIZE)?,
+            allowed_headers: list(env::ALLOWED_HEADERS)?,
+            ignored_headers: list(env::IGNORED_HEADERS)?,
+            masked_headers: list(env::MASKED_HEADERS)?,
+            masked_cookies: list(env::MASKED_COOKIES)?,
+            enabled: var(env::ENABLED).map(|value| parse_bool(env::ENABLED, &value)).transpose()?,
+            environments: list(env::ENVIRONMENTS)?,
+            environment: None,
+        })
+    }
+
+    /// Fill the values this layer doesn't set from a lower one
+    pub(crate) fn or(self, lower: Self) -> Self {
+        Self {
+            api_key: self.api_key.or(lower.api_key),
+            project_id: self.project_id.or(lower.project_id),
+            api_urls: self.api_urls.or(lower.api_urls),
+            masked_fields: self.masked_fields.or(lower.masked_fields),
+            masked_fields_regex: self.masked_fields_regex.or(lower.masked_fields_regex),
+            ignored_routes: self.ignored_routes.or(lower.ignored_routes),
+            ignored_routes_regex: self.ignored_routes_regex.or(lower.ignored_routes_regex),
+            root_ca_path: self.root_ca_path.or(lower.root_ca_path),
+            client_cert_path: self.client_cert_path.or(lower.client_cert_path),
+            client_key_path: self.client_key_path.or(lower.client_key_path),
+            pinned_spki_sha256: self.pinned_spki_sha256.or(lower.pinned_spki_sha256),
+            strict_tls: self.strict_tls.or(lower.strict_tls),
+            ignored_methods: self.ignored_methods.or(lower.ignored_methods),
+            ignored_status_codes: self.ignored_status_codes.or(lower.ignored_status_codes),
+            always_capture_slower_than_ms: self
+                .always_capture_slower_than_ms
+                .or(lower.always_capture_slower_than_ms),
+            capture_rules: self.capture_rules.or(lower.capture_rules),
+            route_overrides: self.route_overrides.or(lower.route_overrides),
+            max_request_body_size: self.max_request_body_size.or(lower.max_request_body_size),
+            max_response_body_size: self.max_response_body_size.or(lower.max_response_body_size),
+            allowed_headers: self.allowed_headers.or(lower.allowed_headers),
+            ignored_headers: self.ignored_headers.or(lower.ignored_headers),
+            masked_headers: self.masked_headers.or(lower.masked_headers),
+            masked_cookies: self.masked_cookies.or(lower.masked_cookies),
+            enabled: self.enabled.or(lower.enabled),
+            environments: self.environments.or(lower.environments),
+            environment: self.environment.or(lower.environment),
+        }
+    }
+}
+
+/// Parse a comma-separated list, or a JSON array when items contain commas
+fn parse_list(name: &str, value: &str) -> Result<Vec<String>> {
+    let value = value.trim();
+    if value.starts_with('[') {
+        return serde_json::from_str(value)
+            .map_err(|e| TreblleError::Config(format!("Invalid {name}: {e}")));
+    }
+
+    Ok(value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect())
+}
+
+fn parse_number<T>(name: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value.trim().parse().map_err(|e| TreblleError::Config(format!("Invalid {name}: {e}")))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(TreblleError::Config(format!("Invalid {name}, expected a boolean: {value}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<ConfigLayer> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        ConfigLayer::from_vars(|name| vars.get(name).map(ToString::to_string))
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let layer = from_vars(&[
+            (env::API_KEY, "env_key"),
+            (env::PROJECT_ID, " "),
+            (env::API_URLS, "https://a.example, https://b.example,"),
+            (env::MASKED_FIELDS_REGEX, r#"["^x{1,3}$", "card"]"#),
+            (env::STRICT_TLS, "Yes"),
+        ])
+        .unwrap();
+
+        assert_eq!(layer.api_key.as_deref(), Some("env_key"));
+        assert!(layer.project_id.is_none());
+        assert_eq!(layer.api_urls.unwrap(), ["https://a.example", "https://b.example"]);
+        assert_eq!(layer.masked_fields_regex.unwrap(), ["^x{1,3}$", "card"]);
+        assert_eq!(layer.strict_tls, Some(true));
+
+        let layer = from_vars(&[
+            (env::IGNORED_METHODS, "OPTIONS, HEAD"),
+            (env::IGNORED_STATUS_CODES, "404, 3xx"),
+            (env::ALWAYS_CAPTURE_SLOWER_THAN_MS, "2000"),
+            (env::MAX_RESPONSE_BODY_SIZE, "65536"),
+            (env::MASKED_COOKIES, "sid, remember_me"),
+        ])
+        .unwrap();
+        assert_eq!(layer.masked_cookies.unwrap(), ["sid", "remember_me"]);
+        assert_eq!(layer.ignored_methods.unwrap(), ["OPTIONS", "HEAD"]);
+        assert_eq!(
+            layer.ignored_status_codes.unwrap(),
+            [StatusCodes::Exact(404), StatusCodes::Class(3)]
+        );
+        assert_eq!(layer.always_capture_slower_than_ms, Some(2000));
+        assert_eq!(layer.max_response_body_size, Some(65536));
+        assert!(layer.max_request_body_size.is_none());
+
+        // Capture rules hold regexes, which can't be compared directly
+        assert_eq!(
+            format!("{:?}", from_vars(&[]).unwrap()),
+            format!("{:?}", ConfigLayer::default())
+        );
+        assert!(from_vars(&[(env::IGNORED_STATUS_CODES, "4x")]).is_err());
+        assert!(from_vars(&[(env::STRICT_TLS, "maybe")]).is_err());
+        assert!(from_vars(&[(env::MAX_REQUEST_BODY_SIZE, "10MB")]).is_err());
+        assert!(from_vars(&[(env::API_URLS, "[not json")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let json = r#"{"apiKey": "file_key", "maskedFields": ["ssn"], "strictTls": true}"#;
+        let layer = ConfigLayer::parse(json, FileFormat::Json).unwrap();
+        assert_eq!(layer.api_key.as_deref(), Some("file_key"));
+        assert_eq!(layer.masked_fields.unwrap(), ["ssn"]);
+        assert_eq!(layer.strict_tls, Some(true));
+
+        #[cfg(feature = "toml")]
+        {
+            let toml = "api_key = \"file_key\"\nignored_routes = [\"/internal\"]\n";
+            let layer = ConfigLayer::parse(toml, FileFormat::Toml).unwrap();
+            assert_eq!(layer.api_key.as_deref(), Some("file_key"));
+            assert_eq!(layer.ignored_routes.unwrap(), ["/internal"]);
+        }
+
+        #[cfg(feature = "yaml")]
+        {
+            let yaml = "apiKey: file_key\napiUrls:\n  - https://a.example\n";
+            let layer = ConfigLayer::parse(yaml, FileFormat::Yaml).unwrap();
+            assert_eq!(layer.api_urls.unwrap(), ["https://a.example"]);
+        }
+    }
+
+    #[test]
+    fn test_file_format_from_path() {
+        assert_eq!(FileFormat::from_path("treblle.json").unwrap(), FileFormat::Json);
+        #[cfg(feature = "toml")]
+        assert_eq!(FileFormat::from_path("conf/Treblle.TOML").unwrap(), FileFormat::Toml);
+        #[cfg(feature = "yaml")]
+        assert_eq!(FileFormat::from_path("treblle.yml").unwrap(), FileFormat::Yaml);
+        assert!(FileFormat::from_path("treblle.ini").is_err());
+        assert!(FileFormat::from_path("treblle").is_err());
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let env = ConfigLayer { api_key: Some("env".into()), ..ConfigLayer::default() };
+        let file = ConfigLayer {
+            api_key: Some("file".into()),
+            project_id: Some("project".into()),
+            ..ConfigLayer::default()
+        };
+
+        let layer = env.or(file);
+        assert_eq!(layer.api_key.as_deref(), Some("env"));
+        assert_eq!(layer.project_id.as_deref(), Some("project"));
+    }
+}
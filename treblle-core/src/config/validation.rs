@@ -0,0 +1,368 @@
+//! Diagnostics for configurations that build but are likely mistakes.
+//!
+//! [`ConfigBuilder::build`](super::ConfigBuilder::build) only rejects configurations that
+//! can't work at all. [`Config::validate`] also reports risky settings, like sending
+//! payloads over plain HTTP or no longer masking passwords, so integrations can log them at
+//! startup.
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+
+use super::Config;
+use crate::constants::defaults::DEFAULT_MASKED_FIELDS;
+
+// Inputs no meaningful pattern matches all of, used to detect catch-all patterns
+const CATCH_ALL_PROBES: [&str; 3] = ["", "\u{0}treblle\u{0}", "/\u{7f}/\u{7f}"];
+
+/// How serious a diagnostic is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The configuration works but is likely a mistake
+    Warning,
+    /// The configuration can't work as intended
+    Error,
+}
+
+/// A problem found in a configuration
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Configuration key the problem is about, as in configuration files, e.g. `apiUrls`
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Diagnostics returned by [`Config::validate`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    /// All diagnostics, in the order they were found
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Diagnostics that keep the configuration from working as intended
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Error)
+    }
+
+    /// Diagnostics about settings that are likely mistakes
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Warning)
+    }
+
+    /// Check if there are no errors, warnings are allowed
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Check if nothing was found
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Add a diagnostic, e.g. from checks of an integration's own limits
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Log each diagnostic through `tracing`
+    #[cfg(feature = "tracing")]
+    pub fn log(&self) {
+        for diagnostic in &self.diagnostics {
+            match diagnostic.severity {
+                Severity::Error => tracing::error!("Treblle configuration {diagnostic}"),
+                Severity::Warning => tracing::warn!("Treblle configuration {diagnostic}"),
+            }
+        }
+    }
+
+    fn warn(&mut self, field: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Warning, field, message });
+    }
+
+    fn error(&mut self, field: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Error, field, message });
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => f.write_str("warning"),
+            Self::Error => f.write_str("error"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {}: {}", self.severity, self.field, self.message)
+    }
+}
+
+impl Config {
+    /// Check the configuration for settings that are invalid or likely mistakes
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        if self.enabled && self.api_key.trim().is_empty() {
+            report.error("apiKey", "API key is required".into());
+        }
+
+        if self.project_id.trim().is_empty() {
+            report.warn("projectId", "Project ID is empty".into());
+        }
+
+        self.validate_api_urls(&mut report);
+        self.validate_masking(&mut report);
+        self.validate_ignored_routes(&mut report);
+        self.validate_route_overrides(&mut report);
+
+        report
+    }
+
+    /// Log the diagnostics of an enabled configuration through `tracing`, e.g. once when
+    /// a middleware is created
+    #[cfg(feature = "tracing")]
+    pub fn log_diagnostics(&self) {
+        if self.is_enabled() {
+            self.validate().log();
+        }
+    }
+
+    fn validate_api_urls(&self, report: &mut ValidationReport) {
+        if self.api_urls.is_empty() {
+            report.error("apiUrls", "No API URLs to send payloads to".into());
+        }
+
+        let mut seen = HashSet::new();
+        for url in &self.api_urls {
+            match url.parse::<http::Uri>().ok().as_ref().and_then(http::Uri::scheme_str) {
+                Some("https") => {}
+                Some("http") => report
+                    .warn("apiUrls", format!("{url} isn't HTTPS, payloads are sent unencrypted")),
+                _ => report.error("apiUrls", format!("{url} isn't an HTTP(S) URL")),
+            }
+
+            if !seen.insert(url.trim_end_matches('/')) {
+                report.warn("apiUrls", format!("{url} is listed more than once"));
+            }
+        }
+    }
+
+    fn validate_masking(&self, report: &mut ValidationReport) {
+        let unmasked: Vec<_> = DEFAULT_MASKED_FIELDS
+            .into_iter()
+            .filter(|field| !self.should_mask_field(field))
+            .collect();
+        if !unmasked.is_empty() {
+            report.warn(
+                "maskedFields",
+                format!("Sensitive fields are no longer masked: {}", unmasked.join(", ")),
+            );
+        }
+
+        for pattern in self.masked_fields_regex.iter().filter(|re| matches_everything(re)) {
+            report.warn(
+                "maskedFieldsRegex",
+                format!("{} matches every field, all values are masked", pattern.as_str()),
+            );
+        }
+    }
+
+    fn validate_ignored_routes(&self, report: &mut ValidationReport) {
+        for pattern in &self.ignored_routes_regex {
+            if matches_everything(pattern) {
+                report.warn(
+                    "ignoredRoutesRegex",
+                    format!("{} matches every route, nothing is sent", pattern.as_str()),
+                );
+            } else if requires_relative_path(pattern.as_str()) {
+                report.warn(
+                    "ignoredRoutesRegex",
+                    format!("{} never matches, request paths start with /", pattern.as_str()),
+                );
+            }
+        }
+
+        let mut routes: Vec<_> = self.ignored_routes.iter().collect();
+        routes.sort();
+        for route in routes {
+            if !route.starts_with('/') {
+                report.warn(
+                    "ignoredRoutes",
+                    format!("{route} never matches, request paths start with /"),
+                );
+            } else if route.contains(['?', '#']) {
+                report.warn(
+                    "ignoredRoutes",
+                    format!("{route} never matches, request paths don't include the query"),
+                );
+            }
+        }
+    }
+
+    fn validate_route_overrides(&self, report: &mut ValidationReport) {
+        for route_override in &self.route_overrides {
+            let route = route_override.route.as_str();
+            if let Some(rate) =
+                route_override.sample_rate.filter(|rate| !(0.0..=1.0).contains(rate))
+            {
+                report.error(
+                    "routeOverrides",
+                    format!("{route} has sample rate {rate}, it must be between 0.0 and 1.0"),
+                );
+            }
+            if requires_relative_path(route) {
+                report.warn(
+                    "routeOverrides",
+                    format!("{route} never matches, request paths start with /"),
+                );
+            }
+        }
+    }
+}
+
+/// Check if a pattern matches any input
+fn matches_everything(pattern: &Regex) -> bool {
+    CATCH_ALL_PROBES.iter().all(|probe| pattern.is_match(probe))
+}
+
+/// Check if a pattern is anchored to a path start other than `/`, e.g. `^health`
+fn requires_relative_path(pattern: &str) -> bool {
+    let pattern = pattern.strip_prefix("(?i)").unwrap_or(pattern);
+
+    pattern
+        .strip_prefix('^')
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(report: &ValidationReport) -> Vec<&str> {
+        report.diagnostics().iter().map(|d| d.field).collect()
+    }
+
+    #[test]
+    fn test_default_config_is_clean() {
+        let config =
+            Config::builder().api_key("test_key").project_id("test_project").build().unwrap();
+        assert!(config.validate().is_empty());
+
+        let config = Config::builder().api_key("test_key").build().unwrap();
+        assert_eq!(fields(&config.validate()), ["projectId"]);
+    }
+
+    #[test]
+    fn test_api_url_diagnostics() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .set_api_urls(vec![
+                "http://localhost:4000",
+                "https://a.example/",
+                "https://a.example",
+                "ftp://b.example",
+            ])
+            .build()
+            .unwrap();
+
+        let report = config.validate();
+        assert!(!report.is_ok());
+        assert_eq!(report.warnings().count(), 2);
+        let error = report.errors().next().unwrap();
+        assert_eq!(error.to_string(), "error in apiUrls: ftp://b.example isn't an HTTP(S) URL");
+    }
+
+    #[test]
+    fn test_masking_diagnostics() {
+        let builder = || {
+            Config::builder()
+                .api_key("test_key")
+                .project_id("test_project")
+                .set_masked_fields(vec!["internal_ref"])
+        };
+
+        // The default regex doesn't cover every default field
+        let report = builder().build().unwrap().validate();
+        assert!(report.is_ok());
+        assert_eq!(fields(&report), ["maskedFields"]);
+        assert!(report.diagnostics()[0].message.contains("pwd"));
+        assert!(!report.diagnostics()[0].message.contains("password"));
+
+        let report = builder().set_masked_fields_regex(vec!["^x_"]).unwrap().build().unwrap();
+        assert!(report.validate().diagnostics()[0].message.contains("password"));
+
+        // A regex replacement covering them is fine
+        let config = builder()
+            .add_masked_fields_regex(vec!["(?i)^(pwd|token|card_\\w+|debit_card|credit_card)$"])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(config.validate().is_empty());
+
+        let config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .add_masked_fields_regex(vec![".*"])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(fields(&config.validate()), ["maskedFieldsRegex"]);
+    }
+
+    #[test]
+    fn test_ignored_route_diagnostics() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .add_ignored_routes(vec!["health", "/status?verbose=1"])
+            .add_ignored_routes_regex(vec!["^internal/", "^(?:/v1)?/jobs/", "^"])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let report = config.validate();
+        assert!(report.is_ok());
+        assert_eq!(
+            fields(&report),
+            ["ignoredRoutesRegex", "ignoredRoutesRegex", "ignoredRoutes", "ignoredRoutes"]
+        );
+        assert!(report.diagnostics()[1].message.contains("matches every route"));
+    }
+
+    #[test]
+    fn test_route_override_diagnostics() {
+        let mut config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .add_route_override(crate::RouteOverride::new("^payments/").unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(fields(&config.validate()), ["routeOverrides"]);
+
+        // Configurations deserialized directly skip the builder's checks
+        config.route_overrides[0].sample_rate = Some(1.5);
+        let report = config.validate();
+        assert!(!report.is_ok());
+        assert!(report.errors().next().unwrap().message.contains("1.5"));
+    }
+
+    #[test]
+    fn test_disabled_config_without_key() {
+        let config = Config::builder().enabled(false).project_id("test_project").build().unwrap();
+        assert!(config.validate().is_empty());
+
+        let mut config = config;
+        config.enabled = true;
+        assert_eq!(config.validate().errors().next().unwrap().field, "apiKey");
+    }
+}
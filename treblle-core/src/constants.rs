@@ -1,5 +1,6 @@
 //! Constant values used across all Treblle integrations.
 
+/// Default limit of the request and response bodies captured, in bytes
 pub const MAX_BODY_SIZE: usize = 10 * 1024 * 1024; // 10MB
 
 // HTTP-related constants
@@ -8,6 +9,51 @@ pub mod http {
 
     pub const HEADER_CONTENT_TYPE: &str = "Content-Type";
     pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+    pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+}
+
+// Names of the SDK's own metrics, see the `metrics` module
+pub mod metrics {
+    pub const PAYLOADS_BUILT: &str = "treblle_payloads_built_total";
+    pub const PAYLOADS_SENT: &str = "treblle_payloads_sent_total";
+    pub const PAYLOADS_FAILED: &str = "treblle_payloads_failed_total";
+    pub const PAYLOADS_DROPPED: &str = "treblle_payloads_dropped_total";
+    pub const PAYLOADS_SAMPLED_OUT: &str = "treblle_payloads_sampled_out_total";
+    pub const PAYLOADS_IGNORED: &str = "treblle_payloads_ignored_total";
+    pub const PAYLOAD_SIZE_BYTES: &str = "treblle_payload_size_bytes";
+    pub const MASKING_DURATION_MS: &str = "treblle_masking_duration_ms";
+    pub const SEND_DURATION_MS: &str = "treblle_send_duration_ms";
+    pub const QUEUE_DEPTH: &str = "treblle_queue_depth";
+    pub const CONFIG_RELOADS: &str = "treblle_config_reloads_total";
+}
+
+// Environment variables read by `ConfigBuilder::load_env`
+pub mod env {
+    pub const API_KEY: &str = "TREBLLE_API_KEY";
+    pub const PROJECT_ID: &str = "TREBLLE_PROJECT_ID";
+    pub const API_URLS: &str = "TREBLLE_API_URLS";
+    pub const MASKED_FIELDS: &str = "TREBLLE_MASKED_FIELDS";
+    pub const MASKED_FIELDS_REGEX: &str = "TREBLLE_MASKED_FIELDS_REGEX";
+    pub const IGNORED_ROUTES: &str = "TREBLLE_IGNORED_ROUTES";
+    pub const IGNORED_ROUTES_REGEX: &str = "TREBLLE_IGNORED_ROUTES_REGEX";
+    pub const ROOT_CA_PATH: &str = "TREBLLE_ROOT_CA_PATH";
+    pub const CLIENT_CERT_PATH: &str = "TREBLLE_CLIENT_CERT_PATH";
+    pub const CLIENT_KEY_PATH: &str = "TREBLLE_CLIENT_KEY_PATH";
+    pub const PINNED_SPKI_SHA256: &str = "TREBLLE_PINNED_SPKI_SHA256";
+    pub const STRICT_TLS: &str = "TREBLLE_STRICT_TLS";
+    pub const IGNORED_METHODS: &str = "TREBLLE_IGNORED_METHODS";
+    pub const IGNORED_STATUS_CODES: &str = "TREBLLE_IGNORED_STATUS_CODES";
+    pub const ALWAYS_CAPTURE_SLOWER_THAN_MS: &str = "TREBLLE_ALWAYS_CAPTURE_SLOWER_THAN_MS";
+    pub const MAX_REQUEST_BODY_SIZE: &str = "TREBLLE_MAX_REQUEST_BODY_SIZE";
+    pub const MAX_RESPONSE_BODY_SIZE: &str = "TREBLLE_MAX_RESPONSE_BODY_SIZE";
+    pub const ALLOWED_HEADERS: &str = "TREBLLE_ALLOWED_HEADERS";
+    pub const IGNORED_HEADERS: &str = "TREBLLE_IGNORED_HEADERS";
+    pub const MASKED_HEADERS: &str = "TREBLLE_MASKED_HEADERS";
+    pub const MASKED_COOKIES: &str = "TREBLLE_MASKED_COOKIES";
+    pub const ENABLED: &str = "TREBLLE_ENABLED";
+    pub const ENVIRONMENTS: &str = "TREBLLE_ENVIRONMENTS";
+    /// Environment the application runs in, checked against the `environments` allow-list
+    pub const APP_ENV: &str = "APP_ENV";
 }
 
 // Default patterns moved to a separate module for clarity
@@ -18,6 +64,10 @@ pub mod defaults {
         "https://sicario.treblle.com",
     ];
 
+    /// Default headers to mask, matched case-insensitively
+    pub const DEFAULT_MASKED_HEADERS: [&str; 4] =
+        ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
     /// Default fields to mask (exact matches)
     pub const DEFAULT_MASKED_FIELDS: [&str; 15] = [
         // Basic security fields
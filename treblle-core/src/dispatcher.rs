@@ -0,0 +1,274 @@
+//! Tracking of detached payload sends for graceful shutdown.
+//!
+//! Integrations send payloads from detached tasks so request handling is never
+//! delayed. The [`Dispatcher`] counts those sends from the moment they are
+//! dispatched, which lets [`Dispatcher::shutdown`] stop accepting new payloads
+//! and wait for everything already queued or in flight.
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use crate::error::{Result, TreblleError};
+use crate::metrics;
+use crate::schema::TrebllePayload;
+use crate::transport::Transport;
+
+/// Bit of the in-flight state marking it closed to new operations
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+/// Counter of pending operations that can be waited on until it drops to zero.
+///
+/// The count and the closed flag share one atomic, so an operation is either
+/// registered before closing or rejected, never registered after it.
+#[derive(Debug, Default)]
+pub(crate) struct InFlight {
+    state: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    /// Register a pending operation, completed when the returned guard is dropped
+    pub(crate) fn start(self: &Arc<Self>) -> InFlightGuard {
+        self.state.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard(Arc::clone(self))
+    }
+
+    /// Register a pending operation unless closed
+    pub(crate) fn try_start(self: &Arc<Self>) -> Option<InFlightGuard> {
+        self.state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state & CLOSED == 0).then_some(state + 1)
+            })
+            .ok()
+            .map(|_| InFlightGuard(Arc::clone(self)))
+    }
+
+    /// Reject operations registered through [`InFlight::try_start`] from now on
+    pub(crate) fn close(&self) {
+        self.state.fetch_or(CLOSED, Ordering::AcqRel);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state.load(Ordering::Acquire) & CLOSED != 0
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.state.load(Ordering::Acquire) & !CLOSED
+    }
+
+    /// Wait until no operations are pending
+    pub(crate) async fn wait_idle(&self) {
+        loop {
+            let mut notified = pin!(self.idle.notified());
+            // Register for notifications before checking, so a completion can't be missed
+            notified.as_mut().enable();
+
+            if self.count() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Marks a pending operation as completed when dropped
+#[derive(Debug)]
+pub(crate) struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.state.fetch_sub(1, Ordering::AcqRel) & !CLOSED == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Queue label of the dispatcher in the queue depth metric
+const QUEUE: &str = "dispatcher";
+
+/// Hands payloads to a [`Transport`] while tracking pending sends
+pub struct Dispatcher {
+    transport: Arc<dyn Transport>,
+    in_flight: Arc<InFlight>,
+}
+
+impl Dispatcher {
+    /// Create a new dispatcher for the given transport
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self { transport, in_flight: Arc::default() }
+    }
+
+    /// The transport payloads are sent through
+    pub fn transport(&self) -> &Arc<dyn Transport> {
+        &self.transport
+    }
+
+    /// Number of payloads dispatched but not yet sent
+    pub fn pending(&self) -> usize {
+        self.in_flight.count()
+    }
+
+    /// Whether the dispatcher has been shut down
+    pub fn is_closed(&self) -> bool {
+        self.in_flight.is_closed()
+    }
+
+    /// Prepare sending a payload.
+    ///
+    /// Returns `None` once the dispatcher is shut down. Otherwise the returned future
+    /// performs the send and should be spawned on the integration's runtime; it counts
+    /// as pending from this call until it completes or is dropped.
+    pub fn dispatch(
+        &self,
+        payload: TrebllePayload,
+    ) -> Option<impl Future<Output = Result<()>> + Send + 'static> {
+        metrics::record_built();
+        let Some(guard) = self.in_flight.try_start() else {
+            metrics::record_dropped("shutdown", 1);
+            return None;
+        };
+        metrics::set_queue_depth(QUEUE, self.in_flight.count());
+        let transport = Arc::clone(&self.transport);
+        let in_flight = Arc::clone(&self.in_flight);
+
+        Some(async move {
+            let result = transport.send(payload).await;
+            match &result {
+                Ok(()) => metrics::record_sent(transport.name()),
+                Err(_) => metrics::record_failed(transport.name()),
+            }
+
+            drop(guard);
+            metrics::set_queue_depth(QUEUE, in_flight.count());
+            result
+        })
+    }
+
+    /// Stop accepting new payloads and wait for pending sends and the transport
+    /// to be flushed, up to the given timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Timeout`] if pending sends don't complete in time,
+    /// or the error returned by the transport flush.
+    pub async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.in_flight.close();
+
+        tokio::time::timeout(timeout, async {
+            self.in_flight.wait_idle().await;
+            self.transport.flush().await
+        })
+        .await
+        .map_err(|_| TreblleError::Timeout)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::PayloadData;
+    use crate::transport::{BoxFuture, MemoryTransport};
+
+    /// Transport delivering to memory after a delay
+    struct SlowTransport {
+        delay: Duration,
+        delivered: MemoryTransport,
+    }
+
+    impl Transport for SlowTransport {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                self.delivered.send(payload).await
+            })
+        }
+    }
+
+    fn test_payload() -> TrebllePayload {
+        TrebllePayload {
+            api_key: "test_key".to_string(),
+            project_id: "test_project".to_string(),
+            version: 0.1,
+            sdk: "treblle-rust".to_string(),
+            data: PayloadData::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_pending_sends() {
+        let delivered = MemoryTransport::new();
+        let dispatcher = Dispatcher::new(Arc::new(SlowTransport {
+            delay: Duration::from_millis(50),
+            delivered: delivered.clone(),
+        }));
+
+        for _ in 0..3 {
+            tokio::spawn(dispatcher.dispatch(test_payload()).unwrap());
+        }
+        assert_eq!(dispatcher.pending(), 3);
+
+        dispatcher.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        assert_eq!(delivered.len(), 3);
+        assert_eq!(dispatcher.pending(), 0);
+        assert!(dispatcher.dispatch(test_payload()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_timeout() {
+        let dispatcher = Dispatcher::new(Arc::new(SlowTransport {
+            delay: Duration::from_secs(10),
+            delivered: MemoryTransport::new(),
+        }));
+
+        tokio::spawn(dispatcher.dispatch(test_payload()).unwrap());
+
+        let result = dispatcher.shutdown(Duration::from_millis(50)).await;
+        assert!(matches!(result, Err(TreblleError::Timeout)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_no_dispatch_after_shutdown_starts() {
+        let dispatcher = Arc::new(Dispatcher::new(Arc::new(MemoryTransport::new())));
+
+        // Every send accepted while shutting down is still waited for
+        let senders: Vec<_> = (0..4)
+            .map(|_| {
+                let dispatcher = Arc::clone(&dispatcher);
+                tokio::spawn(async move {
+                    while let Some(send) = dispatcher.dispatch(test_payload()) {
+                        send.await.unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        dispatcher.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(dispatcher.pending(), 0);
+        for sender in senders {
+            sender.await.unwrap();
+        }
+        assert!(dispatcher.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_send_is_no_longer_pending() {
+        let dispatcher = Dispatcher::new(Arc::new(MemoryTransport::new()));
+
+        let send = dispatcher.dispatch(test_payload()).unwrap();
+        assert_eq!(dispatcher.pending(), 1);
+        drop(send);
+
+        assert_eq!(dispatcher.pending(), 0);
+        dispatcher.shutdown(Duration::from_millis(50)).await.unwrap();
+    }
+}
@@ -59,13 +59,17 @@ pub enum TreblleError {
     #[error("Host function error: {0}")]
     HostFunction(String),
 
+    /// Represents errors related to the on-disk payload spool.
+    #[error("Spool error: {0}")]
+    Spool(String),
+
     /// Represents errors that occur when acquiring a lock.
     #[error("Lock acquisition error: {0}")]
     LockError(String),
 }
 
 /// A `Result` type alias for Treblle operations.
-pub type Result<T> = std::result::Result<T, TreblleError>;
+pub type Result<T, E = TreblleError> = std::result::Result<T, E>;
 
 #[cfg(test)]
 mod tests {
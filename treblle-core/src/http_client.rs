@@ -1,9 +1,15 @@
 use crate::constants::http::REQUEST_TIMEOUT;
+use crate::dispatcher::InFlight;
 use crate::error::{Result as TreblleResult, TreblleError};
+use crate::metrics;
 use crate::schema::TrebllePayload;
+use crate::tls;
+use crate::transport::{BoxFuture, Transport};
 use crate::Config;
 use reqwest::{Client, ClientBuilder};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 // First, implement From<reqwest::Error> for TreblleError
 impl From<reqwest::Error> for TreblleError {
@@ -22,15 +28,25 @@ pub struct TreblleClient {
     client: Client,
     config: Config,
     current_url_index: AtomicUsize,
+    in_flight: Arc<InFlight>,
 }
 
 impl TreblleClient {
     pub fn new(config: Config) -> TreblleResult<Self> {
-        let client = ClientBuilder::new()
-            .timeout(REQUEST_TIMEOUT)
+        let mut builder = ClientBuilder::new().timeout(REQUEST_TIMEOUT);
+        if config.tls.is_customized() {
+            builder = builder.use_preconfigured_tls(tls::http_client::client_config(&config.tls)?);
+        }
+
+        let client = builder
             .build()
             .map_err(|e| TreblleError::Http(format!("Failed to create HTTP client: {e}")))?;
-        Ok(Self { client, config, current_url_index: AtomicUsize::new(0) })
+        Ok(Self {
+            client,
+            config,
+            current_url_index: AtomicUsize::new(0),
+            in_flight: Arc::default(),
+        })
     }
 
     fn get_next_url(&self) -> String {
@@ -40,20 +56,62 @@ impl TreblleClient {
     }
 
     pub async fn send_to_treblle(&self, payload: TrebllePayload) -> TreblleResult<()> {
+        let _in_flight = self.in_flight.start();
         let url = self.get_next_url();
+        let body = serde_json::to_vec(&payload)?;
+        metrics::record_payload_size(&url, body.len());
 
-        // Fire and forget approach - we don't wait for the response
-        let _ = self
+        // Fire and forget approach - we don't read the response body
+        let start = Instant::now();
+        let response = self
             .client
             .post(&url)
-            .json(&payload)
+            .body(body)
             .header("x-api-key", &self.config.api_key)
             .header("Content-Type", "application/json")
             .send()
-            .await?;
+            .await;
+        metrics::record_send_duration(&url, start.elapsed());
+        let response = response?;
+
+        // Server errors are reported so wrapping transports can retry or spool the payload
+        if response.status().is_server_error() {
+            return Err(TreblleError::Http(format!(
+                "Treblle API responded with status {}",
+                response.status()
+            )));
+        }
 
         Ok(())
     }
+
+    /// Wait for requests to the Treblle API that are currently in flight, up to the given timeout
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Timeout`] if the requests don't complete in time.
+    pub async fn flush(&self, timeout: Duration) -> TreblleResult<()> {
+        tokio::time::timeout(timeout, self.in_flight.wait_idle())
+            .await
+            .map_err(|_| TreblleError::Timeout)
+    }
+}
+
+impl Transport for TreblleClient {
+    fn name(&self) -> &'static str {
+        "treblle"
+    }
+
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, TreblleResult<()>> {
+        Box::pin(self.send_to_treblle(payload))
+    }
+
+    fn flush(&self) -> BoxFuture<'_, TreblleResult<()>> {
+        Box::pin(async move {
+            self.in_flight.wait_idle().await;
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
@@ -152,4 +210,73 @@ mod tests {
         let result = client.send_to_treblle(payload).await;
         assert!(matches!(result.unwrap_err(), TreblleError::Timeout));
     }
+
+    #[tokio::test]
+    async fn test_server_error_status() {
+        let mock_server = MockServer::start().await;
+
+        let config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .set_api_urls(vec![mock_server.uri()])
+            .build()
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let client = TreblleClient::new(config.clone()).unwrap();
+
+        let payload = TrebllePayload {
+            api_key: config.api_key,
+            project_id: config.project_id,
+            version: 0.1,
+            sdk: format!("treblle-rust-{}", env!("CARGO_PKG_VERSION")),
+            data: PayloadData::default(),
+        };
+
+        let result = client.send_to_treblle(payload).await;
+        assert!(matches!(result.unwrap_err(), TreblleError::Http(_)));
+    }
+
+    #[tokio::test]
+    async fn test_flush_waits_for_in_flight_requests() {
+        let mock_server = MockServer::start().await;
+
+        let config = Config::builder()
+            .api_key("test_key")
+            .project_id("test_project")
+            .set_api_urls(vec![mock_server.uri()])
+            .build()
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(200)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = Arc::new(TreblleClient::new(config.clone()).unwrap());
+
+        let payload = TrebllePayload {
+            api_key: config.api_key,
+            project_id: config.project_id,
+            version: 0.1,
+            sdk: format!("treblle-rust-{}", env!("CARGO_PKG_VERSION")),
+            data: PayloadData::default(),
+        };
+
+        let sender = Arc::clone(&client);
+        let send = tokio::spawn(async move { sender.send_to_treblle(payload).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(matches!(
+            client.flush(Duration::from_millis(10)).await,
+            Err(TreblleError::Timeout)
+        ));
+        assert!(client.flush(Duration::from_secs(1)).await.is_ok());
+        assert!(send.is_finished());
+    }
 }
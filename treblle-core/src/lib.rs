@@ -3,26 +3,47 @@
 //! This crate provides shared components and utilities for Treblle integrations
 //! across different Rust web frameworks and environments.
 
+pub mod body;
 pub mod config;
 pub mod constants;
 pub mod error;
 pub mod extractors;
+pub mod metrics;
 pub mod payload;
 pub mod schema;
+pub mod tls;
+pub mod transport;
 pub mod utils;
 
+#[cfg(feature = "http_client")]
+pub mod dispatcher;
 #[cfg(feature = "http_client")]
 pub mod http_client;
 
+#[cfg(feature = "http_client")]
+pub use dispatcher::Dispatcher;
 #[cfg(feature = "http_client")]
 pub use http_client::TreblleClient;
 
-pub use config::{Config, ConfigBuilder};
+#[cfg(feature = "otlp")]
+pub use transport::OtlpTransport;
+
+pub use body::{BodyTap, CapturedBody};
+#[cfg(not(target_arch = "wasm32"))]
+pub use config::FileWatcher;
+pub use config::{
+    CaptureConfig, CaptureDecision, CaptureRule, Config, ConfigBuilder, ConfigHandle, Diagnostic,
+    FileFormat, HeaderConfig, RouteOverride, RoutePolicy, Severity, StatusCodes, ValidationReport,
+};
 pub use error::{Result, TreblleError};
 pub use payload::PayloadBuilder;
 pub use schema::{ErrorInfo, LanguageInfo, RequestInfo, ResponseInfo, ServerInfo};
+pub use tls::TlsConfig;
+pub use transport::{
+    FileTransport, MemoryTransport, Spool, SpoolingTransport, StdoutTransport, Transport,
+};
 
-pub use utils::mask_sensitive_data;
+pub use utils::{mask_fields, mask_sensitive_data};
 
 /// The version of the Treblle SDK.
 pub const TREBLLE_SDK_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,207 @@
+//! Self-observability metrics for the SDK.
+//!
+//! With the `metrics` feature enabled, counters, gauges and histograms are
+//! recorded through the [`metrics`](https://docs.rs/metrics) facade, so they end
+//! up in whatever recorder the application installs (e.g. a Prometheus exporter).
+//! Without the feature every function in this module is a no-op.
+//!
+//! | Metric                               | Type      | Labels             |
+//! |--------------------------------------|-----------|--------------------|
+//! | `treblle_payloads_built_total`       | counter   |                    |
+//! | `treblle_payloads_sent_total`        | counter   | `transport`        |
+//! | `treblle_payloads_failed_total`      | counter   | `transport`        |
+//! | `treblle_payloads_dropped_total`     | counter   | `reason`           |
+//! | `treblle_payloads_sampled_out_total` | counter   |                    |
+//! | `treblle_payloads_ignored_total`     | counter   |                    |
+//! | `treblle_payload_size_bytes`         | histogram | `endpoint`         |
+//! | `treblle_masking_duration_ms`        | histogram |                    |
+//! | `treblle_send_duration_ms`           | histogram | `endpoint`         |
+//! | `treblle_queue_depth`                | gauge     | `queue`            |
+//! | `treblle_config_reloads_total`       | counter   | `result`           |
+
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+use crate::constants::metrics::{
+    CONFIG_RELOADS, MASKING_DURATION_MS, PAYLOADS_BUILT, PAYLOADS_DROPPED, PAYLOADS_FAILED,
+    PAYLOADS_IGNORED, PAYLOADS_SAMPLED_OUT, PAYLOADS_SENT, PAYLOAD_SIZE_BYTES, QUEUE_DEPTH,
+    SEND_DURATION_MS,
+};
+
+/// Register descriptions and units for all SDK metrics with the installed recorder.
+///
+/// Call this once after installing the recorder; recording works without it.
+pub fn describe() {
+    #[cfg(feature = "metrics")]
+    {
+        use ::metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+
+        describe_counter!(PAYLOADS_BUILT, "Payloads built and handed over for delivery");
+        describe_counter!(PAYLOADS_SENT, "Payloads delivered by the transport");
+        describe_counter!(PAYLOADS_FAILED, "Payloads the transport failed to deliver");
+        describe_counter!(PAYLOADS_DROPPED, "Payloads discarded without being delivered");
+        describe_counter!(PAYLOADS_SAMPLED_OUT, "Requests skipped by sampling");
+        describe_counter!(PAYLOADS_IGNORED, "Requests skipped by route or content type rules");
+        describe_histogram!(PAYLOAD_SIZE_BYTES, Unit::Bytes, "Size of serialized payloads");
+        describe_histogram!(
+            MASKING_DURATION_MS,
+            Unit::Milliseconds,
+            "Time spent masking sensitive fields"
+        );
+        describe_histogram!(
+            SEND_DURATION_MS,
+            Unit::Milliseconds,
+            "Latency of payload requests to the Treblle API"
+        );
+        describe_gauge!(QUEUE_DEPTH, "Payloads waiting to be delivered");
+        describe_counter!(CONFIG_RELOADS, "Changed configuration files reloaded");
+    }
+}
+
+/// Record a payload built from a request and its response and handed over for delivery
+pub fn record_built() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(PAYLOADS_BUILT).increment(1);
+}
+
+/// Record a payload delivered through the named transport
+pub fn record_sent(transport: &'static str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(PAYLOADS_SENT, "transport" => transport).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = transport;
+}
+
+/// Record a payload the named transport failed to deliver
+pub fn record_failed(transport: &'static str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(PAYLOADS_FAILED, "transport" => transport).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = transport;
+}
+
+/// Record payloads discarded without delivery, e.g. after shutdown or spool eviction
+pub fn record_dropped(reason: &'static str, count: u64) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(PAYLOADS_DROPPED, "reason" => reason).increment(count);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (reason, count);
+}
+
+/// Record a request skipped by sampling
+pub fn record_sampled_out() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(PAYLOADS_SAMPLED_OUT).increment(1);
+}
+
+/// Record a request skipped because of its route or content type
+pub fn record_ignored() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(PAYLOADS_IGNORED).increment(1);
+}
+
+/// Record the size of a serialized payload sent to an endpoint
+pub fn record_payload_size(endpoint: &str, bytes: usize) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(PAYLOAD_SIZE_BYTES, "endpoint" => endpoint.to_string())
+        .record(as_f64(bytes));
+    #[cfg(not(feature = "metrics"))]
+    let _ = (endpoint, bytes);
+}
+
+/// Record the latency of a request to an endpoint
+pub fn record_send_duration(endpoint: &str, duration: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(SEND_DURATION_MS, "endpoint" => endpoint.to_string())
+        .record(duration.as_secs_f64() * 1000.0);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (endpoint, duration);
+}
+
+/// Set the number of payloads waiting in the named queue
+pub fn set_queue_depth(queue: &'static str, depth: usize) {
+    #[cfg(feature = "metrics")]
+    ::metrics::gauge!(QUEUE_DEPTH, "queue" => queue).set(as_f64(depth));
+    #[cfg(not(feature = "metrics"))]
+    let _ = (queue, depth);
+}
+
+/// Record a reload of a changed configuration file (`result` is `"applied"` or `"failed"`)
+pub fn record_config_reload(result: &'static str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(CONFIG_RELOADS, "result" => result).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = result;
+}
+
+/// Convert a count to a metric value, saturating at `u32::MAX`
+#[cfg(feature = "metrics")]
+fn as_f64(value: usize) -> f64 {
+    u32::try_from(value).map_or(f64::from(u32::MAX), f64::from)
+}
+
+/// Run a masking step, recording how long it took.
+///
+/// The clock is only read with the `metrics` feature enabled, so this is safe on
+/// targets without a monotonic clock.
+pub(crate) fn time_masking<T>(mask: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "metrics")]
+    {
+        let start = std::time::Instant::now();
+        let result = mask();
+        ::metrics::histogram!(MASKING_DURATION_MS).record(start.elapsed().as_secs_f64() * 1000.0);
+        result
+    }
+    #[cfg(not(feature = "metrics"))]
+    mask()
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use ::metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use ::metrics_util::MetricKind;
+
+    fn counter_value(
+        snapshot: &[(
+            ::metrics_util::CompositeKey,
+            Option<::metrics::Unit>,
+            Option<::metrics::SharedString>,
+            DebugValue,
+        )],
+        name: &str,
+    ) -> u64 {
+        snapshot
+            .iter()
+            .filter(|(key, ..)| key.kind() == MetricKind::Counter && key.key().name() == name)
+            .map(|(.., value)| match value {
+                DebugValue::Counter(count) => *count,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_records_through_installed_recorder() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            record_built();
+            record_built();
+            record_sent("memory");
+            record_failed("memory");
+            record_dropped("shutdown", 3);
+            record_ignored();
+            assert_eq!(time_masking(|| 42), 42);
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        assert_eq!(counter_value(&snapshot, PAYLOADS_BUILT), 2);
+        assert_eq!(counter_value(&snapshot, PAYLOADS_SENT), 1);
+        assert_eq!(counter_value(&snapshot, PAYLOADS_FAILED), 1);
+        assert_eq!(counter_value(&snapshot, PAYLOADS_DROPPED), 3);
+        assert_eq!(counter_value(&snapshot, PAYLOADS_IGNORED), 1);
+        assert!(snapshot.iter().any(|(key, ..)| key.key().name() == MASKING_DURATION_MS));
+    }
+}
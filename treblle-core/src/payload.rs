@@ -1,11 +1,11 @@
-use crate::utils::{hashmap_to_json_value, json_value_to_hashmap, mask_sensitive_data};
-use crate::Config;
+use crate::metrics;
 use crate::{
     extractors::TreblleExtractor,
     schema::{
         ErrorInfo, LanguageInfo, PayloadData, RequestInfo, ResponseInfo, ServerInfo, TrebllePayload,
     },
 };
+use crate::{Config, RoutePolicy};
 use serde_json::Value;
 use std::time::Duration;
 
@@ -54,20 +54,20 @@ impl PayloadBuilder {
     pub fn build_request_payload<E: TreblleExtractor>(
         req: &E::Request,
         config: &Config,
+        policy: &RoutePolicy,
     ) -> TrebllePayload {
         let mut request_info = E::extract_request_info(req);
 
-        // Convert headers to Value, mask, and convert back
-        let headers_value = hashmap_to_json_value(&request_info.headers);
-        let masked_headers =
-            mask_sensitive_data(&headers_value, &config.masked_fields_regex, &config.masked_fields);
+        // Filter and mask headers
+        request_info.headers =
+            metrics::time_masking(|| policy.mask_headers(config, &request_info.headers));
 
-        request_info.headers = json_value_to_hashmap(masked_headers);
-
-        // Mask body if present
+        // Mask body if present and captured for the route
+        if !policy.capture_request_body() {
+            request_info.body = None;
+        }
         if let Some(body) = request_info.body.as_ref() {
-            request_info.body =
-                Some(mask_sensitive_data(body, &config.masked_fields_regex, &config.masked_fields));
+            request_info.body = Some(metrics::time_masking(|| policy.mask(config, body)));
         }
 
         TrebllePayload {
@@ -91,20 +91,21 @@ impl PayloadBuilder {
     pub fn build_response_payload<E: TreblleExtractor>(
         res: &E::Response,
         config: &Config,
+        policy: &RoutePolicy,
         duration: Duration,
     ) -> TrebllePayload {
         let mut response_info = E::extract_response_info(res, duration);
 
-        // Convert headers to Value, mask, and convert back
-        let headers_value = hashmap_to_json_value(&response_info.headers);
-        let masked_headers =
-            mask_sensitive_data(&headers_value, &config.masked_fields_regex, &config.masked_fields);
-        response_info.headers = json_value_to_hashmap(masked_headers);
+        // Filter and mask headers
+        response_info.headers =
+            metrics::time_masking(|| policy.mask_headers(config, &response_info.headers));
 
-        // Mask body if present
+        // Mask body if present and captured for the route
+        if !policy.capture_response_body() {
+            response_info.body = None;
+        }
         if let Some(body) = response_info.body.as_ref() {
-            response_info.body =
-                Some(mask_sensitive_data(body, &config.masked_fields_regex, &config.masked_fields));
+            response_info.body = Some(metrics::time_masking(|| policy.mask(config, body)));
         }
 
         // Extract and process errors
@@ -200,7 +201,11 @@ mod tests {
         let config =
             Config::builder().api_key("test_key").project_id("test_project").build().unwrap();
 
-        let payload = PayloadBuilder::build_request_payload::<MockExtractor>(&(), &config);
+        let payload = PayloadBuilder::build_request_payload::<MockExtractor>(
+            &(),
+            &config,
+            &RoutePolicy::default(),
+        );
 
         assert_eq!(payload.api_key, "test_key");
         assert_eq!(&payload.data.request.headers["password"], "*****");
@@ -222,6 +227,7 @@ mod tests {
         let payload = PayloadBuilder::build_response_payload::<MockExtractor>(
             &response,
             &config,
+            &RoutePolicy::default(),
             Duration::from_secs(1),
         );
 
@@ -246,6 +252,7 @@ mod tests {
         let payload = PayloadBuilder::build_response_payload::<MockExtractor>(
             &response,
             &config,
+            &RoutePolicy::default(),
             Duration::from_secs(1),
         );
 
@@ -275,6 +282,7 @@ mod tests {
         let payload = PayloadBuilder::build_response_payload::<MockExtractor>(
             &response,
             &config,
+            &RoutePolicy::default(),
             Duration::from_secs(1),
         );
 
@@ -308,6 +316,7 @@ mod tests {
         let payload = PayloadBuilder::build_response_payload::<MockExtractor>(
             &response,
             &config,
+            &RoutePolicy::default(),
             Duration::from_secs(1),
         );
 
@@ -320,4 +329,36 @@ mod tests {
         assert_eq!(response_body["user"]["ssn"], "*****");
         assert_eq!(response_body["user"]["email"], "test@example.com");
     }
+
+    #[test]
+    fn test_build_payloads_with_route_policy() {
+        let config = Config::builder()
+            .api_key("test_key")
+            .add_route_override(
+                crate::RouteOverride::new("^/payments")
+                    .unwrap()
+                    .masked_fields(["email"])
+                    .capture_response_body(false),
+            )
+            .build()
+            .unwrap();
+        let policy = config.policy_for("/payments", None);
+
+        let payload = PayloadBuilder::build_request_payload::<MockExtractor>(&(), &config, &policy);
+        assert_eq!(payload.data.request.body.as_ref().unwrap()["email"], "*****");
+
+        let response = MockResponse {
+            status_code: 500,
+            body: Some(json!({"error": "card declined"})),
+            ..Default::default()
+        };
+        let payload = PayloadBuilder::build_response_payload::<MockExtractor>(
+            &response,
+            &config,
+            &policy,
+            Duration::from_secs(1),
+        );
+        assert!(payload.data.response.body.is_none());
+        assert!(payload.data.errors.is_empty());
+    }
 }
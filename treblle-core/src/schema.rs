@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Represents the main payload sent to Treblle API.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrebllePayload {
     pub api_key: String,
     pub project_id: String,
@@ -13,7 +13,7 @@ pub struct TrebllePayload {
 }
 
 /// Contains the main data of the Treblle payload.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PayloadData {
     pub server: ServerInfo,
     pub language: LanguageInfo,
@@ -46,18 +46,22 @@ pub struct OsInfo {
 }
 
 /// Represents programming language information.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct LanguageInfo {
     pub name: String,
     pub version: String,
 }
 
 /// Represents HTTP request information.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RequestInfo {
     pub timestamp: DateTime<Utc>,
     pub ip: String,
     pub url: String,
+    /// Route template the request matched, e.g. `/users/:id`, which groups requests to the
+    /// same endpoint unlike the concrete `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_path: Option<String>,
     pub user_agent: String,
     pub method: String,
     pub headers: HashMap<String, String>,
@@ -66,7 +70,7 @@ pub struct RequestInfo {
 }
 
 /// Represents HTTP response information.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ResponseInfo {
     pub headers: HashMap<String, String>,
     pub code: u16,
@@ -0,0 +1,249 @@
+//! TLS settings shared by the HTTP clients sending payloads to Treblle.
+//!
+//! Both the reqwest-based [`TreblleClient`](crate::TreblleClient) and the WASM
+//! client read the same options: a custom root CA, a client certificate for
+//! mutual TLS, pinned SPKI hashes and a strict mode that turns an unreadable root
+//! CA into an error instead of falling back to the bundled roots. A client
+//! certificate that can't be loaded is always an error.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Result, TreblleError};
+
+#[cfg(feature = "http_client")]
+pub(crate) mod http_client;
+
+/// SHA-256 hash of a certificate's DER-encoded SubjectPublicKeyInfo
+pub type SpkiHash = [u8; 32];
+
+/// TLS settings for connections to the Treblle API
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    /// PEM file with the root CAs to trust instead of the bundled ones (optional)
+    #[serde(default, alias = "root_ca_path")]
+    pub root_ca_path: Option<String>,
+
+    /// PEM file with the client certificate chain for mutual TLS (optional)
+    #[serde(default)]
+    pub client_cert_path: Option<String>,
+
+    /// PEM file with the private key of the client certificate (optional)
+    #[serde(default)]
+    pub client_key_path: Option<String>,
+
+    /// Base64-encoded SHA-256 hashes of pinned SubjectPublicKeyInfos (optional).
+    /// When set, a certificate in the server's chain has to match one of them.
+    #[serde(default)]
+    pub pinned_spki_sha256: Vec<String>,
+
+    /// Fail when the root CA can't be loaded instead of falling back to the bundled roots (optional)
+    #[serde(default, rename = "strictTls")]
+    pub strict: bool,
+}
+
+impl TlsConfig {
+    /// Whether any option differs from the client's default TLS setup
+    pub fn is_customized(&self) -> bool {
+        self.root_ca_path.is_some()
+            || self.client_cert_path.is_some()
+            || self.client_key_path.is_some()
+            || !self.pinned_spki_sha256.is_empty()
+    }
+
+    /// Validates the TLS settings
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if only one of the client certificate and key is
+    /// set, or if a pinned hash isn't a base64-encoded SHA-256 hash.
+    pub fn validate(&self) -> Result<()> {
+        if self.client_cert_path.is_some() != self.client_key_path.is_some() {
+            return Err(TreblleError::Config(
+                "clientCertPath and clientKeyPath must be set together".into(),
+            ));
+        }
+
+        self.pins().map(|_| ())
+    }
+
+    /// Decodes the pinned SPKI hashes
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if a pin isn't a base64-encoded SHA-256 hash.
+    pub fn pins(&self) -> Result<Vec<SpkiHash>> {
+        self.pinned_spki_sha256
+            .iter()
+            .map(|pin| {
+                BASE64
+                    .decode(pin.trim())
+                    .ok()
+                    .and_then(|hash| SpkiHash::try_from(hash).ok())
+                    .ok_or_else(|| {
+                        TreblleError::Config(format!("Invalid pinned SPKI SHA-256 hash: {pin}"))
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Computes the SHA-256 hash of a DER-encoded certificate's SubjectPublicKeyInfo.
+///
+/// Returns `None` if the certificate can't be parsed.
+pub fn spki_sha256(cert_der: &[u8]) -> Option<SpkiHash> {
+    let (_, certificate, _) = der_element(cert_der)?;
+    let (_, mut tbs_certificate, _) = der_element(certificate)?;
+
+    // The version is an optional, explicitly tagged field
+    let (tag, _, rest) = der_element(tbs_certificate)?;
+    if tag == 0xa0 {
+        tbs_certificate = rest;
+    }
+
+    // Skip the serial number, signature algorithm, issuer, validity and subject
+    for _ in 0..5 {
+        tbs_certificate = der_element(tbs_certificate)?.2;
+    }
+
+    let (_, _, rest) = der_element(tbs_certificate)?;
+    let spki = &tbs_certificate[..tbs_certificate.len() - rest.len()];
+
+    Some(Sha256::digest(spki).into())
+}
+
+/// Checks that a certificate in the server's chain matches one of the pins.
+///
+/// Any chain passes if there are no pins.
+///
+/// # Errors
+///
+/// Returns [`TreblleError::Certificate`] if no certificate matches.
+pub fn verify_pins<'a, I>(chain: I, pins: &[SpkiHash]) -> Result<()>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    if pins.is_empty() || chain.into_iter().filter_map(spki_sha256).any(|hash| pins.contains(&hash))
+    {
+        return Ok(());
+    }
+
+    Err(TreblleError::Certificate(
+        "No certificate in the server chain matches a pinned SPKI hash".into(),
+    ))
+}
+
+/// Reads a DER element, returning its tag, its content and the bytes following it
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, input) = input.split_first()?;
+
+    let (len, input) = if first < 0x80 {
+        (usize::from(first), input)
+    } else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > 4 || input.len() < octets {
+            return None;
+        }
+        let len = input[..octets].iter().fold(0, |len, &b| (len << 8) | usize::from(b));
+        (len, &input[octets..])
+    };
+
+    (input.len() >= len).then(|| (tag, &input[..len], &input[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed P-256 certificate
+    pub(super) const CERT: &str = "\
+MIIBhTCCASugAwIBAgIUXLRVgN9H7wX0AfRIrEgJl2jN/UgwCgYIKoZIzj0EAwIw
+FzEVMBMGA1UEAwwMdHJlYmxsZS10ZXN0MCAXDTI2MTAxODEzMjIyMloYDzIxMjYw
+OTI0MTMyMjIyWjAXMRUwEwYDVQQDDAx0cmVibGxlLXRlc3QwWTATBgcqhkjOPQIB
+BggqhkjOPQMBBwNCAAS9dyEWq4ud80hVPcvzJ3b5LFYVxRSjBgyszWD7X27+sq4h
+1Uj7eY4ngpF3tZpKHkrL9k4kug8ToZ0EttRfgdzTo1MwUTAdBgNVHQ4EFgQUcTBf
+AdkmRLQj2X7oT2Fv9N9jxqEwHwYDVR0jBBgwFoAUcTBfAdkmRLQj2X7oT2Fv9N9j
+xqEwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiAyd8ASpeq5Rk17
+4kC8Ylb1DleFpRB/SSHku9Kk/JCg4gIhALa5bra2Zsq1ZpZo2RmI3cybhScaFJgz
+IQftElVQFSlt";
+
+    /// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`
+    pub(super) const CERT_PIN: &str = "wiJAXrLitmfnWaZ3TdevoR15kozyFmDxdbfnlt+ScyQ=";
+
+    /// Private key of [`CERT`]
+    pub(super) const KEY: &str = "\
+MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQg0EpUut7u9hJozz5m
+MYK+PI2yUsOXW8DK5jV0Ydzfse2hRANCAAS9dyEWq4ud80hVPcvzJ3b5LFYVxRSj
+BgyszWD7X27+sq4h1Uj7eY4ngpF3tZpKHkrL9k4kug8ToZ0EttRfgdzT";
+
+    fn cert_der() -> Vec<u8> {
+        BASE64.decode(CERT.replace('\n', "")).unwrap()
+    }
+
+    #[test]
+    fn test_spki_sha256() {
+        let hash = spki_sha256(&cert_der()).unwrap();
+        assert_eq!(BASE64.encode(hash), CERT_PIN);
+
+        assert!(spki_sha256(b"not a certificate").is_none());
+        assert!(spki_sha256(&cert_der()[..100]).is_none());
+    }
+
+    #[test]
+    fn test_verify_pins() {
+        let cert = cert_der();
+        let config =
+            TlsConfig { pinned_spki_sha256: vec![CERT_PIN.to_string()], ..Default::default() };
+        let pins = config.pins().unwrap();
+
+        assert!(verify_pins([cert.as_slice()], &pins).is_ok());
+        assert!(verify_pins([b"garbage".as_slice(), cert.as_slice()], &pins).is_ok());
+        assert!(verify_pins([cert.as_slice()], &[[0; 32]]).is_err());
+        assert!(verify_pins([], &pins).is_err());
+        assert!(verify_pins([b"garbage".as_slice()], &[]).is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(TlsConfig::default().validate().is_ok());
+        assert!(!TlsConfig::default().is_customized());
+
+        let config =
+            TlsConfig { client_cert_path: Some("client.pem".into()), ..Default::default() };
+        assert!(config.validate().is_err());
+
+        let config = TlsConfig {
+            client_cert_path: Some("client.pem".into()),
+            client_key_path: Some("client.key".into()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert!(config.is_customized());
+
+        let config =
+            TlsConfig { pinned_spki_sha256: vec!["c2hvcnQ=".to_string()], ..Default::default() };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_camel_case_deserialization() {
+        let config: TlsConfig = serde_json::from_value(serde_json::json!({
+            "rootCaPath": "/etc/ca.pem",
+            "clientCertPath": "/etc/client.pem",
+            "clientKeyPath": "/etc/client.key",
+            "pinnedSpkiSha256": [CERT_PIN],
+            "strictTls": true
+        }))
+        .unwrap();
+
+        assert_eq!(config.root_ca_path.as_deref(), Some("/etc/ca.pem"));
+        assert_eq!(config.client_cert_path.as_deref(), Some("/etc/client.pem"));
+        assert_eq!(config.client_key_path.as_deref(), Some("/etc/client.key"));
+        assert_eq!(config.pinned_spki_sha256, [CERT_PIN]);
+        assert!(config.strict);
+    }
+}
@@ -0,0 +1,201 @@
+//! NDJSON file transport with size-based rotation.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Result, TreblleError};
+use crate::schema::TrebllePayload;
+use crate::transport::{redacted_json_line, remove_if_exists, run_blocking, BoxFuture, Transport};
+
+/// Default maximum size of the active file before it is rotated (10MB)
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Default number of rotated files kept next to the active file
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// Transport appending payloads as newline-delimited JSON to a local file.
+///
+/// When the active file would grow past the configured size it is renamed to
+/// `<path>.1`, older files are shifted up (`<path>.1` to `<path>.2`, ...) and
+/// the oldest one is removed. The API key is redacted from every line.
+#[derive(Clone)]
+pub struct FileTransport {
+    path: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    active: Arc<Mutex<Option<ActiveFile>>>,
+}
+
+struct ActiveFile {
+    file: File,
+    size: u64,
+}
+
+impl FileTransport {
+    /// Create a new file transport writing to the given path
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            active: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Set the size in bytes after which the active file is rotated (defaults to 10MB)
+    #[must_use]
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Set how many rotated files are kept (defaults to 5, `0` keeps none)
+    #[must_use]
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Path of the active file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write_line(&self, line: &[u8]) -> Result<()> {
+        let mut guard = self.active.lock().map_err(|e| TreblleError::LockError(e.to_string()))?;
+        let len = line.len() as u64;
+
+        let active = match guard.take() {
+            Some(active) if active.size == 0 || active.size + len <= self.max_file_size => active,
+            Some(active) => {
+                drop(active);
+                self.rotate()?;
+                self.open()?
+            }
+            None => self.open()?,
+        };
+
+        let active = guard.insert(active);
+        active.file.write_all(line)?;
+        active.file.flush()?;
+        active.size += len;
+
+        Ok(())
+    }
+
+    fn open(&self) -> Result<ActiveFile> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let size = file.metadata()?.len();
+
+        Ok(ActiveFile { file, size })
+    }
+
+    fn rotate(&self) -> Result<()> {
+        if self.max_files == 0 {
+            return remove_if_exists(&self.path);
+        }
+
+        remove_if_exists(&self.rotated_path(self.max_files))?;
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+}
+
+impl Transport for FileTransport {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+        let transport = self.clone();
+        run_blocking(move || transport.write_line(&redacted_json_line(payload)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::PayloadData;
+    use serde_json::Value;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("treblle-file-transport-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn test_payload(project_id: &str) -> TrebllePayload {
+        TrebllePayload {
+            api_key: "secret_key".to_string(),
+            project_id: project_id.to_string(),
+            version: 0.1,
+            sdk: "treblle-rust".to_string(),
+            data: PayloadData::default(),
+        }
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_writes_ndjson_lines() {
+        let dir = test_dir("ndjson");
+        let transport = FileTransport::new(dir.join("payloads.ndjson"));
+
+        transport.send(test_payload("first")).await.unwrap();
+        transport.send(test_payload("second")).await.unwrap();
+
+        let lines = read_lines(transport.path());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["project_id"], "first");
+        assert_eq!(lines[1]["project_id"], "second");
+        assert_eq!(lines[0]["api_key"], "*****");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotation() {
+        let dir = test_dir("rotation");
+        let path = dir.join("payloads.ndjson");
+        let transport = FileTransport::new(&path).with_max_file_size(1).with_max_files(2);
+
+        for project_id in ["a", "b", "c", "d"] {
+            transport.send(test_payload(project_id)).await.unwrap();
+        }
+
+        // Every payload exceeds the size limit, so each one ends up in its own file
+        assert_eq!(read_lines(&path)[0]["project_id"], "d");
+        assert_eq!(read_lines(&transport.rotated_path(1))[0]["project_id"], "c");
+        assert_eq!(read_lines(&transport.rotated_path(2))[0]["project_id"], "b");
+        assert!(!transport.rotated_path(3).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
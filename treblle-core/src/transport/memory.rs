@@ -0,0 +1,59 @@
+//! In-memory transport for tests.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::error::Result;
+use crate::schema::TrebllePayload;
+use crate::transport::{BoxFuture, Transport};
+
+/// Transport collecting payloads in memory.
+///
+/// Clones share the same storage, so a clone can be handed to a middleware
+/// while the original is used to inspect what was sent.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    payloads: Arc<Mutex<Vec<TrebllePayload>>>,
+}
+
+impl MemoryTransport {
+    /// Create a new, empty in-memory transport
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a copy of all payloads sent so far
+    pub fn payloads(&self) -> Vec<TrebllePayload> {
+        self.lock().clone()
+    }
+
+    /// Remove and return all payloads sent so far
+    pub fn take(&self) -> Vec<TrebllePayload> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Number of payloads sent so far
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no payloads have been sent yet
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<TrebllePayload>> {
+        // Payloads are only ever pushed, so a poisoned lock still holds valid data
+        self.payloads.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+        self.lock().push(payload);
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
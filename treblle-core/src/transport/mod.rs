@@ -0,0 +1,92 @@
+//! Pluggable transports for delivering Treblle payloads.
+//!
+//! Integrations build and mask payloads through [`crate::PayloadBuilder`] and then hand
+//! them to a [`Transport`]. The default transport is [`crate::TreblleClient`], which
+//! POSTs payloads to the Treblle API, but any destination can be plugged in. Local sinks
+//! ([`FileTransport`], [`StdoutTransport`] and [`MemoryTransport`]) are provided for
+//! air-gapped environments, local debugging and tests, and [`SpoolingTransport`] keeps
+//! payloads on disk while the destination is unreachable.
+
+pub mod file;
+pub mod memory;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod spool;
+pub mod stdout;
+
+pub use file::FileTransport;
+pub use memory::MemoryTransport;
+#[cfg(feature = "otlp")]
+pub use otlp::OtlpTransport;
+pub use spool::{Spool, SpoolingTransport};
+pub use stdout::StdoutTransport;
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+
+use crate::error::Result;
+use crate::schema::TrebllePayload;
+
+/// A boxed future that can be sent across threads, as returned by [`Transport`] methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A destination for Treblle payloads.
+///
+/// Payloads handed to a transport have already gone through the masking pipeline,
+/// so implementations must not need access to the raw request or response data.
+pub trait Transport: Send + Sync {
+    /// Short, static name of the transport used in logs
+    fn name(&self) -> &'static str;
+
+    /// Deliver a single payload
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>>;
+
+    /// Wait until payloads handed to [`Transport::send`] are fully delivered.
+    /// Transports that deliver before `send` resolves don't need to override this.
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
+
+/// Serialize a payload as a single NDJSON line with the API key redacted
+fn redacted_json_line(mut payload: TrebllePayload) -> Result<Vec<u8>> {
+    if !payload.api_key.is_empty() {
+        payload.api_key = "*****".to_string();
+    }
+
+    let mut line = serde_json::to_vec(&payload)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Run blocking file I/O off the async executor.
+///
+/// Native builds move the work to tokio's blocking pool when called from inside a runtime;
+/// otherwise, as in the WASM build, it runs inline when the future is polled.
+fn run_blocking<T, F>(f: F) -> BoxFuture<'static, Result<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    #[cfg(all(feature = "http_client", not(target_arch = "wasm32")))]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Box::pin(async move {
+            tokio::task::spawn_blocking(f)
+                .await
+                .map_err(|e| io::Error::other(e).into())
+                .and_then(|r| r)
+        });
+    }
+
+    Box::pin(async move { f() })
+}
+
+/// Remove a file, ignoring it if it doesn't exist
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
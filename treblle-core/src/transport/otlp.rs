@@ -0,0 +1,447 @@
+//! OpenTelemetry (OTLP/HTTP) exporter backend.
+//!
+//! Every Treblle payload is exported as a single `SERVER` span using the OTLP/HTTP JSON
+//! encoding. Request and response data are mapped to the HTTP semantic-convention
+//! attributes, and each [`ErrorInfo`] becomes an `exception` span event.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client, ClientBuilder};
+use serde_json::{json, Value};
+
+use crate::constants::http::REQUEST_TIMEOUT;
+use crate::error::{Result, TreblleError};
+use crate::schema::{ErrorInfo, TrebllePayload};
+use crate::transport::{BoxFuture, Transport};
+use crate::utils::random_u64;
+use crate::TREBLLE_SDK_VERSION;
+
+/// Path of the OTLP/HTTP traces endpoint on a collector
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Default `service.name` resource attribute
+const DEFAULT_SERVICE_NAME: &str = "treblle";
+
+/// `SPAN_KIND_SERVER` in the OTLP protocol
+const SPAN_KIND_SERVER: u8 = 2;
+
+/// `STATUS_CODE_ERROR` in the OTLP protocol
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// Transport exporting Treblle payloads as spans to an OpenTelemetry collector
+pub struct OtlpTransport {
+    client: Client,
+    traces_url: String,
+    service_name: String,
+    headers: Vec<(String, String)>,
+}
+
+impl OtlpTransport {
+    /// Create a new OTLP transport for the given collector endpoint,
+    /// e.g. `http://otel-collector:4318`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the endpoint is empty or the HTTP client can't be created.
+    pub fn new<T: Into<String>>(endpoint: T) -> Result<Self> {
+        let endpoint = endpoint.into();
+        let endpoint = endpoint.trim_end_matches('/');
+        if endpoint.is_empty() {
+            return Err(TreblleError::InvalidUrl("OTLP endpoint cannot be empty".into()));
+        }
+
+        let traces_url = if endpoint.ends_with(TRACES_PATH) {
+            endpoint.to_string()
+        } else {
+            format!("{endpoint}{TRACES_PATH}")
+        };
+
+        let client = ClientBuilder::new()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| TreblleError::Http(format!("Failed to create HTTP client: {e}")))?;
+
+        Ok(Self {
+            client,
+            traces_url,
+            service_name: DEFAULT_SERVICE_NAME.to_string(),
+            headers: Vec::new(),
+        })
+    }
+
+    /// Set the `service.name` resource attribute (defaults to `treblle`)
+    #[must_use]
+    pub fn with_service_name<T: Into<String>>(mut self, service_name: T) -> Self {
+        self.service_name = service_name.into();
+        self
+    }
+
+    /// Add a header sent with every export request, e.g. for collector authentication
+    #[must_use]
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Build the `ExportTraceServiceRequest` body for a payload
+    fn export_request(&self, payload: &TrebllePayload) -> Value {
+        let mut resource_attributes = vec![
+            string_attr("service.name", &self.service_name),
+            string_attr("telemetry.sdk.name", "treblle-rust"),
+            string_attr("telemetry.sdk.language", "rust"),
+            string_attr("telemetry.sdk.version", TREBLLE_SDK_VERSION),
+        ];
+        if !payload.project_id.is_empty() {
+            resource_attributes.push(string_attr("treblle.project_id", &payload.project_id));
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": { "attributes": resource_attributes },
+                "scopeSpans": [{
+                    "scope": { "name": "treblle-core", "version": TREBLLE_SDK_VERSION },
+                    "spans": [span_from_payload(payload)]
+                }]
+            }]
+        })
+    }
+}
+
+impl Transport for OtlpTransport {
+    fn name(&self) -> &'static str {
+        "otlp"
+    }
+
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let body = self.export_request(&payload);
+
+            let mut request = self.client.post(&self.traces_url).json(&body);
+            for (name, value) in &self.headers {
+                request = request.header(name, value);
+            }
+
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(TreblleError::Http(format!(
+                    "OTLP collector responded with status {}",
+                    response.status()
+                )));
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Map a Treblle payload to an OTLP span
+fn span_from_payload(payload: &TrebllePayload) -> Value {
+    let request = &payload.data.request;
+    let response = &payload.data.response;
+    let server = &payload.data.server;
+
+    let mut attributes = Vec::new();
+
+    if !request.method.is_empty() {
+        attributes.push(string_attr("http.request.method", &request.method));
+    }
+
+    if !request.url.is_empty() {
+        attributes.push(string_attr("url.full", &request.url));
+
+        if let Ok(uri) = request.url.parse::<http::Uri>() {
+            attributes.push(string_attr("url.path", uri.path()));
+            if let Some(query) = uri.query() {
+                attributes.push(string_attr("url.query", query));
+            }
+            if let Some(scheme) = uri.scheme_str() {
+                attributes.push(string_attr("url.scheme", scheme));
+            }
+            if let Some(host) = uri.host().filter(|h| !h.is_empty()) {
+                attributes.push(string_attr("server.address", host));
+            }
+            if let Some(port) = uri.port_u16() {
+                attributes.push(int_attr("server.port", i64::from(port)));
+            }
+        }
+    }
+
+    if let Some(route) = &request.route_path {
+        attributes.push(string_attr("http.route", route));
+    }
+
+    if !request.ip.is_empty() && request.ip != "unknown" {
+        attributes.push(string_attr("client.address", &request.ip));
+    }
+
+    if !request.user_agent.is_empty() {
+        attributes.push(string_attr("user_agent.original", &request.user_agent));
+    }
+
+    if let Some(version) = server.protocol.strip_prefix("HTTP/") {
+        attributes.push(string_attr("network.protocol.name", "http"));
+        attributes.push(string_attr("network.protocol.version", version));
+    }
+
+    attributes.extend(header_attrs("http.request.header", &request.headers));
+    if let Some(body) = &request.body {
+        attributes.push(string_attr("treblle.request.body", &body.to_string()));
+    }
+
+    if response.code != 0 {
+        attributes.push(int_attr("http.response.status_code", i64::from(response.code)));
+        attributes.push(int_attr(
+            "http.response.body.size",
+            i64::try_from(response.size).unwrap_or(i64::MAX),
+        ));
+    }
+
+    attributes.extend(header_attrs("http.response.header", &response.headers));
+    if let Some(body) = &response.body {
+        attributes.push(string_attr("treblle.response.body", &body.to_string()));
+    }
+
+    let (start, end) = span_times(payload);
+
+    // Span names use the low-cardinality route template when it is known
+    let name = match (request.method.as_str(), &request.route_path) {
+        ("", _) => "HTTP".to_string(),
+        (method, Some(route)) => format!("{method} {route}"),
+        (method, None) => method.to_string(),
+    };
+
+    let mut span = json!({
+        "traceId": format!("{:016x}{:016x}", random_u64(), random_u64()),
+        "spanId": format!("{:016x}", random_u64()),
+        "name": name,
+        "kind": SPAN_KIND_SERVER,
+        "startTimeUnixNano": unix_nanos(start).to_string(),
+        "endTimeUnixNano": unix_nanos(end).to_string(),
+        "attributes": attributes,
+        "events": payload.data.errors.iter().map(|e| error_event(e, end)).collect::<Vec<_>>(),
+    });
+
+    // Server spans only report an error status for 5xx responses
+    if response.code >= 500 {
+        span["status"] = json!({
+            "code": STATUS_CODE_ERROR,
+            "message": payload.data.errors.first().map(|e| e.message.as_str()).unwrap_or_default(),
+        });
+    }
+
+    span
+}
+
+/// Compute span start and end times from the request timestamp and load time
+fn span_times(payload: &TrebllePayload) -> (DateTime<Utc>, DateTime<Utc>) {
+    let load_time = chrono::Duration::from_std(std::time::Duration::from_secs_f64(
+        payload.data.response.load_time.max(0.0),
+    ))
+    .unwrap_or_else(|_| chrono::Duration::zero());
+
+    let timestamp = payload.data.request.timestamp;
+    if timestamp.timestamp() > 0 {
+        (timestamp, timestamp + load_time)
+    } else {
+        // Response-only payloads don't carry the request timestamp
+        let end = Utc::now();
+        (end - load_time, end)
+    }
+}
+
+/// Map an error to an `exception` span event
+fn error_event(error: &ErrorInfo, time: DateTime<Utc>) -> Value {
+    let mut attributes = vec![
+        string_attr("exception.type", &error.error_type),
+        string_attr("exception.message", &error.message),
+        string_attr("treblle.error.source", &error.source),
+    ];
+    if !error.file.is_empty() {
+        attributes.push(string_attr("code.filepath", &error.file));
+        attributes.push(int_attr("code.lineno", i64::from(error.line)));
+    }
+
+    json!({
+        "timeUnixNano": unix_nanos(time).to_string(),
+        "name": "exception",
+        "attributes": attributes,
+    })
+}
+
+/// Map headers to `<prefix>.<lowercased name>` string array attributes
+fn header_attrs(prefix: &str, headers: &HashMap<String, String>) -> Vec<Value> {
+    let mut names: Vec<_> = headers.keys().collect();
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            json!({
+                "key": format!("{prefix}.{}", name.to_lowercase()),
+                "value": { "arrayValue": { "values": [{ "stringValue": headers[name] }] } },
+            })
+        })
+        .collect()
+}
+
+fn string_attr(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int_attr(key: &str, value: i64) -> Value {
+    // OTLP/JSON encodes 64-bit integers as strings
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+fn unix_nanos(time: DateTime<Utc>) -> u64 {
+    time.timestamp_nanos_opt().and_then(|n| u64::try_from(n).ok()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{PayloadData, RequestInfo, ResponseInfo};
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_payload() -> TrebllePayload {
+        TrebllePayload {
+            api_key: "test_key".to_string(),
+            project_id: "test_project".to_string(),
+            version: 0.1,
+            sdk: String::new(),
+            data: PayloadData {
+                request: RequestInfo {
+                    timestamp: Utc::now(),
+                    ip: "203.0.113.195".to_string(),
+                    url: "https://api.example.com:8443/users?page=2".to_string(),
+                    route_path: None,
+                    user_agent: "test-agent".to_string(),
+                    method: "POST".to_string(),
+                    headers: HashMap::from([("X-Request-Id".to_string(), "abc".to_string())]),
+                    body: Some(json!({"password": "*****"})),
+                },
+                response: ResponseInfo {
+                    code: 500,
+                    size: 42,
+                    load_time: 0.25,
+                    ..Default::default()
+                },
+                errors: vec![ErrorInfo {
+                    source: "axum".to_string(),
+                    error_type: "HTTP_500".to_string(),
+                    message: "Internal error".to_string(),
+                    file: String::new(),
+                    line: 0,
+                }],
+                ..Default::default()
+            },
+        }
+    }
+
+    fn attribute<'a>(span: &'a Value, key: &str) -> Option<&'a Value> {
+        span["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|attr| attr["key"] == key)
+            .map(|attr| &attr["value"])
+    }
+
+    #[test]
+    fn test_span_attributes() {
+        let span = span_from_payload(&test_payload());
+
+        assert_eq!(span["name"], "POST");
+        assert_eq!(span["kind"], SPAN_KIND_SERVER);
+        assert_eq!(attribute(&span, "http.request.method").unwrap()["stringValue"], "POST");
+        assert_eq!(attribute(&span, "url.path").unwrap()["stringValue"], "/users");
+        assert_eq!(attribute(&span, "url.query").unwrap()["stringValue"], "page=2");
+        assert_eq!(attribute(&span, "server.port").unwrap()["intValue"], "8443");
+        assert_eq!(attribute(&span, "client.address").unwrap()["stringValue"], "203.0.113.195");
+        assert_eq!(attribute(&span, "http.response.status_code").unwrap()["intValue"], "500");
+        assert_eq!(
+            attribute(&span, "http.request.header.x-request-id").unwrap()["arrayValue"]["values"]
+                [0]["stringValue"],
+            "abc"
+        );
+        assert!(attribute(&span, "treblle.request.body").unwrap()["stringValue"]
+            .as_str()
+            .unwrap()
+            .contains("*****"));
+        assert!(attribute(&span, "http.route").is_none());
+
+        let mut payload = test_payload();
+        payload.data.request.route_path = Some("/users/:id".to_string());
+        let span = span_from_payload(&payload);
+        assert_eq!(span["name"], "POST /users/:id");
+        assert_eq!(attribute(&span, "http.route").unwrap()["stringValue"], "/users/:id");
+    }
+
+    #[test]
+    fn test_span_timing_and_errors() {
+        let span = span_from_payload(&test_payload());
+
+        let start: u64 = span["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        let end: u64 = span["endTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        assert_eq!(end - start, 250_000_000);
+
+        assert_eq!(span["status"]["code"], STATUS_CODE_ERROR);
+        assert_eq!(span["events"][0]["name"], "exception");
+        assert_eq!(span["events"][0]["attributes"][0]["value"]["stringValue"], "HTTP_500");
+    }
+
+    #[test]
+    fn test_api_key_is_not_exported() {
+        let transport = OtlpTransport::new("http://localhost:4318").unwrap();
+        let body = transport.export_request(&test_payload()).to_string();
+
+        assert!(!body.contains("test_key"));
+        assert!(body.contains("test_project"));
+    }
+
+    #[test]
+    fn test_traces_url() {
+        let transport = OtlpTransport::new("http://localhost:4318/").unwrap();
+        assert_eq!(transport.traces_url, "http://localhost:4318/v1/traces");
+
+        let transport = OtlpTransport::new("http://localhost:4318/v1/traces").unwrap();
+        assert_eq!(transport.traces_url, "http://localhost:4318/v1/traces");
+
+        assert!(OtlpTransport::new("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_to_collector() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path(TRACES_PATH))
+            .and(header("authorization", "Bearer collector"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let transport = OtlpTransport::new(mock_server.uri())
+            .unwrap()
+            .with_service_name("orders")
+            .with_header("authorization", "Bearer collector");
+
+        assert!(transport.send(test_payload()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_collector_error_status() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let transport = OtlpTransport::new(mock_server.uri()).unwrap();
+        assert!(matches!(transport.send(test_payload()).await, Err(TreblleError::Http(_))));
+    }
+}
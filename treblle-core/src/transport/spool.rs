@@ -0,0 +1,746 @@
+//! Disk-backed spool for payloads that could not be delivered.
+//!
+//! Every spooled payload is stored in its own file named after a monotonically
+//! increasing sequence number. Files are written to a temporary name, synced and
+//! then renamed, so a crash never leaves a half-written entry behind and a restart
+//! resumes from the entries already on disk.
+
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::{Result, TreblleError};
+use crate::metrics;
+use crate::schema::TrebllePayload;
+use crate::transport::{remove_if_exists, run_blocking, BoxFuture, Transport};
+
+/// Extension of committed spool entries
+const ENTRY_EXTENSION: &str = "json";
+
+/// Extension of entries that are still being written
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Queue label of the spool in the queue depth metric
+const QUEUE: &str = "spool";
+
+/// Default maximum size of all spooled payloads (100MB)
+const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Default maximum age of a spooled payload (24 hours)
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Default number of retries before a payload is spooled
+const DEFAULT_MAX_RETRIES: usize = 2;
+
+/// Default delay before the first retry, doubled on every further retry
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Upper bound of the delay between retries
+#[cfg(all(feature = "http_client", not(target_arch = "wasm32")))]
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Default number of consecutive failures that open the circuit
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Default time the circuit stays open before delivery is attempted again
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Crash-safe, FIFO on-disk store of payloads.
+///
+/// Spooled payloads keep their API key so they can be replayed later,
+/// so the spool directory should only be readable by the application.
+pub struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    state: Mutex<SpoolState>,
+}
+
+struct SpoolState {
+    entries: VecDeque<SpoolEntry>,
+    total_bytes: u64,
+    next_seq: u64,
+}
+
+#[derive(Clone, Copy)]
+struct SpoolEntry {
+    seq: u64,
+    size: u64,
+    created: SystemTime,
+}
+
+impl Spool {
+    /// Open the spool in the given directory, creating it if needed.
+    ///
+    /// Entries left behind by a previous run are picked up in order, and
+    /// partially written entries from an interrupted write are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be created or read.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            match path.extension().and_then(OsStr::to_str) {
+                Some(TEMP_EXTENSION) => remove_if_exists(&path)?,
+                Some(ENTRY_EXTENSION) => {
+                    let Some(seq) =
+                        path.file_stem().and_then(OsStr::to_str).and_then(|s| s.parse().ok())
+                    else {
+                        continue;
+                    };
+                    let metadata = entry.metadata()?;
+                    entries.push(SpoolEntry {
+                        seq,
+                        size: metadata.len(),
+                        created: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+                    });
+                }
+                _ => {}
+            }
+        }
+        entries.sort_by_key(|entry| entry.seq);
+
+        let state = SpoolState {
+            total_bytes: entries.iter().map(|entry| entry.size).sum(),
+            next_seq: entries.last().map_or(0, |entry| entry.seq + 1),
+            entries: entries.into(),
+        };
+
+        Ok(Self {
+            dir,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_age: DEFAULT_MAX_AGE,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Set the maximum size in bytes of all spooled payloads (defaults to 100MB).
+    /// The oldest payloads are evicted to make room for new ones.
+    #[must_use]
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Set the maximum age of a spooled payload (defaults to 24 hours).
+    /// Older payloads are evicted instead of being replayed.
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Directory the spool is stored in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Append a payload to the end of the spool
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is larger than the spool size limit or
+    /// can't be written to disk.
+    pub fn push(&self, payload: &TrebllePayload) -> Result<()> {
+        let bytes = serde_json::to_vec(payload)?;
+        let size = bytes.len() as u64;
+        if size > self.max_bytes {
+            return Err(TreblleError::Spool(format!(
+                "Payload of {size} bytes exceeds the spool size limit of {} bytes",
+                self.max_bytes
+            )));
+        }
+
+        let mut state = self.lock()?;
+        self.evict_expired(&mut state)?;
+
+        while state.total_bytes + size > self.max_bytes {
+            let Some(oldest) = state.entries.pop_front() else { break };
+            remove_if_exists(&self.entry_path(oldest.seq, ENTRY_EXTENSION))?;
+            state.total_bytes -= oldest.size;
+            metrics::record_dropped("spool_full", 1);
+        }
+
+        let seq = state.next_seq;
+        let temp_path = self.entry_path(seq, TEMP_EXTENSION);
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, self.entry_path(seq, ENTRY_EXTENSION))?;
+
+        // Persist the rename itself; not supported on every platform
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        state.next_seq += 1;
+        state.total_bytes += size;
+        state.entries.push_back(SpoolEntry { seq, size, created: SystemTime::now() });
+        metrics::set_queue_depth(QUEUE, state.entries.len());
+
+        Ok(())
+    }
+
+    /// Get the oldest spooled payload along with its sequence number, without removing it.
+    /// Entries that can no longer be read are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an expired or unreadable entry can't be removed.
+    pub fn front(&self) -> Result<Option<(u64, TrebllePayload)>> {
+        let mut state = self.lock()?;
+        self.evict_expired(&mut state)?;
+
+        while let Some(entry) = state.entries.front().copied() {
+            let path = self.entry_path(entry.seq, ENTRY_EXTENSION);
+            let payload = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<TrebllePayload>(&bytes).ok());
+
+            if let Some(payload) = payload {
+                return Ok(Some((entry.seq, payload)));
+            }
+
+            remove_if_exists(&path)?;
+            state.entries.pop_front();
+            state.total_bytes -= entry.size;
+            metrics::record_dropped("spool_unreadable", 1);
+        }
+
+        Ok(None)
+    }
+
+    /// Remove a payload from the spool once it has been delivered
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry file can't be removed.
+    pub fn remove(&self, seq: u64) -> Result<()> {
+        let mut state = self.lock()?;
+
+        if let Some(index) = state.entries.iter().position(|entry| entry.seq == seq) {
+            remove_if_exists(&self.entry_path(seq, ENTRY_EXTENSION))?;
+            if let Some(entry) = state.entries.remove(index) {
+                state.total_bytes -= entry.size;
+            }
+            metrics::set_queue_depth(QUEUE, state.entries.len());
+        }
+
+        Ok(())
+    }
+
+    /// Number of spooled payloads
+    pub fn len(&self) -> usize {
+        self.lock().map_or(0, |state| state.entries.len())
+    }
+
+    /// Whether the spool is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size in bytes of all spooled payloads
+    pub fn size_bytes(&self) -> u64 {
+        self.lock().map_or(0, |state| state.total_bytes)
+    }
+
+    fn evict_expired(&self, state: &mut SpoolState) -> Result<()> {
+        let now = SystemTime::now();
+
+        while let Some(entry) = state.entries.front().copied() {
+            let age = now.duration_since(entry.created).unwrap_or_default();
+            if age < self.max_age {
+                break;
+            }
+
+            remove_if_exists(&self.entry_path(entry.seq, ENTRY_EXTENSION))?;
+            state.entries.pop_front();
+            state.total_bytes -= entry.size;
+            metrics::record_dropped("spool_expired", 1);
+        }
+
+        Ok(())
+    }
+
+    fn entry_path(&self, seq: u64, extension: &str) -> PathBuf {
+        self.dir.join(format!("{seq:020}.{extension}"))
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, SpoolState>> {
+        self.state.lock().map_err(|e| TreblleError::LockError(e.to_string()))
+    }
+}
+
+/// Circuit breaker that stops delivery attempts after repeated failures
+struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: AtomicU32,
+    opened_at: Mutex<Option<Instant>>,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold,
+            cooldown,
+            consecutive_failures: AtomicU32::new(0),
+            opened_at: Mutex::new(None),
+        }
+    }
+
+    /// Whether delivery should be skipped; after the cooldown a single attempt is let through
+    fn is_open(&self) -> bool {
+        self.opened_at
+            .lock()
+            .is_ok_and(|opened_at| opened_at.is_some_and(|at| at.elapsed() < self.cooldown))
+    }
+
+    fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Release);
+        if let Ok(mut opened_at) = self.opened_at.lock() {
+            *opened_at = None;
+        }
+    }
+
+    fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+        if failures >= self.failure_threshold {
+            if let Ok(mut opened_at) = self.opened_at.lock() {
+                *opened_at = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Transport wrapper that spools payloads to disk when delivery fails.
+///
+/// Payloads are retried a few times before being appended to the [`Spool`]. After
+/// repeated failures the circuit opens and payloads go straight to the spool until
+/// the cooldown has passed. Spooled payloads are replayed in order, ahead of new
+/// ones, on the next send once the circuit has recovered, on [`Transport::flush`],
+/// periodically once [`SpoolingTransport::spawn_replay`] is running, or explicitly
+/// through [`SpoolingTransport::replay`].
+///
+/// Retries back off exponentially and spool file I/O runs on tokio's blocking pool when
+/// called from inside a tokio runtime; in the WASM build both happen inline.
+pub struct SpoolingTransport {
+    inner: Arc<dyn Transport>,
+    spool: Arc<Spool>,
+    max_retries: usize,
+    retry_backoff: Duration,
+    breaker: CircuitBreaker,
+    replaying: AtomicBool,
+}
+
+impl SpoolingTransport {
+    /// Wrap a transport with the given spool
+    pub fn new<T: Transport + 'static>(inner: T, spool: Spool) -> Self {
+        Self::from_arc(Arc::new(inner), spool)
+    }
+
+    /// Wrap a shared transport with the given spool
+    pub fn from_arc(inner: Arc<dyn Transport>, spool: Spool) -> Self {
+        Self {
+            inner,
+            spool: Arc::new(spool),
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            breaker: CircuitBreaker::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN),
+            replaying: AtomicBool::new(false),
+        }
+    }
+
+    /// Set how many times a payload is retried before it is spooled (defaults to 2)
+    #[must_use]
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the delay before the first retry (defaults to 100ms). The delay doubles on
+    /// every further retry, up to 5 seconds.
+    #[must_use]
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Set how many consecutive failures open the circuit (defaults to 5) and
+    /// how long it stays open (defaults to 30 seconds)
+    #[must_use]
+    pub fn with_circuit_breaker(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = CircuitBreaker::new(failure_threshold.max(1), cooldown);
+        self
+    }
+
+    /// The underlying spool
+    pub fn spool(&self) -> &Spool {
+        &self.spool
+    }
+
+    /// Replay spooled payloads in order until the spool is empty or delivery fails.
+    /// Returns the number of payloads delivered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the spool can't be read or updated.
+    pub async fn replay(&self) -> Result<usize> {
+        if self.breaker.is_open() || self.replaying.swap(true, Ordering::AcqRel) {
+            return Ok(0);
+        }
+
+        let _guard = ReplayGuard(&self.replaying);
+        let mut replayed = 0;
+
+        loop {
+            let spool = Arc::clone(&self.spool);
+            let Some((seq, payload)) = run_blocking(move || spool.front()).await? else {
+                break;
+            };
+            if self.send_with_retries(&payload).await.is_err() {
+                break;
+            }
+            let spool = Arc::clone(&self.spool);
+            run_blocking(move || spool.remove(seq)).await?;
+            replayed += 1;
+        }
+
+        Ok(replayed)
+    }
+
+    /// Replay spooled payloads every `interval` on the current tokio runtime, so they
+    /// are delivered even when no new payloads are sent. The task stops once the
+    /// transport is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    #[cfg(all(feature = "http_client", not(target_arch = "wasm32")))]
+    pub fn spawn_replay(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let transport = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                ticks.tick().await;
+                let Some(transport) = transport.upgrade() else { break };
+                if !transport.spool.is_empty() {
+                    let _ = transport.replay().await;
+                }
+            }
+        })
+    }
+
+    async fn push(&self, payload: TrebllePayload) -> Result<()> {
+        let spool = Arc::clone(&self.spool);
+        run_blocking(move || spool.push(&payload)).await
+    }
+
+    async fn send_with_retries(&self, payload: &TrebllePayload) -> Result<()> {
+        let mut attempt = 0;
+
+        loop {
+            match self.inner.send(payload.clone()).await {
+                Ok(()) => {
+                    self.breaker.record_success();
+                    return Ok(());
+                }
+                Err(e) if attempt >= self.max_retries => {
+                    self.breaker.record_failure();
+                    return Err(e);
+                }
+                Err(_) => {
+                    #[cfg(all(feature = "http_client", not(target_arch = "wasm32")))]
+                    backoff(self.retry_backoff, attempt).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Wait before a retry, doubling the base delay for every previous retry.
+/// Outside of a tokio runtime retries happen immediately.
+#[cfg(all(feature = "http_client", not(target_arch = "wasm32")))]
+async fn backoff(base: Duration, attempt: usize) {
+    let delay = base.saturating_mul(1 << attempt.min(16)).min(MAX_RETRY_BACKOFF);
+    if !delay.is_zero() && tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+impl Transport for SpoolingTransport {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            if self.breaker.is_open() {
+                return self.push(payload).await;
+            }
+
+            // Queue behind spooled payloads so delivery order is preserved
+            if !self.spool.is_empty() || self.replaying.load(Ordering::Acquire) {
+                self.push(payload).await?;
+                self.replay().await?;
+                return Ok(());
+            }
+
+            if self.send_with_retries(&payload).await.is_err() {
+                self.push(payload).await?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.replay().await?;
+            self.inner.flush().await
+        })
+    }
+}
+
+/// Clears the replay flag even if the replaying future is dropped
+struct ReplayGuard<'a>(&'a AtomicBool);
+
+impl Drop for ReplayGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::PayloadData;
+    use crate::transport::MemoryTransport;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("treblle-spool-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn test_payload(project_id: &str) -> TrebllePayload {
+        TrebllePayload {
+            api_key: "test_key".to_string(),
+            project_id: project_id.to_string(),
+            version: 0.1,
+            sdk: "treblle-rust".to_string(),
+            data: PayloadData::default(),
+        }
+    }
+
+    /// Transport failing while `down` is set, delivering to memory otherwise
+    #[derive(Clone, Default)]
+    struct FlakyTransport {
+        down: Arc<AtomicBool>,
+        attempts: Arc<AtomicU32>,
+        delivered: MemoryTransport,
+    }
+
+    impl Transport for FlakyTransport {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Box::pin(std::future::ready(Err(TreblleError::Timeout)));
+            }
+            self.delivered.send(payload)
+        }
+    }
+
+    #[test]
+    fn test_spool_survives_restart() {
+        let dir = test_dir("restart");
+
+        let spool = Spool::open(&dir).unwrap();
+        spool.push(&test_payload("first")).unwrap();
+        spool.push(&test_payload("second")).unwrap();
+        drop(spool);
+
+        // Leftover from a write interrupted by a crash
+        fs::write(dir.join(format!("{:020}.tmp", 2)), b"{\"api_key\":").unwrap();
+
+        let spool = Spool::open(&dir).unwrap();
+        assert_eq!(spool.len(), 2);
+        assert!(!dir.join(format!("{:020}.tmp", 2)).exists());
+
+        let (seq, payload) = spool.front().unwrap().unwrap();
+        assert_eq!(payload.project_id, "first");
+        assert_eq!(payload.api_key, "test_key");
+        spool.remove(seq).unwrap();
+
+        spool.push(&test_payload("third")).unwrap();
+        assert_eq!(spool.front().unwrap().unwrap().1.project_id, "second");
+        assert!(dir.join(format!("{:020}.json", 2)).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_size_cap_evicts_oldest() {
+        let dir = test_dir("size-cap");
+        let entry_size = serde_json::to_vec(&test_payload("a")).unwrap().len() as u64;
+
+        let spool = Spool::open(&dir).unwrap().with_max_bytes(entry_size * 2);
+        for project_id in ["a", "b", "c"] {
+            spool.push(&test_payload(project_id)).unwrap();
+        }
+
+        assert_eq!(spool.len(), 2);
+        assert_eq!(spool.size_bytes(), entry_size * 2);
+        assert_eq!(spool.front().unwrap().unwrap().1.project_id, "b");
+
+        let oversized = Spool::open(&dir).unwrap().with_max_bytes(1);
+        assert!(matches!(oversized.push(&test_payload("d")), Err(TreblleError::Spool(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_age_eviction() {
+        let dir = test_dir("age");
+
+        let spool = Spool::open(&dir).unwrap().with_max_age(Duration::ZERO);
+        spool.push(&test_payload("expired")).unwrap();
+
+        assert!(spool.front().unwrap().is_none());
+        assert!(spool.is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spools_during_outage_and_replays_in_order() {
+        let dir = test_dir("replay");
+        let inner = FlakyTransport::default();
+        let transport = SpoolingTransport::new(inner.clone(), Spool::open(&dir).unwrap())
+            .with_max_retries(1)
+            .with_circuit_breaker(2, Duration::ZERO);
+
+        inner.down.store(true, Ordering::SeqCst);
+        transport.send(test_payload("a")).await.unwrap();
+        transport.send(test_payload("b")).await.unwrap();
+        assert_eq!(inner.attempts.load(Ordering::SeqCst), 4);
+        assert_eq!(transport.spool().len(), 2);
+
+        inner.down.store(false, Ordering::SeqCst);
+        transport.send(test_payload("c")).await.unwrap();
+
+        let delivered: Vec<_> =
+            inner.delivered.payloads().into_iter().map(|p| p.project_id).collect();
+        assert_eq!(delivered, ["a", "b", "c"]);
+        assert!(transport.spool().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_replays_spool() {
+        let dir = test_dir("flush");
+        let inner = FlakyTransport::default();
+        let transport = SpoolingTransport::new(inner.clone(), Spool::open(&dir).unwrap())
+            .with_max_retries(0)
+            .with_circuit_breaker(1, Duration::ZERO);
+
+        inner.down.store(true, Ordering::SeqCst);
+        transport.send(test_payload("a")).await.unwrap();
+        assert_eq!(transport.spool().len(), 1);
+
+        inner.down.store(false, Ordering::SeqCst);
+        transport.flush().await.unwrap();
+        assert_eq!(inner.delivered.len(), 1);
+        assert!(transport.spool().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_periodic_replay() {
+        let dir = test_dir("periodic");
+        let inner = FlakyTransport::default();
+        let transport = Arc::new(
+            SpoolingTransport::new(inner.clone(), Spool::open(&dir).unwrap())
+                .with_max_retries(0)
+                .with_circuit_breaker(1, Duration::ZERO),
+        );
+        let task = transport.spawn_replay(Duration::from_millis(10));
+
+        inner.down.store(true, Ordering::SeqCst);
+        transport.send(test_payload("a")).await.unwrap();
+        inner.down.store(false, Ordering::SeqCst);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(inner.delivered.len(), 1);
+        assert!(transport.spool().is_empty());
+
+        // The task ends with the transport
+        drop(transport);
+        tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retries_back_off() {
+        let dir = test_dir("backoff");
+        let inner = FlakyTransport::default();
+        let transport = SpoolingTransport::new(inner.clone(), Spool::open(&dir).unwrap())
+            .with_max_retries(3)
+            .with_retry_backoff(Duration::from_millis(10));
+
+        inner.down.store(true, Ordering::SeqCst);
+        let start = Instant::now();
+        transport.send(test_payload("a")).await.unwrap();
+
+        // 10ms + 20ms + 40ms between the four attempts
+        assert_eq!(inner.attempts.load(Ordering::SeqCst), 4);
+        assert!(start.elapsed() >= Duration::from_millis(70));
+        assert_eq!(transport.spool().len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_skips_delivery() {
+        let dir = test_dir("circuit");
+        let inner = FlakyTransport::default();
+        let transport = SpoolingTransport::new(inner.clone(), Spool::open(&dir).unwrap())
+            .with_max_retries(0)
+            .with_circuit_breaker(1, Duration::from_secs(60));
+
+        inner.down.store(true, Ordering::SeqCst);
+        transport.send(test_payload("a")).await.unwrap();
+        transport.send(test_payload("b")).await.unwrap();
+
+        // The circuit opened after the first failure
+        assert_eq!(inner.attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(transport.spool().len(), 2);
+        assert_eq!(transport.replay().await.unwrap(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+//! Stdout transport for local debugging.
+
+use std::io::Write;
+
+use crate::error::Result;
+use crate::schema::TrebllePayload;
+use crate::transport::{redacted_json_line, BoxFuture, Transport};
+
+/// Transport printing payloads to stdout as newline-delimited JSON.
+///
+/// Intended for local development; the API key is redacted from every line.
+#[derive(Debug, Default)]
+pub struct StdoutTransport;
+
+impl StdoutTransport {
+    /// Create a new stdout transport
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Transport for StdoutTransport {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn send(&self, payload: TrebllePayload) -> BoxFuture<'_, Result<()>> {
+        let result = redacted_json_line(payload)
+            .and_then(|line| std::io::stdout().lock().write_all(&line).map_err(Into::into));
+        Box::pin(std::future::ready(result))
+    }
+}
@@ -1,7 +1,11 @@
 use http::header::HeaderMap;
 use regex::Regex;
 use serde_json::{Map, Value};
+use std::collections::hash_map::RandomState;
 use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Masks sensitive data in a JSON value based on both regex patterns and exact string matches.
 /// For primitive values, returns a clone.
@@ -11,17 +15,22 @@ pub fn mask_sensitive_data(
     patterns: &[Regex],
     exact_matches: &HashSet<String>,
 ) -> Value {
+    mask_fields(data, &|key| {
+        exact_matches.contains(key) || patterns.iter().any(|re| re.is_match(key))
+    })
+}
+
+/// Masks the fields of a JSON value for which `should_mask` returns true.
+pub fn mask_fields(data: &Value, should_mask: &dyn Fn(&str) -> bool) -> Value {
     match data {
         Value::Object(map) => {
             let mut new_map = Map::new();
             for (key, value) in map {
-                let should_mask =
-                    exact_matches.contains(key) || patterns.iter().any(|re| re.is_match(key));
-                let new_value = if should_mask && !value.is_object() {
+                let new_value = if should_mask(key) && !value.is_object() {
                     /* @TODO: Only mask leaf nodes or mask full objects? `&& !value.is_object()` */
                     Value::String("*****".to_string())
                 } else {
-                    mask_sensitive_data(value, patterns, exact_matches)
+                    mask_fields(value, should_mask)
                 };
                 new_map.insert(key.clone(), new_value);
             }
@@ -30,7 +39,7 @@ pub fn mask_sensitive_data(
         Value::Array(arr) => {
             let mut new_arr = Vec::with_capacity(arr.len());
             for value in arr {
-                new_arr.push(mask_sensitive_data(value, patterns, exact_matches));
+                new_arr.push(mask_fields(value, should_mask));
             }
             Value::Array(new_arr)
         }
@@ -97,6 +106,23 @@ pub fn extract_ip_from_headers(headers: &HeaderMap) -> Option<String> {
     None
 }
 
+/// Cheap non-cryptographic random number, e.g. for trace IDs and sampling.
+pub fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(nanos);
+    hasher.finish()
+}
+
+/// Cheap non-cryptographic random number in `[0, 1)`, good enough for sampling.
+pub fn random_fraction() -> f64 {
+    // Random mantissa bits with the exponent of 1.0 give a number in [1, 2)
+    f64::from_bits(0x3ff0_0000_0000_0000 | (random_u64() >> 12)) - 1.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
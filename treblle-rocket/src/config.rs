@@ -1,5 +1,18 @@
+use rocket::figment::Figment;
 use serde::{Deserialize, Serialize};
-use treblle_core::{Config as CoreConfig, Result};
+use std::path::Path;
+use std::time::Duration;
+use treblle_core::constants::MAX_BODY_SIZE;
+use treblle_core::{
+    CaptureRule, Config as CoreConfig, Diagnostic, FileFormat, Result, RouteOverride, Severity,
+    StatusCodes, TreblleError, ValidationReport,
+};
+
+/// Table of Rocket's configuration the Treblle options are read from
+const FIGMENT_TABLE: &str = "treblle";
+
+/// Most bytes of a request body Rocket lets fairings peek at
+pub(crate) const PEEK_BYTES: usize = 512;
 
 /// Configuration for the Treblle Rocket fairing
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -25,6 +38,93 @@ impl RocketConfig {
     pub fn core(&self) -> &CoreConfig {
         &self.core
     }
+
+    /// Load the configuration from the `TREBLLE_*` environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is invalid or the API key is missing.
+    pub fn from_env() -> Result<Self> {
+        Self::builder().load_env()?.build()
+    }
+
+    /// Load the configuration from a TOML, YAML or JSON file, overridden by the `TREBLLE_*`
+    /// environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file or a variable is invalid, or the API key is missing.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::builder().load_file(path)?.load_env()?.build()
+    }
+
+    /// Load the configuration from the `treblle` table of Rocket's configuration, overridden
+    /// by the `TREBLLE_*` environment variables.
+    ///
+    /// With Rocket's default figment that's the `[default.treblle]` table of `Rocket.toml`,
+    /// or the table of the selected profile such as `[release.treblle]`:
+    ///
+    /// ```ignore
+    /// let rocket = rocket::build();
+    /// let config = RocketConfig::from_figment(rocket.figment())?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table or a variable is invalid, or the API key is missing.
+    pub fn from_figment(figment: &Figment) -> Result<Self> {
+        Self::builder().load_figment(figment)?.load_env()?.build()
+    }
+
+    /// Check the configuration for settings that are invalid or likely mistakes.
+    ///
+    /// Besides the checks of the core configuration, this warns about request body limits
+    /// set above the 512 bytes Rocket lets fairings peek at, as they're capped there.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = self.core.validate();
+        let mut warn = |field, message| {
+            report.push(Diagnostic { severity: Severity::Warning, field, message });
+        };
+
+        let limit = self.core.max_request_body_size;
+        if limit > PEEK_BYTES && limit != MAX_BODY_SIZE {
+            warn("maxRequestBodySize", peek_warning(limit));
+        }
+        for route_override in &self.core.route_overrides {
+            let Some(limit) = route_override.max_body_size else { continue };
+            if limit > PEEK_BYTES && route_override.capture_request_body != Some(false) {
+                let route = route_override.route.as_str();
+                warn("routeOverrides", format!("{route}: {}", peek_warning(limit)));
+            }
+        }
+
+        report
+    }
+
+    /// Log the diagnostics of an enabled configuration through `tracing`
+    pub fn log_diagnostics(&self) {
+        if self.core.is_enabled() {
+            self.validate().log();
+        }
+    }
+
+    /// Bytes of a request body to peek at, enough for the largest limit of any route
+    pub(crate) fn request_peek_size(&self) -> usize {
+        self.core
+            .route_overrides
+            .iter()
+            .filter_map(|route_override| route_override.max_body_size)
+            .fold(self.core.max_request_body_size, usize::max)
+            .min(PEEK_BYTES)
+    }
+}
+
+/// Warning for a request body limit Rocket can't peek that far
+fn peek_warning(limit: usize) -> String {
+    format!(
+        "Rocket captures request bodies up to {PEEK_BYTES} bytes, so a limit of {limit} bytes \
+         is capped there"
+    )
 }
 
 impl RocketConfigBuilder {
@@ -124,6 +224,181 @@ impl RocketConfigBuilder {
         Ok(self)
     }
 
+    /// Set HTTP methods of requests that aren't captured, e.g. `OPTIONS` (optional)
+    #[must_use]
+    pub fn ignored_methods<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        methods: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.ignored_methods(methods);
+        self
+    }
+
+    /// Set status codes of responses that aren't captured, e.g. `404` (optional)
+    #[must_use]
+    pub fn ignored_status_codes<T: Into<StatusCodes>, I: IntoIterator<Item = T>>(
+        mut self,
+        status_codes: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.ignored_status_codes(status_codes);
+        self
+    }
+
+    /// Capture requests slower than this regardless of status code rules (optional)
+    #[must_use]
+    pub fn always_capture_slower_than(mut self, latency: Duration) -> Self {
+        self.core_builder = self.core_builder.always_capture_slower_than(latency);
+        self
+    }
+
+    /// Add a rule restricting the status codes captured for some routes (optional)
+    #[must_use]
+    pub fn add_capture_rule(mut self, rule: CaptureRule) -> Self {
+        self.core_builder = self.core_builder.add_capture_rule(rule);
+        self
+    }
+
+    /// Add settings replacing the configuration for some routes (optional)
+    #[must_use]
+    pub fn add_route_override(mut self, route_override: RouteOverride) -> Self {
+        self.core_builder = self.core_builder.add_route_override(route_override);
+        self
+    }
+
+    /// Only capture these headers, matched case-insensitively (optional, defaults to all)
+    #[must_use]
+    pub fn allowed_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.allowed_headers(headers);
+        self
+    }
+
+    /// Set headers that aren't captured, matched case-insensitively (optional)
+    #[must_use]
+    pub fn ignored_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.ignored_headers(headers);
+        self
+    }
+
+    /// Add masked headers to the default authorization and cookie headers
+    #[must_use]
+    pub fn add_masked_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.add_masked_headers(headers);
+        self
+    }
+
+    /// Set masked headers, replacing the defaults
+    #[must_use]
+    pub fn set_masked_headers<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.set_masked_headers(headers);
+        self
+    }
+
+    /// Only mask these cookies in `Cookie` and `Set-Cookie` headers (optional, defaults to all)
+    #[must_use]
+    pub fn masked_cookies<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        cookies: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.masked_cookies(cookies);
+        self
+    }
+
+    /// Set the largest request body captured in bytes, defaults to 10MB (optional).
+    ///
+    /// Rocket lets fairings peek at the first 512 bytes of a request body only, so request
+    /// bodies are captured up to 512 bytes whatever the limit.
+    #[must_use]
+    pub fn max_request_body_size(mut self, bytes: usize) -> Self {
+        self.core_builder = self.core_builder.max_request_body_size(bytes);
+        self
+    }
+
+    /// Set the largest response body captured in bytes, defaults to 10MB (optional)
+    #[must_use]
+    pub fn max_response_body_size(mut self, bytes: usize) -> Self {
+        self.core_builder = self.core_builder.max_response_body_size(bytes);
+        self
+    }
+
+    /// Turn Treblle on or off (optional, defaults to enabled)
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.core_builder = self.core_builder.enabled(enabled);
+        self
+    }
+
+    /// Only enable Treblle in these environments (optional, defaults to all environments)
+    #[must_use]
+    pub fn environments<T: Into<String>, I: IntoIterator<Item = T>>(
+        mut self,
+        environments: I,
+    ) -> Self {
+        self.core_builder = self.core_builder.environments(environments);
+        self
+    }
+
+    /// Set the environment the application runs in (optional, defaults to `APP_ENV`)
+    #[must_use]
+    pub fn environment<T: Into<String>>(mut self, environment: T) -> Self {
+        self.core_builder = self.core_builder.environment(environment);
+        self
+    }
+
+    /// Load values from a TOML, YAML or JSON file, picked by its extension (optional).
+    ///
+    /// File values are used for anything neither the builder nor environment variables set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or parsed.
+    pub fn load_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        self.core_builder = self.core_builder.load_file(path)?;
+        Ok(self)
+    }
+
+    /// Load values from the `treblle` table of Rocket's configuration (optional).
+    ///
+    /// The values take the same precedence as those of [`load_file`](Self::load_file).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table can't be parsed.
+    pub fn load_figment(mut self, figment: &Figment) -> Result<Self> {
+        if !figment.contains(FIGMENT_TABLE) {
+            return Ok(self);
+        }
+
+        let table: serde_json::Value = figment.extract_inner(FIGMENT_TABLE).map_err(|e| {
+            TreblleError::Config(format!("Invalid [{FIGMENT_TABLE}] configuration: {e}"))
+        })?;
+        self.core_builder = self.core_builder.load_str(&table.to_string(), FileFormat::Json)?;
+        Ok(self)
+    }
+
+    /// Load values from the `TREBLLE_*` environment variables (optional).
+    ///
+    /// Environment values are used for anything the builder doesn't set, and win over files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable has an invalid value.
+    pub fn load_env(mut self) -> Result<Self> {
+        self.core_builder = self.core_builder.load_env()?;
+        Ok(self)
+    }
+
     /// Build the configuration
     pub fn build(self) -> Result<RocketConfig> {
         Ok(RocketConfig { core: self.core_builder.build()? })
@@ -263,4 +538,63 @@ mod tests {
         let invalid_config = "invalid json";
         assert!(RocketConfig::try_from(invalid_config).is_err());
     }
+
+    #[test]
+    fn test_from_figment() {
+        use rocket::figment::providers::{Format, Toml};
+
+        let toml = r#"
+            [default.treblle]
+            api_key = "rocket_key"
+            masked_fields = ["ssn"]
+        "#;
+        let figment = Figment::from(Toml::string(toml).nested());
+
+        let config = RocketConfig::builder().load_figment(&figment).unwrap().build().unwrap();
+        assert_eq!(config.core.api_key, "rocket_key");
+        assert!(config.core.should_mask_field("ssn"));
+        assert!(!config.core.should_mask_field("debit_card"));
+
+        // Without a table, the builder is left as is
+        let config = RocketConfig::builder().load_figment(&Figment::new()).unwrap();
+        assert!(config.build().is_err());
+    }
+
+    #[test]
+    fn test_request_limits_above_peek_size_warn() {
+        let config =
+            RocketConfig::builder().api_key("test_key").project_id("test").build().unwrap();
+        assert!(config.validate().is_empty());
+        assert_eq!(config.request_peek_size(), PEEK_BYTES);
+
+        let config = RocketConfig::builder()
+            .api_key("test_key")
+            .project_id("test")
+            .max_request_body_size(64)
+            .add_route_override(RouteOverride::new("^/uploads").unwrap().max_body_size(4096))
+            .add_route_override(
+                RouteOverride::new("^/files")
+                    .unwrap()
+                    .max_body_size(4096)
+                    .capture_request_body(false),
+            )
+            .build()
+            .unwrap();
+        let report = config.validate();
+        let fields: Vec<_> = report.warnings().map(|d| d.field).collect();
+        assert_eq!(fields, ["routeOverrides"]);
+        assert!(report.diagnostics()[0].message.starts_with("^/uploads: "));
+        assert_eq!(config.request_peek_size(), PEEK_BYTES);
+
+        let config = RocketConfig::builder()
+            .api_key("test_key")
+            .max_request_body_size(2048)
+            .build()
+            .unwrap();
+        assert!(config.validate().warnings().any(|d| d.field == "maxRequestBodySize"));
+
+        let config =
+            RocketConfig::builder().api_key("test_key").max_request_body_size(64).build().unwrap();
+        assert_eq!(config.request_peek_size(), 64);
+    }
 }
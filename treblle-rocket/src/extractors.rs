@@ -72,6 +72,7 @@ impl TreblleExtractor for RocketExtractor {
                 .or_else(|| req.client_ip().map(|addr| addr.to_string()))
                 .unwrap_or_else(|| "unknown".to_string()),
             url: Self::construct_full_url(req),
+            route_path: req.route().map(|route| route.uri.path().to_string()),
             user_agent: req.headers().get_one("User-Agent").unwrap_or("").to_string(),
             method: req.method().to_string(),
             headers: req
@@ -1,64 +1,177 @@
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
 use rocket::{
     fairing::{Fairing, Info, Kind},
-    Data, Request, Response,
+    Data, Orbit, Request, Response, Rocket,
 };
-use serde_json::Value;
-use tokio::sync::OnceCell;
 use tracing::error;
 
-use crate::config::RocketConfig;
+use crate::config::{RocketConfig, PEEK_BYTES};
 use crate::extractors::TreblleState;
-use treblle_core::constants::MAX_BODY_SIZE;
+use treblle_core::constants::http::SHUTDOWN_TIMEOUT;
 use treblle_core::{
+    metrics,
     schema::{LanguageInfo, PayloadData, RequestInfo, ResponseInfo, ServerInfo, TrebllePayload},
-    TreblleClient,
+    CaptureDecision, CapturedBody, ConfigHandle, Dispatcher, Transport, TreblleClient,
 };
 
-static START_TIME: OnceCell<Instant> = OnceCell::const_new();
+/// When the fairing first saw the request, `None` for requests it didn't see
+struct RequestStart(Option<Instant>);
+
+/// Request data and its peeked body, held back until the response is known
+#[derive(Default)]
+struct DeferredRequest(Mutex<Option<(RequestInfo, PeekedBody)>>);
+
+/// The start of a request body, peeked without consuming it.
+///
+/// Rocket peeks at most [`PEEK_BYTES`] bytes, so larger bodies are truncated there even under
+/// a higher limit.
+struct PeekedBody {
+    bytes: Bytes,
+    complete: bool,
+    size: Option<u64>,
+}
+
+impl PeekedBody {
+    /// Peek up to `limit` bytes of a request body
+    async fn peek(req: &Request<'_>, data: &mut Data<'_>, limit: usize) -> Self {
+        // One byte past the limit tells whether the body goes on past it
+        let mut bytes = Bytes::copy_from_slice(data.peek(limit.saturating_add(1)).await);
+        // A body Rocket already holds whole, like that of a local request, is peeked at no cost
+        if data.peek_complete() && bytes.len() > limit {
+            bytes = Bytes::copy_from_slice(data.peek(PEEK_BYTES).await);
+        }
+        let size = req.headers().get_one("content-length").and_then(|len| len.parse().ok());
+        // Peeks stop at PEEK_BYTES, so a body filling them may go on past them
+        let complete =
+            (data.peek_complete() && bytes.len() < PEEK_BYTES) || size == Some(bytes.len() as u64);
+
+        Self { bytes, complete, size }
+    }
+
+    /// Capture the body, keeping at most `limit` bytes of it
+    fn capture(&self, limit: usize) -> CapturedBody {
+        if self.complete {
+            CapturedBody::complete(self.bytes.clone(), limit)
+        } else {
+            let end = self.bytes.len().min(limit);
+            CapturedBody::partial(self.bytes.slice(..end), self.size)
+        }
+    }
+}
 
 /// Treblle fairing for Rocket
+///
+/// Pending payloads are flushed when Rocket shuts down, up to the shutdown timeout.
 pub struct TreblleFairing {
-    config: Arc<RocketConfig>,
-    treblle_client: Arc<TreblleClient>,
+    config: ConfigHandle<RocketConfig>,
+    dispatcher: Arc<Dispatcher>,
+    shutdown_timeout: Duration,
 }
 
 impl TreblleFairing {
+    /// Create a new Treblle fairing sending payloads to the Treblle API
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Treblle HTTP client can't be created.
     pub fn new(config: RocketConfig) -> Self {
+        let transport =
+            TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client");
+        Self::with_transport(config, Arc::new(transport))
+    }
+
+    /// Create a new Treblle fairing sending payloads through a custom transport
+    pub fn with_transport(config: RocketConfig, transport: Arc<dyn Transport>) -> Self {
+        Self::with_config_handle(config.into(), transport)
+    }
+
+    /// Create a new Treblle fairing reading its configuration from a handle on every request
+    pub fn with_config_handle(
+        config: ConfigHandle<RocketConfig>,
+        transport: Arc<dyn Transport>,
+    ) -> Self {
+        config.load().log_diagnostics();
         TreblleFairing {
-            treblle_client: Arc::new(
-                TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client"),
-            ),
-            config: Arc::new(config),
+            config,
+            dispatcher: Arc::new(Dispatcher::new(transport)),
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    /// Get the handle to replace the configuration at runtime
+    pub fn config_handle(&self) -> &ConfigHandle<RocketConfig> {
+        &self.config
+    }
+
+    /// Set how long to wait for pending payloads on shutdown (defaults to 5 seconds)
+    #[must_use]
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Dispatch a payload, logging failures to send it in the background
+    fn send_payload(&self, payload: TrebllePayload) {
+        let transport = self.dispatcher.transport().name();
+        if let Some(send) = self.dispatcher.dispatch(payload) {
+            tokio::spawn(async move {
+                if let Err(e) = send.await {
+                    error!("Failed to send payload via {} transport: {:?}", transport, e);
+                }
+            });
         }
     }
 }
 
+/// Request data of a payload, without the body
+fn request_info(req: &Request<'_>) -> RequestInfo {
+    RequestInfo {
+        timestamp: chrono::Utc::now(),
+        ip: req.client_ip().map_or_else(|| "unknown".to_string(), |addr| addr.to_string()),
+        url: req.uri().to_string(),
+        route_path: None,
+        method: req.method().to_string(),
+        headers: req.headers().iter().map(|h| (h.name.to_string(), h.value.to_string())).collect(),
+        user_agent: req.headers().get_one("User-Agent").unwrap_or("").to_string(),
+        body: None,
+    }
+}
+
 #[rocket::async_trait]
 impl Fairing for TreblleFairing {
     fn info(&self) -> Info {
-        Info { name: "Treblle", kind: Kind::Request | Kind::Response | Kind::Singleton }
+        Info {
+            name: "Treblle",
+            kind: Kind::Request | Kind::Response | Kind::Shutdown | Kind::Singleton,
+        }
     }
 
     async fn on_request(&self, req: &mut Request<'_>, data: &mut Data<'_>) {
-        let config = self.config.clone();
-        let treblle_client = self.treblle_client.clone();
+        let config = self.config.load();
+        if !config.core.is_enabled() {
+            return;
+        }
+        req.local_cache(|| RequestStart(Some(Instant::now())));
 
         // Only process JSON requests that aren't ignored
-        let should_process = !config.core.should_ignore_route(&req.uri().path().to_string())
+        let path = req.uri().path().to_string();
+        let decision = config.core.capture.capture_request(req.method().as_str(), &path);
+        let should_process = decision != CaptureDecision::Skip
+            && !config.core.should_ignore_route(&path)
             && req.content_type().map(|ct| ct.is_json()).unwrap_or(false);
 
-        if should_process {
-            if START_TIME.get().is_none() {
-                let _ = START_TIME.set(Instant::now());
-            }
+        if !should_process {
+            metrics::record_ignored();
+        }
 
-            // Read request data
-            let bytes = data.peek(MAX_BODY_SIZE).await;
-            if !bytes.is_empty() {
-                if let Ok(json_body) = serde_json::from_slice::<Value>(bytes) {
+        if should_process {
+            // Read request data, as far as the largest limit of any route and Rocket allow
+            let body = PeekedBody::peek(req, data, config.request_peek_size()).await;
+            if !body.bytes.is_empty() {
+                if let Some(json_body) = body.capture(config.core.max_request_body_size).to_json() {
                     // Store the body in state
                     if let Some(state) = req.rocket().state::<TreblleState>() {
                         if let Ok(mut body) = state.request_body.write() {
@@ -66,58 +179,73 @@ impl Fairing for TreblleFairing {
                         }
                     }
 
-                    let payload = TrebllePayload {
-                        api_key: config.core.api_key.clone(),
-                        project_id: config.core.project_id.clone(),
-                        version: 0.1,
-                        sdk: format!("treblle-rust-{}", env!("CARGO_PKG_VERSION")),
-                        data: PayloadData {
-                            server: ServerInfo::default(),
-                            language: LanguageInfo {
-                                name: "rust".to_string(),
-                                version: env!("CARGO_PKG_VERSION").to_string(),
-                            },
-                            request: RequestInfo {
-                                timestamp: chrono::Utc::now(),
-                                ip: req
-                                    .client_ip()
-                                    .map(|addr| addr.to_string())
-                                    .unwrap_or_else(|| "unknown".to_string()),
-                                url: req.uri().to_string(),
-                                method: req.method().to_string(),
-                                headers: req
-                                    .headers()
-                                    .iter()
-                                    .map(|h| (h.name.to_string(), h.value.to_string()))
-                                    .collect(),
-                                user_agent: req
-                                    .headers()
-                                    .get_one("User-Agent")
-                                    .unwrap_or("")
-                                    .to_string(),
-                                body: Some(json_body),
-                            },
-                            response: ResponseInfo::default(),
-                            errors: Vec::new(),
-                        },
-                    };
-
-                    let treblle_client_clone = treblle_client.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = treblle_client_clone.send_to_treblle(payload).await {
-                            error!("Failed to send request payload to Treblle: {:?}", e);
-                        }
-                    });
+                    let request = RequestInfo { body: Some(json_body), ..request_info(req) };
+
+                    // Requests are routed after this, so the request is held back until the
+                    // response, once the route template is known, and sent with it
+                    let deferred = req.local_cache(DeferredRequest::default);
+                    if let Ok(mut deferred) = deferred.0.lock() {
+                        *deferred = Some((request, body));
+                    }
                 }
             }
         }
     }
 
-    async fn on_response<'r>(&self, _req: &'r Request<'_>, res: &mut Response<'r>) {
-        if let Some(start_time) = START_TIME.get() {
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let config = self.config.load();
+        if !config.core.is_enabled() {
+            return;
+        }
+
+        let method = req.method().as_str();
+        let path = req.uri().path().to_string();
+        let decision = config.core.capture.capture_request(method, &path);
+        if decision == CaptureDecision::Skip {
+            return;
+        }
+
+        let route_path = req.route().map(|route| route.uri.path().to_string());
+        if config.core.should_ignore_request(&path, route_path.as_deref()) {
+            return;
+        }
+
+        let policy = config.core.policy_for(&path, route_path.as_deref());
+        if !policy.is_sampled() {
+            metrics::record_sampled_out();
+            return;
+        }
+
+        if let RequestStart(Some(start_time)) = req.local_cache(|| RequestStart(None)) {
             let duration = start_time.elapsed();
-            let treblle_client = self.treblle_client.clone();
-            let config = self.config.clone();
+
+            if decision == CaptureDecision::AfterResponse {
+                let status = res.status().code;
+                if !config.core.capture.should_capture_response(method, &path, status, duration) {
+                    metrics::record_ignored();
+                    return;
+                }
+            }
+
+            // Requests without a captured body are reported without it
+            let deferred = req.local_cache(DeferredRequest::default);
+            let mut request = match deferred.0.lock().ok().and_then(|mut r| r.take()) {
+                Some((request, body)) => RequestInfo {
+                    body: policy
+                        .capture_request_body()
+                        .then(|| body.capture(policy.max_request_body_size(&config.core)).to_json())
+                        .flatten()
+                        .map(|body| policy.mask(&config.core, &body)),
+                    ..request
+                },
+                None => RequestInfo {
+                    timestamp: chrono::Utc::now()
+                        - chrono::Duration::from_std(duration).unwrap_or_default(),
+                    ..request_info(req)
+                },
+            };
+            request.route_path = route_path;
+            request.headers = policy.mask_headers(&config.core, &request.headers);
 
             let payload = TrebllePayload {
                 api_key: config.core.api_key.clone(),
@@ -130,13 +258,15 @@ impl Fairing for TreblleFairing {
                         name: "rust".to_string(),
                         version: env!("CARGO_PKG_VERSION").to_string(),
                     },
-                    request: RequestInfo::default(),
+                    request,
                     response: ResponseInfo {
-                        headers: res
-                            .headers()
-                            .iter()
-                            .map(|h| (h.name.to_string(), h.value.to_string()))
-                            .collect(),
+                        headers: policy.mask_headers(
+                            &config.core,
+                            &res.headers()
+                                .iter()
+                                .map(|h| (h.name.to_string(), h.value.to_string()))
+                                .collect(),
+                        ),
                         code: res.status().code,
                         size: res
                             .headers()
@@ -150,11 +280,13 @@ impl Fairing for TreblleFairing {
                 },
             };
 
-            tokio::spawn(async move {
-                if let Err(e) = treblle_client.send_to_treblle(payload).await {
-                    error!("Failed to send response payload to Treblle: {:?}", e);
-                }
-            });
+            self.send_payload(payload);
+        }
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        if let Err(e) = self.dispatcher.shutdown(self.shutdown_timeout).await {
+            error!("Failed to flush pending Treblle payloads on shutdown: {:?}", e);
         }
     }
 }
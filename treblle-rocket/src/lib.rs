@@ -10,11 +10,24 @@ mod fairing;
 pub use config::RocketConfig;
 pub use extractors::TreblleState;
 pub use fairing::TreblleFairing;
+pub use treblle_core::{
+    CaptureRule, ConfigHandle, FileTransport, FileWatcher, MemoryTransport, RouteOverride, Spool,
+    SpoolingTransport, StatusCodes, StdoutTransport, Transport,
+};
+
+#[cfg(feature = "otlp")]
+pub use treblle_core::OtlpTransport;
+
+use std::sync::Arc;
+use std::time::Duration;
+use treblle_core::TreblleClient;
 
 /// Main struct for Treblle integration with Rocket
 #[derive(Clone)]
 pub struct Treblle {
-    config: RocketConfig,
+    config: ConfigHandle<RocketConfig>,
+    transport: Option<Arc<dyn Transport>>,
+    shutdown_timeout: Option<Duration>,
 }
 
 impl Treblle {
@@ -22,17 +35,55 @@ impl Treblle {
     pub fn new<T: Into<String>>(api_key: T) -> Self {
         let config = RocketConfig::builder().api_key(api_key).build().unwrap();
 
-        Treblle { config }
+        Self::from_config(config)
     }
 
     /// Create a new Treblle instance from configuration
     pub fn from_config(config: RocketConfig) -> Self {
-        Treblle { config }
+        Self::from_config_handle(config.into())
+    }
+
+    /// Create a new Treblle instance reading its configuration from a handle on every request.
+    ///
+    /// Configurations stored in the handle apply from the next request on. The API key,
+    /// URLs and TLS settings of the Treblle HTTP client are taken from the configuration
+    /// when the fairing is created and don't change afterwards.
+    pub fn from_config_handle(config: ConfigHandle<RocketConfig>) -> Self {
+        Treblle { config, transport: None, shutdown_timeout: None }
+    }
+
+    /// Send payloads through a custom transport instead of the Treblle API
+    #[must_use]
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Set how long to wait for pending payloads when Rocket shuts down (defaults to 5 seconds)
+    #[must_use]
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
     }
 
     /// Create the Treblle fairing for Rocket
+    ///
+    /// # Panics
+    ///
+    /// Panics if no custom transport is set and the Treblle HTTP client can't be created.
     pub fn fairing(self) -> TreblleFairing {
-        TreblleFairing::new(self.config)
+        let transport = self.transport.unwrap_or_else(|| {
+            let config = self.config.load();
+            let client =
+                TreblleClient::new(config.core.clone()).expect("Failed to create Treblle client");
+            Arc::new(client)
+        });
+        let fairing = TreblleFairing::with_config_handle(self.config, transport);
+
+        match self.shutdown_timeout {
+            Some(timeout) => fairing.with_shutdown_timeout(timeout),
+            None => fairing,
+        }
     }
 }
 
@@ -76,3 +76,315 @@ fn test_ignores_specified_routes() {
 
     assert_eq!(response.status(), Status::Ok);
 }
+
+#[rocket::async_test]
+async fn test_fairing_sends_payloads_through_custom_transport() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let rocket = rocket::build()
+        .attach(Treblle::new("test_key".to_string()).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/", routes![echo]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+
+    let response = client
+        .post("/echo")
+        .header(ContentType::JSON)
+        .body(json!({"username": "test_user"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Payloads are sent from spawned tasks
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert!(payloads.iter().any(|p| p.data.request.body.is_some()));
+    assert!(payloads.iter().any(|p| p.data.response.code == 200));
+}
+
+#[rocket::async_test]
+async fn test_shutdown_fairing_flushes_pending_payloads() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let rocket = rocket::build()
+        .attach(
+            Treblle::new("test_key".to_string())
+                .with_transport(transport.clone())
+                .with_shutdown_timeout(std::time::Duration::from_secs(1))
+                .fairing(),
+        )
+        .manage(TreblleState::default())
+        .mount("/", routes![echo]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+
+    let response = client
+        .post("/echo")
+        .header(ContentType::JSON)
+        .body(json!({"username": "test_user"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    drop(response);
+
+    // Shutdown fairings wait for the spawned sends
+    client.terminate().await;
+    assert_eq!(transport.len(), 1);
+}
+
+#[rocket::async_test]
+async fn test_config_handle_applies_replaced_config() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::{ConfigHandle, MemoryTransport};
+
+    let transport = MemoryTransport::new();
+    let handle = ConfigHandle::new(RocketConfig::builder().api_key("test_key").build().unwrap());
+    let rocket = rocket::build()
+        .attach(
+            Treblle::from_config_handle(handle.clone()).with_transport(transport.clone()).fairing(),
+        )
+        .manage(TreblleState::default())
+        .mount("/", routes![echo]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+
+    let mut config = RocketConfig::clone(&handle.load());
+    config.core.project_id = "replaced_project".to_string();
+    handle.store(config);
+
+    let response = client
+        .post("/echo")
+        .header(ContentType::JSON)
+        .body(json!({"username": "test_user"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads.iter().all(|p| p.project_id == "replaced_project"));
+}
+
+#[rocket::async_test]
+async fn test_disabled_fairing_passes_requests_through() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let config = RocketConfig::builder().enabled(false).build().unwrap();
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/", routes![echo]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+
+    let response = client
+        .post("/echo")
+        .header(ContentType::JSON)
+        .body(json!({"username": "test_user"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_fairing_skips_ignored_status_codes() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let config = RocketConfig::builder().api_key("test_key").ignored_status_codes([404]).build();
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config.unwrap()).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/", routes![echo]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let body = json!({"username": "test_user"}).to_string();
+
+    let response = client.post("/missing").header(ContentType::JSON).body(&body).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(transport.is_empty());
+
+    // Held back requests are sent once their response is known to be captured
+    let response = client.post("/echo").header(ContentType::JSON).body(&body).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads.iter().any(|p| p.data.request.body.is_some()));
+}
+
+#[rocket::async_test]
+async fn test_fairing_times_each_request() {
+    use rocket::local::asynchronous::Client;
+    use std::time::Duration;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let config = RocketConfig::builder()
+        .api_key("test_key")
+        .ignored_status_codes([404])
+        .always_capture_slower_than(Duration::from_millis(50))
+        .build();
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config.unwrap()).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/", routes![echo]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let body = json!({"username": "test_user"}).to_string();
+
+    let response = client.post("/echo").header(ContentType::JSON).body(&body).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+    let captured = transport.len();
+
+    // A fast 404 stays under the threshold, however long ago earlier requests started
+    let response = client.post("/missing").header(ContentType::JSON).body(&body).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(transport.len(), captured);
+    assert!(transport.payloads().iter().all(|p| p.data.response.load_time < 0.05));
+}
+
+#[post("/users/<id>", format = "json", data = "<input>")]
+pub fn user(id: u64, input: Json<Value>) -> Json<Value> {
+    Json(json!({ "id": id, "input": input.into_inner() }))
+}
+
+#[rocket::async_test]
+async fn test_fairing_reports_route_templates() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let config = RocketConfig::builder().api_key("test_key").build().unwrap();
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/api", routes![user]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let body = json!({"username": "test_user"}).to_string();
+
+    let response = client.post("/api/users/8271").header(ContentType::JSON).body(&body).dispatch();
+    assert_eq!(response.await.status(), Status::Ok);
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    assert_eq!(payloads.len(), 1);
+    assert!(payloads
+        .iter()
+        .all(|p| p.data.request.route_path.as_deref() == Some("/api/users/<id>")));
+}
+
+#[rocket::async_test]
+async fn test_fairing_applies_route_overrides() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::{MemoryTransport, RouteOverride};
+
+    let transport = MemoryTransport::new();
+    let config = RocketConfig::builder()
+        .api_key("test_key")
+        .add_route_override(
+            RouteOverride::new("^/api/users/<id>$").unwrap().masked_fields(["username"]),
+        )
+        .build()
+        .unwrap();
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/api", routes![user]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let body = json!({"username": "test_user", "password": "secret"}).to_string();
+
+    let response = client.post("/api/users/8271").header(ContentType::JSON).body(&body).dispatch();
+    assert_eq!(response.await.status(), Status::Ok);
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    let request = payloads.iter().find_map(|p| p.data.request.body.as_ref()).unwrap();
+    assert_eq!(request["username"], "*****");
+    assert_eq!(request["password"], "*****");
+}
+
+#[rocket::async_test]
+async fn test_fairing_truncates_oversized_request_bodies() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let config =
+        RocketConfig::builder().api_key("test_key").max_request_body_size(36).build().unwrap();
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/api", routes![user]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let body = r#"{"password":"hunter2","items":[1,2,3,4,5,6,7,8,9]}"#;
+
+    // The route still receives the whole body
+    let response = client.post("/api/users/1").header(ContentType::JSON).body(body).dispatch();
+    let response = response.await;
+    assert_eq!(response.status(), Status::Ok);
+    let echoed: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(echoed["input"]["items"], json!([1, 2, 3, 4, 5, 6, 7, 8, 9]));
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let payloads = transport.payloads();
+    let request = payloads.iter().find_map(|p| p.data.request.body.as_ref()).unwrap();
+    assert_eq!(
+        request,
+        &json!({
+            "truncated": true,
+            "original_size": body.len(),
+            "body": { "password": "*****", "items": [1, 2] }
+        })
+    );
+}
+
+#[rocket::async_test]
+async fn test_fairing_caps_request_bodies_at_peek_size() {
+    use rocket::local::asynchronous::Client;
+    use treblle_rocket::MemoryTransport;
+
+    let transport = MemoryTransport::new();
+    let config =
+        RocketConfig::builder().api_key("test_key").max_request_body_size(4096).build().unwrap();
+    assert!(config.validate().warnings().any(|d| d.field == "maxRequestBodySize"));
+    let rocket = rocket::build()
+        .attach(Treblle::from_config(config).with_transport(transport.clone()).fairing())
+        .manage(TreblleState::default())
+        .mount("/api", routes![user]);
+
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let body = json!({ "items": vec![1000; 200] }).to_string();
+    assert!(body.len() > 512);
+
+    let response = client.post("/api/users/1").header(ContentType::JSON).body(&body).dispatch();
+    assert_eq!(response.await.status(), Status::Ok);
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // Rocket only lets the fairing peek at the first 512 bytes, whatever the limit
+    let payloads = transport.payloads();
+    let request = payloads.iter().find_map(|p| p.data.request.body.as_ref()).unwrap();
+    assert_eq!(request["truncated"], true);
+    let items = request["body"]["items"].as_array().unwrap();
+    assert!(!items.is_empty() && items.len() * 5 < 512);
+}
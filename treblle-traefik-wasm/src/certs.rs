@@ -2,13 +2,18 @@
 //!
 //! This module provides functionality for loading root certificates,
 //! either from a custom file specified in the configuration or from
-//! the webpki-roots bundle.
+//! the webpki-roots bundle, loading the client certificate for mutual
+//! TLS, and pinning the server's SPKI hashes.
 
-use rustls::{OwnedTrustAnchor, RootCertStore};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
 use std::fs::File;
 use std::io::BufReader;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use treblle_core::error::{Result, TreblleError};
+use treblle_core::tls::{verify_pins, SpkiHash, TlsConfig};
 
 use crate::logger::{log, LogLevel};
 
@@ -18,6 +23,8 @@ use crate::logger::{log, LogLevel};
 ///
 /// * `root_store` - A mutable reference to the `RootCertStore` to load certificates into.
 /// * `root_ca_path` - Optional path to a custom root CA certificate file.
+/// * `strict` - Whether a custom root CA that can't be loaded is an error
+///   instead of falling back to webpki-roots.
 ///
 /// # Returns
 ///
@@ -25,6 +32,7 @@ use crate::logger::{log, LogLevel};
 pub fn load_root_certs(
     root_store: &mut RootCertStore,
     root_ca_path: Option<&String>,
+    strict: bool,
 ) -> Result<()> {
     if let Some(ca_path) = root_ca_path {
         log(LogLevel::Debug, &format!("Attempting to load custom root CA from: {ca_path}"));
@@ -33,6 +41,10 @@ pub fn load_root_certs(
                 log(LogLevel::Debug, "Custom root CA loaded successfully");
                 return Ok(());
             }
+            Err(e) if strict => {
+                log(LogLevel::Error, &format!("Failed to load custom root CA: {e}"));
+                return Err(e);
+            }
             Err(e) => {
                 log(
                     LogLevel::Error,
@@ -49,11 +61,91 @@ pub fn load_root_certs(
     Ok(())
 }
 
-/// Loads custom certificates from a specified file path.
-fn load_custom_certificates(root_store: &mut RootCertStore, ca_path: &str) -> Result<()> {
-    log(LogLevel::Debug, &format!("Opening custom root CA file: {ca_path}"));
-    let file = File::open(ca_path).map_err(|e| {
-        let error_msg = format!("Failed to open custom root CA file: {e}");
+/// Builds the TLS client configuration for the given settings.
+///
+/// # Arguments
+///
+/// * `tls` - The TLS settings: custom root CA, client certificate, pinned SPKI hashes
+///   and strict mode.
+///
+/// # Returns
+///
+/// The client configuration.
+///
+/// # Errors
+///
+/// Returns [`TreblleError::Certificate`] if the client certificate can't be loaded, or
+/// strict mode is enabled and the root CA can't be loaded, or [`TreblleError::Config`] if a pinned hash is invalid.
+pub fn client_config(tls: &TlsConfig) -> Result<ClientConfig> {
+    let mut root_store = RootCertStore::empty();
+    load_root_certs(&mut root_store, tls.root_ca_path.as_ref(), tls.strict)?;
+
+    let verifier =
+        PinningVerifier { inner: WebPkiVerifier::new(root_store, None), pins: tls.pins()? };
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    match load_client_identity(tls)? {
+        Some((cert_chain, key)) => {
+            log(LogLevel::Debug, "Using client certificate for mutual TLS");
+            builder.with_client_auth_cert(cert_chain, key).map_err(|e| {
+                let error_msg = format!("Invalid client certificate: {e}");
+                log(LogLevel::Error, &error_msg);
+                TreblleError::Certificate(error_msg)
+            })
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Loads the client certificate chain and private key for mutual TLS, if configured.
+///
+/// Files that can't be loaded are always an error, as connecting without the client
+/// certificate would only fail later, at the handshake, or silently bypass mutual TLS.
+fn load_client_identity(tls: &TlsConfig) -> Result<Option<(Vec<Certificate>, PrivateKey)>> {
+    let (Some(cert_path), Some(key_path)) = (&tls.client_cert_path, &tls.client_key_path) else {
+        return Ok(None);
+    };
+
+    let identity =
+        read_certificates(cert_path).and_then(|certs| Ok((certs, read_private_key(key_path)?)));
+
+    identity.map(Some).inspect_err(|e| {
+        log(LogLevel::Error, &format!("Failed to load client certificate: {e}"));
+    })
+}
+
+/// Reads the first private key from a PEM file.
+fn read_private_key(key_path: &str) -> Result<PrivateKey> {
+    log(LogLevel::Debug, &format!("Opening client key file: {key_path}"));
+    let file = File::open(key_path).map_err(|e| {
+        let error_msg = format!("Failed to open client key file: {e}");
+        log(LogLevel::Error, &error_msg);
+        TreblleError::Certificate(error_msg)
+    })?;
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| {
+            let error_msg = format!("Failed to parse client key file: {e}");
+            log(LogLevel::Error, &error_msg);
+            TreblleError::Certificate(error_msg)
+        })?
+        .ok_or_else(|| {
+            let error_msg = "No private key found in the client key file";
+            log(LogLevel::Error, error_msg);
+            TreblleError::Certificate(error_msg.to_string())
+        })?;
+
+    Ok(PrivateKey(key.secret_der().to_vec()))
+}
+
+/// Reads the certificates from a PEM file.
+fn read_certificates(path: &str) -> Result<Vec<Certificate>> {
+    log(LogLevel::Debug, &format!("Opening certificate file: {path}"));
+    let file = File::open(path).map_err(|e| {
+        let error_msg = format!("Failed to open certificate file: {e}");
         log(LogLevel::Error, &error_msg);
         TreblleError::Certificate(error_msg)
     })?;
@@ -62,24 +154,31 @@ fn load_custom_certificates(root_store: &mut RootCertStore, ca_path: &str) -> Re
     log(LogLevel::Debug, "Parsing certificates from file");
 
     let certs = rustls_pemfile::certs(&mut reader)
+        .map(|cert| cert.map(|cert| Certificate(cert.to_vec())))
         .collect::<std::result::Result<Vec<_>, _>>()
         .map_err(|e| {
-            let error_msg = format!("Failed to parse custom root CA file: {e}");
+            let error_msg = format!("Failed to parse certificate file: {e}");
             log(LogLevel::Error, &error_msg);
             TreblleError::Certificate(error_msg)
         })?;
 
     if certs.is_empty() {
-        let error_msg = "No certificates found in the custom root CA file";
-        log(LogLevel::Error, error_msg);
-        return Err(TreblleError::Certificate(error_msg.to_string()));
+        let error_msg = format!("No certificates found in {path}");
+        log(LogLevel::Error, &error_msg);
+        return Err(TreblleError::Certificate(error_msg));
     }
 
     log(LogLevel::Debug, &format!("Found {} certificates", certs.len()));
+    Ok(certs)
+}
+
+/// Loads custom certificates from a specified file path.
+fn load_custom_certificates(root_store: &mut RootCertStore, ca_path: &str) -> Result<()> {
+    let certs = read_certificates(ca_path)?;
 
     for (index, cert) in certs.iter().enumerate() {
         log(LogLevel::Debug, &format!("Adding certificate {} to store", index + 1));
-        root_store.add(&rustls::Certificate(cert.to_vec())).map_err(|e| {
+        root_store.add(cert).map_err(|e| {
             let error_msg = format!("Failed to add custom root CA to store: {e}");
             log(LogLevel::Error, &error_msg);
             TreblleError::Certificate(error_msg)
@@ -104,6 +203,41 @@ fn load_webpki_roots(root_store: &mut RootCertStore) {
     log(LogLevel::Debug, "Webpki root certificates loaded successfully");
 }
 
+/// Verifies server certificates with webpki, then checks the chain against the pins.
+struct PinningVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<SpkiHash>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+
+        let chain = std::iter::once(end_entity).chain(intermediates).map(|cert| cert.0.as_slice());
+        verify_pins(chain, &self.pins).map_err(|e| {
+            log(LogLevel::Error, &e.to_string());
+            rustls::Error::General(e.to_string())
+        })?;
+
+        Ok(verified)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,7 +254,7 @@ mod tests {
     fn test_load_root_certs_without_path() {
         let mut root_store = RootCertStore::empty();
 
-        assert!(load_root_certs(&mut root_store, None).is_ok());
+        assert!(load_root_certs(&mut root_store, None, false).is_ok());
         assert!(!root_store.is_empty());
     }
 
@@ -129,7 +263,28 @@ mod tests {
         let mut root_store = RootCertStore::empty();
         let invalid_path = String::from("/invalid/path/to/cert.pem");
 
-        assert!(load_root_certs(&mut root_store, Some(&invalid_path)).is_ok()); // Should fallback to webpki
+        assert!(load_root_certs(&mut root_store, Some(&invalid_path), false).is_ok()); // Should fallback to webpki
         assert!(!root_store.is_empty());
     }
+
+    #[test]
+    fn test_load_root_certs_with_invalid_path_strict() {
+        let mut root_store = RootCertStore::empty();
+        let invalid_path = String::from("/invalid/path/to/cert.pem");
+
+        assert!(load_root_certs(&mut root_store, Some(&invalid_path), true).is_err());
+        assert!(root_store.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_client_identity_is_an_error() {
+        let tls = TlsConfig {
+            client_cert_path: Some("/invalid/path/client.pem".into()),
+            client_key_path: Some("/invalid/path/client.key".into()),
+            ..Default::default()
+        };
+
+        assert!(load_client_identity(&tls).is_err());
+        assert!(client_config(&tls).is_err());
+    }
 }
@@ -0,0 +1,249 @@
+//! Swappable handle to the plugin configuration.
+//!
+//! The configuration Traefik passes to the plugin is loaded on first use. When it names a
+//! `configFilePath`, the file is re-read every `configReloadIntervalMs` and a changed file
+//! replaces the configuration, e.g. to mask a new field or lower the sample rate. Changes to
+//! the API URLs, TLS, proxy, retries or transport rebuild the HTTP client and transport the
+//! next time a payload is sent.
+//!
+//! An invalid configuration disables the plugin, which then passes requests through
+//! untouched and retries loading periodically. An invalid reload keeps the previous
+//! configuration.
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use treblle_core::{Result, Severity, TreblleError};
+
+use crate::config::WasmConfig;
+use crate::host_functions::host_get_config;
+use crate::logger::{log, LogLevel};
+
+/// Time between two attempts to load a configuration while the plugin is disabled
+const DISABLED_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// When the configuration is next checked for changes
+#[derive(Debug, Clone, Copy)]
+enum Reload {
+    Now,
+    At(Instant),
+    Never,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Active configuration, `None` while the plugin is disabled
+    config: Option<Arc<WasmConfig>>,
+    /// Contents of the config file the active configuration was loaded from
+    file_contents: Option<String>,
+    reload: Reload,
+}
+
+/// Handle to the active configuration, swapped when the config file changes
+#[derive(Debug)]
+pub struct WasmConfigHandle {
+    state: RwLock<State>,
+}
+
+impl Default for WasmConfigHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasmConfigHandle {
+    /// Create a handle that loads the configuration on first use
+    pub const fn new() -> Self {
+        Self {
+            state: RwLock::new(State { config: None, file_contents: None, reload: Reload::Now }),
+        }
+    }
+
+    /// Get the active configuration, loading or reloading it when due.
+    ///
+    /// Returns `None` while the plugin is disabled by an invalid configuration.
+    pub fn get(&self) -> Option<Arc<WasmConfig>> {
+        self.get_at(Instant::now())
+    }
+
+    /// Get the active configuration without loading or reloading it
+    pub fn current(&self) -> Option<Arc<WasmConfig>> {
+        self.state.read().ok()?.config.clone()
+    }
+
+    /// Replace the active configuration
+    pub fn replace(&self, config: WasmConfig) {
+        if let Ok(mut state) = self.state.write() {
+            state.reload = reload_after(Some(&config), Instant::now());
+            state.config = Some(Arc::new(config));
+        }
+    }
+
+    pub(crate) fn get_at(&self, now: Instant) -> Option<Arc<WasmConfig>> {
+        {
+            let state = self.state.read().ok()?;
+            if !state.reload.is_due(now) {
+                return state.config.clone();
+            }
+        }
+
+        let mut state = self.state.write().ok()?;
+        if state.reload.is_due(now) {
+            state.reload(now);
+        }
+        state.config.clone()
+    }
+}
+
+impl Reload {
+    fn is_due(self, now: Instant) -> bool {
+        match self {
+            Reload::Now => true,
+            Reload::At(at) => now >= at,
+            Reload::Never => false,
+        }
+    }
+}
+
+impl State {
+    fn reload(&mut self, now: Instant) {
+        match self.load() {
+            Ok(None) => log(LogLevel::Debug, "Configuration file is unchanged"),
+            Ok(Some((config, file_contents))) => {
+                let action = if self.config.is_some() { "Reloaded" } else { "Loaded" };
+                log(LogLevel::Info, &format!("{action} and validated configuration"));
+                log(LogLevel::Debug, &format!("Using config: {config:?}"));
+                if config.core.is_enabled() {
+                    for diagnostic in config.core.validate().diagnostics() {
+                        let level = match diagnostic.severity {
+                            Severity::Error => LogLevel::Error,
+                            Severity::Warning => LogLevel::Warn,
+                        };
+                        log(level, &format!("Treblle configuration {diagnostic}"));
+                    }
+                }
+                self.config = Some(Arc::new(config));
+                self.file_contents = file_contents;
+            }
+            Err(e) if self.config.is_some() => log(
+                LogLevel::Error,
+                &format!("Failed to reload configuration, keeping the previous one: {e}"),
+            ),
+            Err(e) => log(
+                LogLevel::Error,
+                &format!("Treblle is DISABLED, no data will be sent until this is fixed: {e}"),
+            ),
+        }
+
+        self.reload = reload_after(self.config.as_deref(), now);
+    }
+
+    /// Load the configuration, or `None` if its file hasn't changed since the last load
+    fn load(&self) -> Result<Option<(WasmConfig, Option<String>)>> {
+        let host_config = host_get_config()
+            .map_err(|e| TreblleError::Config(format!("Failed to get config from host: {e}")))?;
+
+        let file_contents = match WasmConfig::file_path_of(&host_config) {
+            Some(path) => Some(std::fs::read_to_string(&path).map_err(|e| {
+                TreblleError::Config(format!("Failed to read config file {path}: {e}"))
+            })?),
+            None => None,
+        };
+
+        if self.config.is_some() && file_contents == self.file_contents {
+            return Ok(None);
+        }
+
+        let config = WasmConfig::from_sources(&host_config, file_contents.as_deref())?;
+        Ok(Some((config, file_contents)))
+    }
+}
+
+/// When to check a configuration for changes next
+fn reload_after(config: Option<&WasmConfig>, now: Instant) -> Reload {
+    match config {
+        Some(config) if config.config_file_path().is_some() => {
+            Reload::At(now + config.config_reload_interval())
+        }
+        Some(_) => Reload::Never,
+        None => Reload::At(now + DISABLED_RETRY_INTERVAL),
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::mock_host::{exchange, with_host};
+    use serde_json::json;
+
+    /// Run `f` with the host passing `config` to the plugin
+    fn with_host_config<T, F>(config: &serde_json::Value, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let _exchange = exchange();
+        with_host(|host| host.config = Some(config.to_string()));
+        let result = f();
+        with_host(|host| host.config = None);
+        result
+    }
+
+    #[test]
+    fn test_invalid_config_disables_the_plugin() {
+        with_host_config(&json!({ "projectId": "no_key" }), || {
+            let handle = WasmConfigHandle::new();
+            let now = Instant::now();
+            assert!(handle.get_at(now).is_none());
+
+            // Loading is retried later rather than falling back to a placeholder key
+            with_host(|host| host.config = Some(json!({ "apiKey": "test_key" }).to_string()));
+            assert!(handle.get_at(now).is_none());
+            let config = handle.get_at(now + DISABLED_RETRY_INTERVAL).unwrap();
+            assert_eq!(config.core.api_key, "test_key");
+        });
+    }
+
+    #[test]
+    fn test_config_file_is_reloaded() {
+        let path =
+            std::env::temp_dir().join(format!("treblle-config-handle-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"sampleRate": 0.5}"#).unwrap();
+
+        let host_config = json!({
+            "apiKey": "test_key",
+            "configFilePath": path,
+            "configReloadIntervalMs": 1000,
+        });
+        with_host_config(&host_config, || {
+            let handle = WasmConfigHandle::new();
+            let start = Instant::now();
+            let config = handle.get_at(start).unwrap();
+            assert!((config.sample_rate() - 0.5).abs() < f64::EPSILON);
+
+            // Changes are only picked up once the interval has passed
+            std::fs::write(&path, r#"{"sampleRate": 0.25, "maskedFields": ["ssn"]}"#).unwrap();
+            assert!(Arc::ptr_eq(&config, &handle.get_at(start).unwrap()));
+
+            let later = start + Duration::from_secs(1);
+            let reloaded = handle.get_at(later).unwrap();
+            assert!((reloaded.sample_rate() - 0.25).abs() < f64::EPSILON);
+            assert!(reloaded.core.should_mask_field("ssn"));
+
+            // An invalid file keeps the previous configuration
+            std::fs::write(&path, r#"{"sampleRate": 4}"#).unwrap();
+            let kept = handle.get_at(later + Duration::from_secs(1)).unwrap();
+            assert!(Arc::ptr_eq(&reloaded, &kept));
+        });
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replace() {
+        let handle = WasmConfigHandle::new();
+        assert!(handle.current().is_none());
+
+        handle.replace(WasmConfig::builder().api_key("replaced").build().unwrap());
+        assert_eq!(handle.get().unwrap().core.api_key, "replaced");
+    }
+}
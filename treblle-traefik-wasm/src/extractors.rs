@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use chrono::Utc;
 use serde_json::Value;
 use std::collections::HashMap;
@@ -16,16 +17,18 @@ use crate::{
 };
 
 use treblle_core::{
-    constants::MAX_BODY_SIZE, utils::extract_ip_from_headers, ErrorInfo, RequestInfo, ResponseInfo,
-    ServerInfo,
+    utils::extract_ip_from_headers, CapturedBody, ErrorInfo, RequestInfo, ResponseInfo, ServerInfo,
 };
 
 /// WASM data extractor for Treblle middleware
 pub struct WasmExtractor;
 
-/// Type aliases for empty request/response types
-pub type Request = ();
-pub type Response = ();
+/// Largest body captured in bytes, the only request/response state not read from the host
+#[derive(Clone, Copy, Debug)]
+pub struct BodyLimit(pub usize);
+
+pub type Request = BodyLimit;
+pub type Response = BodyLimit;
 
 /// Clean up JSON Value to avoid String() wrapping
 fn clean_json_value(value: Value) -> Value {
@@ -44,8 +47,14 @@ fn clean_json_value(value: Value) -> Value {
 }
 
 impl WasmExtractor {
-    /// Extract and process request/response body
-    fn extract_body(kind: u32) -> Option<Value> {
+    /// Read the request/response body, keeping at most `limit` bytes of it.
+    ///
+    /// Bodies with a limit of 0 aren't captured, so they aren't read from the host at all.
+    fn capture_body(kind: u32, limit: BodyLimit) -> Option<CapturedBody> {
+        if limit.0 == 0 {
+            log(LogLevel::Debug, &format!("Body capture disabled for kind: {kind}"));
+            return None;
+        }
         log(LogLevel::Debug, &format!("Starting body extraction for kind: {kind}"));
 
         match host_read_body(kind) {
@@ -58,39 +67,26 @@ impl WasmExtractor {
                     return None;
                 }
 
-                if body.len() > MAX_BODY_SIZE {
-                    log(
-                        LogLevel::Debug,
-                        &format!("Body size {} exceeds maximum {}", body.len(), MAX_BODY_SIZE),
-                    );
-                    return None;
-                }
-
                 // Write body back for next middleware if buffering is disabled
-                if !CONFIG.buffer_request {
+                if !CONFIG.current().is_some_and(|config| config.buffer_request) {
                     log(LogLevel::Debug, "Response buffering disabled, writing body back");
                     if let Err(e) = host_write_body(kind, &body) {
                         log(LogLevel::Error, &format!("Failed to write back body: {e}"));
                     }
                 }
 
-                // Parse JSON and ensure proper handling of string values
-                match serde_json::from_slice(&body) {
-                    Ok(json) => {
-                        log(LogLevel::Debug, &format!("Successfully parsed JSON body: {:?}", json));
-                        Some(clean_json_value(json))
-                    }
-                    Err(e) => {
-                        log(
-                            LogLevel::Warn,
-                            &format!(
-                                "Failed to parse JSON body: {e}, raw body: {:?}",
-                                String::from_utf8_lossy(&body)
-                            ),
-                        );
-                        None
-                    }
+                if body.len() > limit.0 {
+                    log(
+                        LogLevel::Debug,
+                        &format!(
+                            "Body size {} exceeds maximum {}, truncating",
+                            body.len(),
+                            limit.0
+                        ),
+                    );
                 }
+
+                Some(CapturedBody::complete(Bytes::from(body), limit.0))
             }
             Err(e) => {
                 log(LogLevel::Error, &format!("Failed to read body: {e}"));
@@ -99,33 +95,45 @@ impl WasmExtractor {
         }
     }
 
+    /// Parse a captured body, truncated bodies are wrapped in a marker object
+    fn body_json(captured: &CapturedBody) -> Option<Value> {
+        // Parse JSON and ensure proper handling of string values
+        let Some(json) = captured.to_json() else {
+            log(
+                LogLevel::Warn,
+                &format!(
+                    "Failed to parse JSON body, raw body: {:?}",
+                    String::from_utf8_lossy(captured.bytes())
+                ),
+            );
+            return None;
+        };
+
+        log(LogLevel::Debug, &format!("Successfully parsed JSON body: {json:?}"));
+        Some(clean_json_value(json))
+    }
+
     /// Extract headers from WASM host
     fn extract_headers(kind: u32) -> HashMap<String, String> {
         log(LogLevel::Debug, &format!("Starting header extraction for kind: {kind}"));
 
         match host_get_header_names(kind) {
-            Ok(header_names) => {
-                let mut headers = HashMap::new();
-
-                // Split on null bytes and commas, clean up empty strings
-                let names: Vec<_> =
-                    header_names.split(['\0', ',']).filter(|s| !s.is_empty()).collect();
-
-                log(LogLevel::Debug, &format!("Found headers: {:?}", names));
+            Ok(names) => {
+                log(LogLevel::Debug, &format!("Found headers: {names:?}"));
 
+                let mut headers = HashMap::new();
                 for name in names {
-                    let name = name.trim();
-                    if !name.is_empty() {
-                        match host_get_header_values(kind, name) {
-                            Ok(values) => {
-                                log(LogLevel::Debug, &format!("Header '{name}' = '{values}'"));
-                                headers.insert(name.to_string(), values);
-                            }
-                            Err(e) => log(
-                                LogLevel::Error,
-                                &format!("Failed to get values for header '{name}': {e}"),
-                            ),
+                    match host_get_header_values(kind, &name) {
+                        Ok(values) => {
+                            // Multiple values are reported like a folded header
+                            let values = values.join(", ");
+                            log(LogLevel::Debug, &format!("Header '{name}' = '{values}'"));
+                            headers.insert(name, values);
                         }
+                        Err(e) => log(
+                            LogLevel::Error,
+                            &format!("Failed to get values for header '{name}': {e}"),
+                        ),
                     }
                 }
                 headers
@@ -142,7 +150,7 @@ impl treblle_core::extractors::TreblleExtractor for WasmExtractor {
     type Request = Request;
     type Response = Response;
 
-    fn extract_request_info(_req: &Self::Request) -> RequestInfo {
+    fn extract_request_info(req: &Self::Request) -> RequestInfo {
         log(LogLevel::Debug, "Starting request info extraction");
 
         let method = host_get_method().unwrap_or_else(|e| {
@@ -175,43 +183,61 @@ impl treblle_core::extractors::TreblleExtractor for WasmExtractor {
             timestamp: Utc::now(),
             ip,
             url,
+            route_path: None,
             user_agent,
             method,
             headers,
-            body: Self::extract_body(REQUEST_KIND),
+            body: Self::capture_body(REQUEST_KIND, *req).as_ref().and_then(Self::body_json),
         };
 
-        log(LogLevel::Debug, &format!("Completed request info extraction: {:?}", info));
+        log(LogLevel::Debug, &format!("Completed request info extraction: {info:?}"));
 
         info
     }
 
-    fn extract_response_info(_res: &Self::Response, duration: Duration) -> ResponseInfo {
+    fn extract_response_info(res: &Self::Response, duration: Duration) -> ResponseInfo {
         log(LogLevel::Debug, "Starting response info extraction");
 
-        let body = Self::extract_body(RESPONSE_KIND);
-        log(LogLevel::Debug, &format!("Extracted response body: {:?}", body));
+        let headers = Self::extract_headers(RESPONSE_KIND);
+
+        // Non-JSON bodies are left untouched, only their metadata is reported
+        let is_json = headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case("content-type")
+                && value.to_lowercase().contains("application/json")
+        });
+        let captured = if is_json { Self::capture_body(RESPONSE_KIND, *res) } else { None };
+        let body = captured.as_ref().and_then(Self::body_json);
+        log(LogLevel::Debug, &format!("Extracted response body: {body:?}"));
 
-        let size = body.as_ref().map(|b| b.to_string().len() as u64).unwrap_or(0);
+        // Truncated bodies report their original size rather than the marker's
+        let size = if let Some(captured) = captured.filter(CapturedBody::is_truncated) {
+            captured.size().unwrap_or_default()
+        } else {
+            body.as_ref().map_or(0, |b| b.to_string().len() as u64)
+        };
 
         let info = ResponseInfo {
-            headers: Self::extract_headers(RESPONSE_KIND),
+            headers,
             code: u16::try_from(host_get_status_code()).expect("Failed to extract status code"),
             size,
             load_time: duration.as_secs_f64(),
             body,
         };
 
-        log(LogLevel::Debug, &format!("Completed response info extraction: {:?}", info));
+        log(LogLevel::Debug, &format!("Completed response info extraction: {info:?}"));
 
         info
     }
 
-    fn extract_error_info(_res: &Self::Response) -> Option<Vec<ErrorInfo>> {
+    fn extract_error_info(res: &Self::Response) -> Option<Vec<ErrorInfo>> {
         let status_code = host_get_status_code();
 
         if status_code >= 400 {
-            let body = Self::extract_body(RESPONSE_KIND);
+            // Truncated bodies have no message to read
+            let body = Self::capture_body(RESPONSE_KIND, *res)
+                .filter(|captured| !captured.is_truncated())
+                .as_ref()
+                .and_then(Self::body_json);
 
             // Try to extract error message from body if available
             let message = body
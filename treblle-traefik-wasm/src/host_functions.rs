@@ -10,7 +10,7 @@ use treblle_core::{Result, TreblleError};
 // - https://github.com/http-wasm/http-wasm-host-go/blob/main/api/handler/handler.go
 // - https://github.com/http-wasm/http-wasm-host-go/blob/main/api/handler/wasm.go
 // - https://github.com/http-wasm/http-wasm/blob/main/content/http-handler-abi.md
-#[cfg(feature = "wasm")]
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
 #[link(wasm_import_module = "http_handler")]
 extern "C" {
     fn log(level: i32, message: *const u8, message_len: u32);
@@ -25,6 +25,20 @@ extern "C" {
         buf: *mut u8,
         buf_limit: i32,
     ) -> i64;
+    fn set_header_value(
+        header_kind: u32,
+        name_ptr: *const u8,
+        name_len: u32,
+        value_ptr: *const u8,
+        value_len: u32,
+    );
+    fn add_header_value(
+        header_kind: u32,
+        name_ptr: *const u8,
+        name_len: u32,
+        value_ptr: *const u8,
+        value_len: u32,
+    );
 
     // Body only methods
     fn read_body(body_kind: u32, ptr: *mut u8, buf_limit: u32) -> i64;
@@ -32,7 +46,7 @@ extern "C" {
 
     // Request only methods
     fn get_method(buf: *mut u8, buf_limit: i32) -> i32;
-    fn get_uri(buf: *mut u8, buf_limit: u32) -> i32;
+    fn get_uri(buf: *mut u8, buf_limit: i32) -> i32;
     fn get_protocol_version(buf: *mut u8, buf_limit: i32) -> i32;
     fn get_source_addr(buf: *mut u8, buf_limit: i32) -> i32;
 
@@ -40,6 +54,14 @@ extern "C" {
     fn get_status_code() -> u32;
 }
 
+// Outside of WASM, the host functions are mocked so the middleware can be tested natively
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mock_host::{
+    add_header_value, enable_features, get_config, get_header_names, get_header_values, get_method,
+    get_protocol_version, get_source_addr, get_status_code, get_uri, log, read_body,
+    set_header_value, write_body,
+};
+
 /// Enables features in the host environment.
 ///
 /// # Arguments
@@ -51,6 +73,7 @@ extern "C" {
 /// Returns a bitfield of successfully enabled features.
 #[cfg(feature = "wasm")]
 pub fn host_enable_features(features: u32) -> Result<u32> {
+    // SAFETY: the call takes no pointers
     let enabled = unsafe { enable_features(features) };
 
     // Verify the requested features were enabled
@@ -70,7 +93,10 @@ pub fn host_enable_features(features: u32) -> Result<u32> {
 /// Returns the configuration as a string, or an error if retrieval fails.
 #[cfg(feature = "wasm")]
 pub fn host_get_config() -> Result<String> {
-    read_from_buffer(|buf, buf_limit| unsafe { get_config(buf, buf_limit) })
+    read_from_buffer(|buf, buf_limit| {
+        // SAFETY: the buffer is valid for `buf_limit` bytes for the duration of the call
+        i64::from(unsafe { get_config(buf, buf_limit) })
+    })
 }
 
 pub mod log {
@@ -88,6 +114,7 @@ pub mod log {
         let sanitized_message = message.replace('\0', "");
 
         if let Ok(c_message) = CString::new(sanitized_message) {
+            // SAFETY: the message is valid for its length for the duration of the call
             unsafe {
                 log(level, c_message.as_ptr() as *const u8, c_message.as_bytes().len() as u32);
             }
@@ -96,11 +123,13 @@ pub mod log {
 }
 
 pub mod headers {
-    use crate::host_functions::{get_header_names, get_header_values, read_from_buffer};
+    use crate::host_functions::{
+        add_header_value, get_header_names, get_header_values, read_values, set_header_value,
+    };
     use std::ffi::CString;
     use treblle_core::{Result, TreblleError};
 
-    /// Retrieves the header names for a given header kind if it isn't larger than `buf_limit`.
+    /// Retrieves the header names for a given header kind.
     ///
     /// # Arguments
     ///
@@ -108,15 +137,16 @@ pub mod headers {
     ///
     /// # Returns
     ///
-    /// Returns a comma-separated string of header names, or an error if retrieval fails.
+    /// Returns the header names, or an error if retrieval fails.
     #[cfg(feature = "wasm")]
-    pub fn host_get_header_names(header_kind: u32) -> Result<String> {
-        read_from_buffer(|buf, buf_limit| unsafe {
-            get_header_names(header_kind, buf, buf_limit) as i32
+    pub fn host_get_header_names(header_kind: u32) -> Result<Vec<String>> {
+        read_values(|buf, buf_limit| {
+            // SAFETY: the buffer is valid for `buf_limit` bytes for the duration of the call
+            unsafe { get_header_names(header_kind, buf, buf_limit) }
         })
     }
 
-    /// Retrieves the values for a specific header if it isn't larger than `buf_limit`.
+    /// Retrieves the values for a specific header.
     ///
     /// # Arguments
     ///
@@ -125,15 +155,17 @@ pub mod headers {
     ///
     /// # Returns
     ///
-    /// Returns a comma-separated string of header values, or an error if retrieval fails.
+    /// Returns each value of the header, or an error if retrieval fails.
     #[cfg(feature = "wasm")]
-    pub fn host_get_header_values(header_kind: u32, name: &str) -> Result<String> {
+    pub fn host_get_header_values(header_kind: u32, name: &str) -> Result<Vec<String>> {
         let sanitized_name = name.replace('\0', "");
         let c_name = CString::new(sanitized_name)
             .map_err(|e| TreblleError::HostFunction(format!("Invalid header name: {e}")))?;
 
-        read_from_buffer(|buf, buf_limit| {
-            let result = unsafe {
+        read_values(|buf, buf_limit| {
+            // SAFETY: the name is valid for its length and the buffer for `buf_limit` bytes
+            // for the duration of the call
+            unsafe {
                 get_header_values(
                     header_kind,
                     c_name.as_ptr() as *const u8,
@@ -141,16 +173,62 @@ pub mod headers {
                     buf,
                     buf_limit,
                 )
-            };
-
-            // Check for negative values indicating errors
-            if result < 0 {
-                return -1;
             }
-
-            result as i32
         })
     }
+
+    /// Sets a header, replacing any values it already has.
+    ///
+    /// Response headers set while handling the request are sent with the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_kind` - The kind of headers to modify (0 for request, 1 for response).
+    /// * `name` - The name of the header to set.
+    /// * `value` - The value to set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name or value can't be passed to the host.
+    #[cfg(feature = "wasm")]
+    pub fn host_set_header_value(header_kind: u32, name: &str, value: &str) -> Result<()> {
+        let (name_len, value_len) = (string_len(name)?, string_len(value)?);
+        // SAFETY: both pointers are valid for the given lengths for the whole call
+        unsafe {
+            set_header_value(header_kind, name.as_ptr(), name_len, value.as_ptr(), value_len);
+        }
+
+        Ok(())
+    }
+
+    /// Adds a value to a header, keeping the values it already has.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_kind` - The kind of headers to modify (0 for request, 1 for response).
+    /// * `name` - The name of the header to add a value to.
+    /// * `value` - The value to add.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name or value can't be passed to the host.
+    #[cfg(feature = "wasm")]
+    pub fn host_add_header_value(header_kind: u32, name: &str, value: &str) -> Result<()> {
+        let (name_len, value_len) = (string_len(name)?, string_len(value)?);
+        // SAFETY: both pointers are valid for the given lengths for the whole call
+        unsafe {
+            add_header_value(header_kind, name.as_ptr(), name_len, value.as_ptr(), value_len);
+        }
+
+        Ok(())
+    }
+
+    /// Length of a string passed to the host
+    #[cfg(feature = "wasm")]
+    fn string_len(s: &str) -> Result<u32> {
+        u32::try_from(s.len())
+            .map_err(|_| TreblleError::HostFunction(format!("Header too long: {} bytes", s.len())))
+    }
 }
 
 pub mod body {
@@ -173,6 +251,7 @@ pub mod body {
 
         loop {
             // read_body is stateful, so repeated calls read what's remaining in the stream, as opposed to starting from zero
+            // SAFETY: the buffer is valid for its length for the duration of the call
             let result = unsafe { read_body(body_kind, buffer.as_mut_ptr(), buffer.len() as u32) };
             // The high 32-bits is EOF (1 or 0) and the low 32-bits is the length
             let eof = (result >> 32) & 1;
@@ -202,6 +281,7 @@ pub mod body {
     ///
     /// Returns Result<(), TreblleError>
     pub fn host_write_body(body_kind: u32, body: &[u8]) -> Result<()> {
+        // SAFETY: the body is valid for its length for the duration of the call
         unsafe {
             write_body(body_kind, body.as_ptr(), body.len() as u32);
         }
@@ -223,7 +303,10 @@ pub mod request {
     /// Returns the HTTP method as a string, e.g. `"GET"`, or an error if retrieval fails.
     #[cfg(feature = "wasm")]
     pub fn host_get_method() -> Result<String> {
-        read_from_buffer(|buf, buf_limit| unsafe { get_method(buf, buf_limit) })
+        read_from_buffer(|buf, buf_limit| {
+            // SAFETY: the buffer is valid for `buf_limit` bytes for the duration of the call
+            i64::from(unsafe { get_method(buf, buf_limit) })
+        })
     }
 
     /// Retrieves the URI of the current request if it isn't larger than `buf_limit`.
@@ -240,7 +323,10 @@ pub mod request {
     /// Returns the URI as a string, e.g. `"/v1.0/hi?name=panda"`, or an error if retrieval fails.
     #[cfg(feature = "wasm")]
     pub fn host_get_uri() -> Result<String> {
-        read_from_buffer(|buf, buf_limit| unsafe { get_uri(buf, buf_limit as u32) })
+        read_from_buffer(|buf, buf_limit| {
+            // SAFETY: the buffer is valid for `buf_limit` bytes for the duration of the call
+            i64::from(unsafe { get_uri(buf, buf_limit) })
+        })
     }
 
     /// Retrieves the address of the current request if it isn't larger than `buf_limit`.
@@ -251,7 +337,10 @@ pub mod request {
     ///  Returns the source address as a string, or an error if retrieval fails.
     #[cfg(feature = "wasm")]
     pub fn host_get_source_addr() -> Result<String> {
-        read_from_buffer(|buf, buf_limit| unsafe { get_source_addr(buf, buf_limit) })
+        read_from_buffer(|buf, buf_limit| {
+            // SAFETY: the buffer is valid for `buf_limit` bytes for the duration of the call
+            i64::from(unsafe { get_source_addr(buf, buf_limit) })
+        })
     }
 
     /// Retrieves the protocol version of the current request if it isn't larger than `buf_limit`.
@@ -264,7 +353,10 @@ pub mod request {
     /// The result is its length in bytes.
     #[cfg(feature = "wasm")]
     pub fn host_get_protocol_version() -> Result<String> {
-        read_from_buffer(|buf, buf_limit| unsafe { get_protocol_version(buf, buf_limit) })
+        read_from_buffer(|buf, buf_limit| {
+            // SAFETY: the buffer is valid for `buf_limit` bytes for the duration of the call
+            i64::from(unsafe { get_protocol_version(buf, buf_limit) })
+        })
     }
 }
 
@@ -278,28 +370,148 @@ pub mod response {
     /// Returns the status code as an u32, e.g. 200.
     #[cfg(feature = "wasm")]
     pub fn host_get_status_code() -> u32 {
+        // SAFETY: the call takes no pointers
         unsafe { get_status_code() }
     }
 }
 
+/// Size of the buffer values are first read into from the host
+const INITIAL_BUFFER_SIZE: usize = 4096;
+
 /// Helper function to read data from the host into a buffer.
 ///
+/// Following the http-wasm convention, the host returns the length of a value without
+/// writing it if it's larger than `buf_limit`. The read is then retried with a buffer
+/// of the reported size.
+///
 /// # Arguments
 ///
-/// * `read_fn` - A function that reads data into a buffer and returns the number of bytes read.
+/// * `read_fn` - A function that reads data into a buffer and returns its length in the
+///   low 32 bits, e.g. `count << 32 | len` for header functions.
 ///
 /// # Returns
 ///
-/// Returns the read data as a string, or an error if reading fails.
+/// Returns the read data, or an error if reading fails.
+#[cfg(feature = "wasm")]
+fn read_bytes<F: Fn(*mut u8, i32) -> i64>(read_fn: F) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; INITIAL_BUFFER_SIZE];
+
+    // A second attempt can only come up short if the value grew in between
+    for _ in 0..2 {
+        let buf_limit = i32::try_from(buffer.len())
+            .map_err(|_| TreblleError::HostFunction("Value is too large to read".to_string()))?;
+
+        let result = read_fn(buffer.as_mut_ptr(), buf_limit);
+        let len = usize::try_from(result & 0xffff_ffff)
+            .ok()
+            .filter(|_| result >= 0)
+            .ok_or_else(|| TreblleError::HostFunction("Failed to read from buffer".to_string()))?;
+
+        if len <= buffer.len() {
+            buffer.truncate(len);
+            return Ok(buffer);
+        }
+        buffer.resize(len, 0);
+    }
+
+    Err(TreblleError::HostFunction("Value changed size while being read".to_string()))
+}
+
+/// Reads a UTF-8 string from the host, see [`read_bytes`]
 #[cfg(feature = "wasm")]
-fn read_from_buffer<F: Fn(*mut u8, i32) -> i32>(read_fn: F) -> Result<String> {
-    let mut buffer = vec![0u8; 4096];
-    let len = read_fn(buffer.as_mut_ptr(), buffer.len() as i32);
-
-    if len < 0 {
-        Err(TreblleError::HostFunction("Failed to read from buffer".to_string()))
-    } else {
-        buffer.truncate(len as usize);
-        String::from_utf8(buffer).map_err(|e| TreblleError::HostFunction(e.to_string()))
+fn read_from_buffer<F: Fn(*mut u8, i32) -> i64>(read_fn: F) -> Result<String> {
+    String::from_utf8(read_bytes(read_fn)?).map_err(|e| TreblleError::HostFunction(e.to_string()))
+}
+
+/// Reads NUL-terminated values from the host, see [`read_bytes`]
+#[cfg(feature = "wasm")]
+fn read_values<F: Fn(*mut u8, i32) -> i64>(read_fn: F) -> Result<Vec<String>> {
+    let values = read_from_buffer(read_fn)?;
+    Ok(values.split_terminator('\0').map(ToString::to_string).collect())
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::headers::{
+        host_add_header_value, host_get_header_names, host_get_header_values, host_set_header_value,
+    };
+    use super::request::host_get_uri;
+    use super::*;
+    use crate::constants::http::REQUEST_KIND;
+    use crate::mock_host::{exchange, with_host};
+    use std::cell::Cell;
+
+    #[test]
+    fn test_read_bytes_retries_with_reported_length() {
+        let value = vec![b'a'; INITIAL_BUFFER_SIZE * 2 + 1];
+        let calls = Cell::new(0);
+
+        let read = read_bytes(|buf, buf_limit| {
+            calls.set(calls.get() + 1);
+            if usize::try_from(buf_limit).is_ok_and(|limit| value.len() <= limit) {
+                // SAFETY: the buffer holds `buf_limit` bytes
+                unsafe { std::ptr::copy_nonoverlapping(value.as_ptr(), buf, value.len()) };
+            }
+            (3 << 32) | i64::try_from(value.len()).unwrap()
+        })
+        .unwrap();
+
+        assert_eq!(read, value);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_read_bytes_fails_on_growing_value() {
+        let result = read_bytes(|_, buf_limit| i64::from(buf_limit) + 1);
+        assert!(matches!(result, Err(TreblleError::HostFunction(_))));
+
+        assert!(read_bytes(|_, _| -1).is_err());
+    }
+
+    #[test]
+    fn test_large_values_are_read_whole() {
+        let _exchange = exchange();
+        let uri = format!("/search?q={}", "a".repeat(10_000));
+        let names: Vec<String> = (0..300).map(|i| format!("x-custom-header-{i}")).collect();
+
+        with_host(|host| {
+            let headers = names.iter().map(|name| (name.as_str(), "value"));
+            host.set_request("GET", &uri, headers, b"");
+        });
+
+        assert_eq!(host_get_uri().unwrap(), uri);
+        assert_eq!(host_get_header_names(REQUEST_KIND).unwrap(), names);
+    }
+
+    #[test]
+    fn test_multi_valued_headers() {
+        let _exchange = exchange();
+        let headers = [("Accept", "text/html"), ("accept", "application/json"), ("x-list", "a, b")];
+        with_host(|host| host.set_request("GET", "/", headers, b""));
+
+        assert_eq!(host_get_header_names(REQUEST_KIND).unwrap(), ["Accept", "x-list"]);
+        assert_eq!(
+            host_get_header_values(REQUEST_KIND, "accept").unwrap(),
+            ["text/html", "application/json"]
+        );
+        assert_eq!(host_get_header_values(REQUEST_KIND, "x-list").unwrap(), ["a, b"]);
+        assert!(host_get_header_values(REQUEST_KIND, "missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_and_add_header_values() {
+        let _exchange = exchange();
+        with_host(|host| host.set_request("GET", "/", [("Accept", "text/html")], b""));
+
+        host_add_header_value(REQUEST_KIND, "accept", "application/json").unwrap();
+        assert_eq!(
+            host_get_header_values(REQUEST_KIND, "accept").unwrap(),
+            ["text/html", "application/json"]
+        );
+
+        host_set_header_value(REQUEST_KIND, "ACCEPT", "*/*").unwrap();
+        host_set_header_value(REQUEST_KIND, "x-request-id", "abc").unwrap();
+        assert_eq!(host_get_header_values(REQUEST_KIND, "accept").unwrap(), ["*/*"]);
+        assert_eq!(host_get_header_names(REQUEST_KIND).unwrap(), ["ACCEPT", "x-request-id"]);
     }
 }
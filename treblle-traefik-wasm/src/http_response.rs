@@ -0,0 +1,377 @@
+//! Minimal HTTP/1.1 response parser for the WASI HTTP client.
+//!
+//! Parses the status line, headers and body of a response read from a socket.
+//! Bodies delimited by `Content-Length`, chunked transfer encoding or the end of
+//! the connection are supported.
+
+use treblle_core::constants::MAX_BODY_SIZE;
+use treblle_core::TreblleError;
+
+/// Maximum size of the status line and headers
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// A parsed HTTP response
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// Minor version of the protocol (`1` for HTTP/1.1)
+    pub minor_version: u8,
+    /// Status code
+    pub status: u16,
+    /// Header names and values, in the order received
+    pub headers: Vec<(String, String)>,
+    /// Decoded body
+    pub body: Vec<u8>,
+    /// Whether the body was delimited by the end of the connection
+    read_until_close: bool,
+}
+
+impl HttpResponse {
+    /// Get the value of a header, matching the name case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the status code is in the 2xx range
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Whether the connection can be reused for another request
+    pub fn keep_alive(&self) -> bool {
+        if self.read_until_close {
+            return false;
+        }
+
+        match self.header("connection").map(str::to_ascii_lowercase) {
+            Some(value) if value.contains("close") => false,
+            Some(value) if value.contains("keep-alive") => true,
+            _ => self.minor_version >= 1,
+        }
+    }
+}
+
+/// Parse a response from the bytes read so far.
+///
+/// Returns `Ok(None)` while the response is incomplete, or the response together with
+/// the number of bytes it occupied. `eof` tells whether the connection has been closed,
+/// which completes a body that isn't delimited otherwise.
+///
+/// # Errors
+///
+/// Returns [`TreblleError::Http`] if the response is malformed or exceeds the size limits.
+pub fn parse_response(
+    buf: &[u8],
+    eof: bool,
+) -> Result<Option<(HttpResponse, usize)>, TreblleError> {
+    let mut offset = 0;
+
+    // Interim 1xx responses carry no body and are followed by the final response
+    loop {
+        let Some(head_end) = find(&buf[offset..], b"\r\n\r\n") else {
+            if buf.len() - offset > MAX_HEAD_SIZE {
+                return Err(malformed("response headers too large"));
+            }
+            return incomplete(eof);
+        };
+
+        let head = std::str::from_utf8(&buf[offset..offset + head_end])
+            .map_err(|_| malformed("response headers are not valid UTF-8"))?;
+        let (minor_version, status, headers) = parse_head(head)?;
+        let body_start = offset + head_end + 4;
+
+        if (100..200).contains(&status) && status != 101 {
+            offset = body_start;
+            continue;
+        }
+
+        let response = |body: Vec<u8>, read_until_close: bool| HttpResponse {
+            minor_version,
+            status,
+            headers: headers.clone(),
+            body,
+            read_until_close,
+        };
+
+        // Responses without a body
+        if status == 204 || status == 304 {
+            return Ok(Some((response(Vec::new(), false), body_start)));
+        }
+
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim())
+        };
+
+        let chunked = header("transfer-encoding")
+            .is_some_and(|value| value.to_ascii_lowercase().ends_with("chunked"));
+
+        if chunked {
+            return match parse_chunked(&buf[body_start..])? {
+                Some((body, len)) => Ok(Some((response(body, false), body_start + len))),
+                None => incomplete(eof),
+            };
+        }
+
+        if let Some(length) = header("content-length") {
+            let length: usize =
+                length.parse().map_err(|_| malformed("invalid Content-Length header"))?;
+            if length > MAX_BODY_SIZE {
+                return Err(malformed("response body too large"));
+            }
+
+            let body_end = body_start + length;
+            if buf.len() < body_end {
+                return incomplete(eof);
+            }
+            return Ok(Some((response(buf[body_start..body_end].to_vec(), false), body_end)));
+        }
+
+        // Without a length the body runs until the connection is closed
+        if buf.len() - body_start > MAX_BODY_SIZE {
+            return Err(malformed("response body too large"));
+        }
+        if !eof {
+            return Ok(None);
+        }
+        return Ok(Some((response(buf[body_start..].to_vec(), true), buf.len())));
+    }
+}
+
+/// Parse a response that has no body, such as the reply to a `CONNECT` request.
+///
+/// Returns `Ok(None)` while the headers are incomplete, or the response together with
+/// the number of bytes its head occupied.
+///
+/// # Errors
+///
+/// Returns [`TreblleError::Http`] if the response is malformed or its head is too large.
+pub fn parse_response_head(
+    buf: &[u8],
+    eof: bool,
+) -> Result<Option<(HttpResponse, usize)>, TreblleError> {
+    let Some(head_end) = find(buf, b"\r\n\r\n") else {
+        if buf.len() > MAX_HEAD_SIZE {
+            return Err(malformed("response headers too large"));
+        }
+        return incomplete(eof);
+    };
+
+    let head = std::str::from_utf8(&buf[..head_end])
+        .map_err(|_| malformed("response headers are not valid UTF-8"))?;
+    let (minor_version, status, headers) = parse_head(head)?;
+    let response =
+        HttpResponse { minor_version, status, headers, body: Vec::new(), read_until_close: false };
+
+    Ok(Some((response, head_end + 4)))
+}
+
+/// Minor version, status code and headers of a response
+type Head = (u8, u16, Vec<(String, String)>);
+
+/// Parse the status line and headers
+fn parse_head(head: &str) -> Result<Head, TreblleError> {
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+
+    let mut parts = status_line.splitn(3, ' ');
+    let minor_version = match parts.next() {
+        Some("HTTP/1.1") => 1,
+        Some("HTTP/1.0") => 0,
+        _ => return Err(malformed("invalid status line")),
+    };
+    let status = parts
+        .next()
+        .filter(|code| code.len() == 3)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| malformed("invalid status code"))?;
+
+    let headers = lines
+        .map(|line| {
+            line.split_once(':')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| malformed("invalid header line"))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((minor_version, status, headers))
+}
+
+/// Decode a chunked body, returning it with the number of bytes it occupied
+fn parse_chunked(buf: &[u8]) -> Result<Option<(Vec<u8>, usize)>, TreblleError> {
+    let mut body = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let Some(line_end) = find(&buf[offset..], b"\r\n") else { return Ok(None) };
+        let line = std::str::from_utf8(&buf[offset..offset + line_end])
+            .map_err(|_| malformed("invalid chunk size"))?;
+
+        // Chunk extensions after ';' are ignored
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| malformed("invalid chunk size"))?;
+        offset += line_end + 2;
+
+        if size == 0 {
+            // Skip trailers up to the empty line ending the body
+            loop {
+                let Some(line_end) = find(&buf[offset..], b"\r\n") else { return Ok(None) };
+                offset += line_end + 2;
+                if line_end == 0 {
+                    return Ok(Some((body, offset)));
+                }
+            }
+        }
+
+        if body.len() + size > MAX_BODY_SIZE {
+            return Err(malformed("response body too large"));
+        }
+        if buf.len() < offset + size + 2 {
+            return Ok(None);
+        }
+        if &buf[offset + size..offset + size + 2] != b"\r\n" {
+            return Err(malformed("missing chunk terminator"));
+        }
+
+        body.extend_from_slice(&buf[offset..offset + size]);
+        offset += size + 2;
+    }
+}
+
+fn incomplete(eof: bool) -> Result<Option<(HttpResponse, usize)>, TreblleError> {
+    if eof {
+        Err(malformed("connection closed before the response was complete"))
+    } else {
+        Ok(None)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn malformed(reason: &str) -> TreblleError {
+    TreblleError::Http(format!("Malformed HTTP response: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> HttpResponse {
+        let (response, len) = parse_response(raw.as_bytes(), false).unwrap().unwrap();
+        assert_eq!(len, raw.len());
+        response
+    }
+
+    #[test]
+    fn test_content_length() {
+        let response = parse(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}",
+        );
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        assert_eq!(response.body, b"{}");
+        assert!(response.is_success());
+        assert!(response.keep_alive());
+    }
+
+    #[test]
+    fn test_chunked() {
+        let response = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nX-Trailer: 1\r\n\r\n",
+        );
+
+        assert_eq!(response.body, b"Wikipedia");
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n{\"ok\":";
+        assert!(parse_response(raw, false).unwrap().is_none());
+        assert!(parse_response(raw, true).is_err());
+
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-", false).unwrap().is_none());
+        assert!(parse_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWi",
+            false
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn test_connection_close_and_http_1_0() {
+        let response = parse(
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        );
+        assert_eq!(response.status, 503);
+        assert!(!response.is_success());
+        assert!(!response.keep_alive());
+
+        let response = parse("HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n");
+        assert!(!response.keep_alive());
+
+        let response =
+            parse("HTTP/1.0 200 OK\r\nConnection: keep-alive\r\nContent-Length: 0\r\n\r\n");
+        assert!(response.keep_alive());
+    }
+
+    #[test]
+    fn test_body_until_close() {
+        let raw = b"HTTP/1.1 200 OK\r\n\r\nhello";
+        assert!(parse_response(raw, false).unwrap().is_none());
+
+        let (response, _) = parse_response(raw, true).unwrap().unwrap();
+        assert_eq!(response.body, b"hello");
+        assert!(!response.keep_alive());
+    }
+
+    #[test]
+    fn test_skips_interim_responses_and_empty_bodies() {
+        let response = parse("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n");
+        assert_eq!(response.status, 204);
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn test_pipelined_responses() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\nHTTP/1.1 500 Oops\r\nContent-Length: 0\r\n\r\n";
+        let (first, len) = parse_response(raw, false).unwrap().unwrap();
+        assert_eq!(first.status, 200);
+
+        let (second, _) = parse_response(&raw[len..], false).unwrap().unwrap();
+        assert_eq!(second.status, 500);
+    }
+
+    #[test]
+    fn test_response_head() {
+        let raw = b"HTTP/1.1 200 Connection established\r\nVia: proxy\r\n\r\n\x16\x03";
+        let (response, len) = parse_response_head(raw, false).unwrap().unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("via"), Some("proxy"));
+        assert_eq!(len, raw.len() - 2);
+
+        assert!(parse_response_head(b"HTTP/1.1 200 OK\r\n", false).unwrap().is_none());
+        assert!(parse_response_head(b"HTTP/1.1 200 OK\r\n", true).is_err());
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(parse_response(b"SMTP 220 hello\r\n\r\n", false).is_err());
+        assert!(parse_response(b"HTTP/1.1 2000 OK\r\n\r\n", false).is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nbad header\r\n\r\n", false).is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: x\r\n\r\n", false).is_err());
+        assert!(parse_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+            false
+        )
+        .is_err());
+    }
+}
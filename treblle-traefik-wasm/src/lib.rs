@@ -8,73 +8,144 @@
 pub mod bindings;
 pub mod certs;
 pub mod config;
+pub mod config_handle;
 pub mod constants;
 pub mod extractors;
 pub mod host_functions;
+pub mod http_response;
 pub mod logger;
 pub mod middleware;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mock_host;
+pub mod queue;
+pub mod request_context;
+pub mod request_filter;
+pub mod route_template;
+pub mod trace_context;
 pub mod wasi_http_client;
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
 
-use once_cell::sync::Lazy;
+use treblle_core::transport::{FileTransport, StdoutTransport, Transport};
+use treblle_core::TlsConfig;
 
-use crate::config::WasmConfig;
+use crate::config::{TransportKind, WasmConfig};
+use crate::config_handle::WasmConfigHandle;
 use crate::logger::{log, LogLevel};
 use crate::middleware::TreblleMiddleware;
-use crate::wasi_http_client::WasiHttpClient;
+use crate::queue::PayloadQueue;
+use crate::request_context::{RequestContexts, MAX_REQUEST_CONTEXTS};
+use crate::wasi_http_client::{Proxy, WasiHttpClient};
 
 use bindings::exports::traefik::http_handler::handler::Guest;
 
-pub static CONFIG: Lazy<WasmConfig> = Lazy::new(|| {
-    WasmConfig::get_or_fallback().unwrap_or_else(|e| {
-        log(LogLevel::Error, &format!("Failed to get config from host, using defaults: {e}"));
-        WasmConfig::builder().api_key("default").build().expect("Failed to create default config")
-    })
-});
+// Configuration, loaded on first use and reloaded from its file if it has one
+pub static CONFIG: WasmConfigHandle = WasmConfigHandle::new();
 
-// Initialize HTTP client statically
-pub static HTTP_CLIENT: Lazy<Arc<WasiHttpClient>> = Lazy::new(|| {
-    log(LogLevel::Debug, "Initializing HTTP client");
+static HTTP_CLIENT: Mutex<Option<(ClientSettings, Arc<WasiHttpClient>)>> = Mutex::new(None);
+
+static TRANSPORT: Mutex<Option<CachedTransport>> = Mutex::new(None);
+
+/// Local transport along with the file it writes to, `None` for stdout
+type CachedTransport = (Option<String>, Arc<dyn Transport>);
+
+/// Options the HTTP client is built from; the client is rebuilt when a reload changes them
+#[derive(Debug, PartialEq)]
+struct ClientSettings {
+    api_urls: Vec<String>,
+    max_retries: usize,
+    max_pool_size: usize,
+    tls: TlsConfig,
+    proxy_url: Option<String>,
+}
 
-    // Log the configuration being used
-    log(LogLevel::Debug, &format!("Using config: {:?}", &*CONFIG));
+impl ClientSettings {
+    fn of(config: &WasmConfig) -> Self {
+        Self {
+            api_urls: config.core.api_urls.clone(),
+            max_retries: config.max_retries,
+            max_pool_size: config.max_pool_size,
+            tls: config.core.tls.clone(),
+            proxy_url: config.proxy_url.clone(),
+        }
+    }
+}
+
+/// HTTP client for the Treblle API, built from the configuration and rebuilt when
+/// a reloaded configuration changes the URLs, retries, pool size, TLS or proxy
+pub fn http_client(config: &WasmConfig) -> Arc<WasiHttpClient> {
+    let settings = ClientSettings::of(config);
+    let mut cached = HTTP_CLIENT.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some((current, client)) = cached.as_ref() {
+        if *current == settings {
+            return Arc::clone(client);
+        }
+        log(LogLevel::Info, "Client settings changed, rebuilding the HTTP client");
+    }
 
-    let client = WasiHttpClient::new(
-        CONFIG.core.api_urls.clone(),
-        CONFIG.max_retries,
-        CONFIG.max_pool_size,
-        CONFIG.root_ca_path.clone(),
+    log(LogLevel::Debug, "Initializing HTTP client");
+    let mut client = WasiHttpClient::new(
+        settings.api_urls.clone(),
+        settings.max_retries,
+        settings.max_pool_size,
+        settings.tls.clone(),
     );
 
+    if let Some(proxy_url) = &settings.proxy_url {
+        match Proxy::parse(proxy_url) {
+            Ok(proxy) => client = client.with_proxy(proxy),
+            Err(e) => log(LogLevel::Error, &format!("Ignoring invalid proxy URL: {e}")),
+        }
+    }
+
     log(LogLevel::Debug, "HTTP client initialized successfully");
-    Arc::new(client)
-});
+    let client = Arc::new(client);
+    *cached = Some((settings, Arc::clone(&client)));
+    client
+}
+
+/// Transport payloads are sent through, built from the configuration and rebuilt when
+/// a reloaded configuration changes it
+pub fn transport(config: &WasmConfig) -> Arc<dyn Transport> {
+    let file_path = match (config.transport, &config.transport_file_path) {
+        (TransportKind::Stdout, _) => None,
+        (TransportKind::File, Some(path)) => Some(path.clone()),
+        _ => return http_client(config),
+    };
+
+    let mut cached = TRANSPORT.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((path, transport)) = cached.as_ref() {
+        if *path == file_path {
+            return Arc::clone(transport);
+        }
+    }
+
+    log(LogLevel::Debug, &format!("Initializing {:?} transport", config.transport));
+    let transport: Arc<dyn Transport> = match &file_path {
+        Some(path) => Arc::new(FileTransport::new(path)),
+        None => Arc::new(StdoutTransport::new()),
+    };
+    *cached = Some((file_path, Arc::clone(&transport)));
+    transport
+}
+
+// State of requests awaiting their response, keyed by the http-wasm req_ctx
+pub static REQUEST_CONTEXTS: Mutex<RequestContexts> =
+    Mutex::new(RequestContexts::new(MAX_REQUEST_CONTEXTS));
+
+// Payloads waiting to be flushed to the transport
+pub static PAYLOAD_QUEUE: Mutex<PayloadQueue> = Mutex::new(PayloadQueue::new());
 
 // Implement the Guest trait required by Traefik
 impl Guest for TreblleMiddleware {
     fn handle_request() -> i64 {
-        // Force initialization of our statics if not already done
-        Lazy::force(&CONFIG);
-        Lazy::force(&HTTP_CLIENT);
-
-        // Initialize logger with the configured level, if not already initialized
-        logger::init(CONFIG.log_level);
-
         log(LogLevel::Debug, "Guest::handle_request called");
         TreblleMiddleware::handle_request()
     }
 
     fn handle_response(req_ctx: i32, is_error: i32) {
-        // Force initialization of our statics if not already done
-        Lazy::force(&CONFIG);
-        Lazy::force(&HTTP_CLIENT);
-
-        // Initialize logger with the configured level, if not already initialized
-        logger::init(CONFIG.log_level);
-
         log(LogLevel::Debug, "Guest::handle_response called");
-
         TreblleMiddleware::handle_response(req_ctx, is_error);
     }
 }
@@ -89,3 +160,30 @@ pub extern "C" fn handle_request() -> i64 {
 pub extern "C" fn handle_response(req_ctx: i32, is_error: i32) {
     <TreblleMiddleware as Guest>::handle_response(req_ctx, is_error);
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::mock_host::exchange;
+
+    fn config(api_url: &str) -> WasmConfig {
+        WasmConfig::builder().api_key("test_key").set_api_urls([api_url]).build().unwrap()
+    }
+
+    #[test]
+    fn test_http_client_is_rebuilt_when_its_settings_change() {
+        let _exchange = exchange();
+        let first = config("http://first.test");
+
+        let client = http_client(&first);
+        assert!(Arc::ptr_eq(&client, &http_client(&first)));
+
+        // Options the client doesn't depend on keep it
+        let mut masked = first.clone();
+        masked.core.masked_fields.insert("ssn".to_string());
+        assert!(Arc::ptr_eq(&client, &http_client(&masked)));
+
+        let rebuilt = http_client(&config("http://second.test"));
+        assert!(!Arc::ptr_eq(&client, &rebuilt));
+    }
+}
@@ -7,6 +7,7 @@ use crate::host_functions::log::host_log;
 static LOG_LEVEL: AtomicI32 = AtomicI32::new(log_level::INFO);
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Default)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Debug,
@@ -42,7 +43,7 @@ impl LogLevel {
 /// Initialize the logger with the configured log level
 pub fn init(level: LogLevel) {
     LOG_LEVEL.store(level.as_i32(), Ordering::Relaxed);
-    log(LogLevel::Debug, &format!("Log level set to: {:?}", crate::CONFIG.log_level));
+    log(LogLevel::Debug, &format!("Log level set to: {level:?}"));
 }
 
 /// Log a message with the specified level
@@ -1,16 +1,31 @@
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 use std::time::Instant;
-use treblle_core::{extractors::TreblleExtractor, PayloadBuilder};
+use treblle_core::{
+    extractors::TreblleExtractor, metrics, schema::TrebllePayload, CaptureDecision, PayloadBuilder,
+};
 
+use crate::config::{TransportKind, WasmConfig};
 use crate::constants::host_features::{FEATURE_BUFFER_REQUEST, FEATURE_BUFFER_RESPONSE};
 use crate::constants::http::{REQUEST_KIND, RESPONSE_KIND};
 use crate::{
-    extractors::WasmExtractor,
+    extractors::{BodyLimit, WasmExtractor},
     host_functions,
-    host_functions::headers::host_get_header_values,
+    host_functions::headers::{host_get_header_values, host_set_header_value},
+    host_functions::request::{host_get_method, host_get_uri},
+    host_functions::response::host_get_status_code,
+    http_client, logger,
     logger::{log, LogLevel},
-    CONFIG, HTTP_CLIENT,
+    request_context::{ctx_next, RequestContext, CTX_NEXT},
+    request_filter::request_path,
+    trace_context::{self, TRACEPARENT},
+    transport, CONFIG, PAYLOAD_QUEUE, REQUEST_CONTEXTS,
 };
 
+/// Queue label of the guest payload queue in the queue depth metric
+const QUEUE: &str = "guest";
+
 /// WASM middleware for Traefik that sends API analytics to Treblle
 pub struct TreblleMiddleware;
 
@@ -18,9 +33,10 @@ impl TreblleMiddleware {
     /// Check if the request/response should be processed
     fn should_process(kind: u32) -> bool {
         host_get_header_values(kind, "content-type")
-            .map(|ct| {
-                let is_json = ct.to_lowercase().contains("application/json");
-                log(LogLevel::Debug, &format!("Content-Type: {ct}, is_json: {is_json}"));
+            .map(|values| {
+                let is_json =
+                    values.iter().any(|ct| ct.to_lowercase().contains("application/json"));
+                log(LogLevel::Debug, &format!("Content-Type: {values:?}, is_json: {is_json}"));
                 is_json
             })
             .unwrap_or_else(|e| {
@@ -29,87 +45,256 @@ impl TreblleMiddleware {
             })
     }
 
-    /// Process an incoming HTTP request
+    /// Add the request ID to the response and forward trace headers upstream, as configured.
+    ///
+    /// Runs before extraction so the recorded request carries the forwarded headers.
+    fn inject_headers(config: &WasmConfig) {
+        let request_id_header = config.request_id_header();
+        let forward = config.forward_trace_headers();
+        if request_id_header.is_none() && !forward {
+            return;
+        }
+
+        // Requests that are already part of a trace keep its ID
+        let trace_id = host_get_header_values(REQUEST_KIND, TRACEPARENT).ok().and_then(|values| {
+            values.first().and_then(|v| trace_context::trace_id(v)).map(String::from)
+        });
+        let request_id = trace_id.clone().unwrap_or_else(trace_context::new_request_id);
+
+        let mut headers = Vec::new();
+        if let Some(header) = request_id_header {
+            headers.push((RESPONSE_KIND, header, request_id.clone()));
+            if forward {
+                headers.push((REQUEST_KIND, header, request_id.clone()));
+            }
+        }
+        if forward && trace_id.is_none() {
+            headers.push((REQUEST_KIND, TRACEPARENT, trace_context::new_traceparent(&request_id)));
+        }
+
+        for (kind, name, value) in headers {
+            if let Err(e) = host_set_header_value(kind, name, &value) {
+                log(LogLevel::Error, &format!("Failed to set {name} header: {e}"));
+            }
+        }
+    }
+
+    /// Queue a payload to be sent by a later flush
+    fn enqueue_payload(config: &WasmConfig, payload: TrebllePayload) {
+        metrics::record_built();
+        let Ok(mut queue) = PAYLOAD_QUEUE.lock() else {
+            log(LogLevel::Error, "Payload queue is poisoned, dropping payload");
+            metrics::record_dropped("queue_poisoned", 1);
+            return;
+        };
+
+        let evicted = queue.push(payload, config.max_queue_size);
+        if evicted > 0 {
+            log(LogLevel::Warn, &format!("Payload queue is full, dropped {evicted} payloads"));
+            metrics::record_dropped("queue_full", evicted as u64);
+        }
+        metrics::set_queue_depth(QUEUE, queue.len());
+    }
+
+    /// Send queued payloads if a flush is due, within the configured time budget
+    fn flush_if_due(config: &WasmConfig) {
+        let policy = config.flush_policy();
+        let Ok(mut queue) = PAYLOAD_QUEUE.lock() else {
+            return;
+        };
+
+        if !queue.should_flush(&policy) {
+            return;
+        }
+
+        let start = Instant::now();
+        let stats = queue.flush(policy.budget, |payload, deadline| {
+            let result = Self::send_before(config, payload, deadline);
+            match &result {
+                Ok(()) => metrics::record_sent(transport(config).name()),
+                Err(e) => {
+                    log(
+                        LogLevel::Error,
+                        &format!(
+                            "Failed to send payload via {} transport: {e}",
+                            transport(config).name()
+                        ),
+                    );
+                    metrics::record_failed(transport(config).name());
+                }
+            }
+            result
+        });
+
+        log(
+            LogLevel::Debug,
+            &format!(
+                "Flushed {} payloads via {} transport in {:?} ({} failed, {} still queued)",
+                stats.sent,
+                transport(config).name(),
+                start.elapsed(),
+                stats.failed,
+                queue.len()
+            ),
+        );
+        metrics::set_queue_depth(QUEUE, queue.len());
+    }
+
+    /// Send a payload through the configured transport, giving up at the deadline
+    fn send_before(
+        config: &WasmConfig,
+        payload: &TrebllePayload,
+        deadline: Instant,
+    ) -> treblle_core::Result<()> {
+        match config.transport {
+            // Only network sends can be slow, so only they are bounded by the deadline
+            TransportKind::Treblle => {
+                let json = serde_json::to_vec(payload)?;
+                http_client(config).send_before(&json, &payload.api_key, deadline)
+            }
+            TransportKind::Stdout | TransportKind::File => {
+                block_on(transport(config).send(payload.clone()))
+            }
+        }
+    }
+
+    /// Process an incoming HTTP request.
+    ///
+    /// Returns the ID of the stored request context in the high 32 bits, so
+    /// `handle_response` can correlate the response with this request.
+    /// Requests pass through untouched while the configuration is invalid.
     pub fn handle_request() -> i64 {
+        let Some(config) = CONFIG.get() else {
+            return CTX_NEXT;
+        };
+        if !config.core.is_enabled() {
+            return CTX_NEXT;
+        }
+        logger::init(config.log_level);
+
         log(LogLevel::Debug, "Starting request processing");
         let start = Instant::now();
 
-        if CONFIG.buffer_request {
+        Self::flush_if_due(&config);
+
+        if config.buffer_request {
             match host_functions::host_enable_features(FEATURE_BUFFER_REQUEST) {
                 Ok(features) => {
                     log(LogLevel::Info, &format!("Enabled features: {features}"));
                 }
                 Err(e) => {
                     log(LogLevel::Error, &format!("Failed to enable request buffering: {e}"));
-                    return 1;
+                    return CTX_NEXT;
                 }
             }
         }
 
-        // Check if we should process this request
-        if !Self::should_process(REQUEST_KIND) {
-            log(LogLevel::Debug, "Not a JSON request, skipping processing");
-            return 1;
+        // Cheap filters run before anything is extracted from the request
+        let uri = host_get_uri().unwrap_or_default();
+        let path = request_path(&uri);
+
+        let host = host_get_header_values(REQUEST_KIND, "host")
+            .ok()
+            .and_then(|values| values.into_iter().next())
+            .unwrap_or_default();
+        if config.should_ignore_host(&host) {
+            log(LogLevel::Debug, &format!("Ignoring host: {host}"));
+            metrics::record_ignored();
+            return CTX_NEXT;
+        }
+
+        let method = host_get_method().unwrap_or_default();
+        let decision = config.core.capture.capture_request(&method, path);
+        if decision == CaptureDecision::Skip {
+            log(LogLevel::Debug, &format!("Ignoring method: {method}"));
+            metrics::record_ignored();
+            return CTX_NEXT;
         }
 
-        log(LogLevel::Debug, "Request is JSON, proceeding with processing");
+        let route_path = config.route_template(path);
+        if config.core.should_ignore_request(path, route_path.as_deref()) {
+            log(LogLevel::Debug, &format!("Ignoring route: {path}"));
+            metrics::record_ignored();
+            return CTX_NEXT;
+        }
+        let policy = config.core.policy_for(path, route_path.as_deref());
 
-        // Extract request data and check routing
-        let start_extract = Instant::now();
-        let request_payload =
-            PayloadBuilder::build_request_payload::<WasmExtractor>(&(), &CONFIG.core);
-        // Track timing for each stage
-        log(
-            LogLevel::Debug,
-            &format!("Extracted request payload for URL: {}", request_payload.data.request.url),
-        );
-        log(LogLevel::Debug, &format!("Payload extraction took: {:?}", start_extract.elapsed()));
+        let Ok(mut contexts) = REQUEST_CONTEXTS.lock() else {
+            log(LogLevel::Error, "Request context store is poisoned, skipping processing");
+            return CTX_NEXT;
+        };
 
-        // Check if route should be ignored
-        if CONFIG.core.should_ignore_route(&request_payload.data.request.url) {
-            log(LogLevel::Debug, &format!("Ignoring route: {}", request_payload.data.request.url));
-            return 1;
-        }
-
-        // Send to Treblle using static HTTP client
-        let start_send = Instant::now();
-        match serde_json::to_vec(&request_payload) {
-            Ok(payload_json) => {
-                log(
-                    LogLevel::Debug,
-                    &format!(
-                        "Sending request payload of size {} bytes to Treblle",
-                        payload_json.len()
-                    ),
-                );
-                log(
-                    LogLevel::Debug,
-                    &format!(
-                        "JSON serialization took: {:?}, size: {} bytes",
-                        start_send.elapsed(),
-                        payload_json.len()
-                    ),
-                );
-                if let Err(e) = HTTP_CLIENT.send(&payload_json, &CONFIG.core.api_key) {
-                    log(LogLevel::Error, &format!("Failed to send request data to Treblle: {e}"));
-                } else {
-                    log(LogLevel::Debug, "Successfully sent request data to Treblle");
-                }
-            }
-            Err(e) => log(LogLevel::Error, &format!("Failed to serialize request payload: {e}")),
+        if !contexts.sample(policy.sample_rate().unwrap_or(config.sample_rate)) {
+            log(LogLevel::Debug, &format!("Request to {path} sampled out"));
+            metrics::record_sampled_out();
+            return CTX_NEXT;
         }
 
+        Self::inject_headers(&config);
+
+        // Only JSON request bodies are captured; the response may still be JSON
+        let request = if Self::should_process(REQUEST_KIND) {
+            log(LogLevel::Debug, "Request is JSON, proceeding with processing");
+
+            let start_extract = Instant::now();
+            let request_payload = PayloadBuilder::build_request_payload::<WasmExtractor>(
+                &BodyLimit(policy.max_request_body_size(&config.core)),
+                &config.core,
+                &policy,
+            );
+            log(
+                LogLevel::Debug,
+                &format!("Payload extraction took: {:?}", start_extract.elapsed()),
+            );
+
+            Some(request_payload.data.request)
+        } else {
+            log(LogLevel::Debug, "Not a JSON request, skipping request extraction");
+            None
+        };
+
         log(LogLevel::Debug, &format!("Total request processing took: {:?}", start.elapsed()));
 
-        1
+        // Latency is measured from here, once the request is handed to the upstream
+        let deferred = (decision == CaptureDecision::AfterResponse).then_some(method);
+        let ctx_id = contexts.insert(RequestContext {
+            start: Instant::now(),
+            request,
+            route_path,
+            path: path.into(),
+            deferred,
+        });
+        ctx_next(ctx_id)
     }
 
-    /// Process an HTTP response
-    pub fn handle_response(_req_ctx: i32, is_error: i32) {
+    /// Process an HTTP response, sending one payload combining it with its request
+    pub fn handle_response(req_ctx: i32, is_error: i32) {
+        let Some(config) = CONFIG.get() else {
+            return;
+        };
+        logger::init(config.log_level);
+
         log(LogLevel::Debug, "Starting response processing");
         let start = Instant::now();
 
-        if CONFIG.buffer_response {
+        let context = REQUEST_CONTEXTS.lock().ok().and_then(|mut contexts| contexts.take(req_ctx));
+        let Some(context) = context else {
+            log(LogLevel::Debug, "No request context, skipping response processing");
+            return;
+        };
+        let latency = context.start.elapsed();
+
+        let path = &context.path;
+        if let Some(method) = &context.deferred {
+            let status = u16::try_from(host_get_status_code()).unwrap_or_default();
+            if !config.core.capture.should_capture_response(method, path, status, latency) {
+                log(LogLevel::Debug, &format!("Ignoring status code {status} for {path}"));
+                metrics::record_ignored();
+                return;
+            }
+        }
+
+        if config.buffer_response {
             match host_functions::host_enable_features(FEATURE_BUFFER_RESPONSE) {
                 Ok(features) => {
                     log(LogLevel::Info, &format!("Enabled features: {features}"));
@@ -121,67 +306,443 @@ impl TreblleMiddleware {
             }
         }
 
-        // Check if we should process this response
-        if !Self::should_process(RESPONSE_KIND) {
-            log(LogLevel::Debug, "Not a JSON response, skipping processing");
+        let response_is_json = Self::should_process(RESPONSE_KIND);
+        if !response_is_json && context.request.is_none() {
+            log(LogLevel::Debug, "Neither request nor response is JSON, skipping processing");
+            metrics::record_ignored();
             return;
         }
 
-        log(
-            LogLevel::Debug,
-            "Response is JSON and buffering is enabled, proceeding with processing",
-        );
-
-        let start_time = Instant::now();
-
         // Extract response data
         let start_extract = Instant::now();
-        let mut response_payload = PayloadBuilder::build_response_payload::<WasmExtractor>(
-            &(),
-            &CONFIG.core,
-            start_time.elapsed(),
-        );
-        log(
-            LogLevel::Debug,
-            &format!("Extracted response payload for URL: {}", response_payload.data.request.url),
+        // Bodies left out for the route aren't read, not even for error messages
+        let policy = config.core.policy_for(path, context.route_path.as_deref());
+        let limit = BodyLimit(if policy.capture_response_body() {
+            policy.max_response_body_size(&config.core)
+        } else {
+            0
+        });
+        let mut payload = PayloadBuilder::build_response_payload::<WasmExtractor>(
+            &limit,
+            &config.core,
+            &policy,
+            latency,
         );
         log(LogLevel::Debug, &format!("Payload extraction took: {:?}", start_extract.elapsed()));
 
-        // Add error information if needed
-        if is_error != 0 || response_payload.data.response.code >= 400 {
-            if let Some(errors) = WasmExtractor::extract_error_info(&()) {
-                response_payload.data.errors.extend(errors);
-            }
+        if let Some(request) = context.request {
+            payload.data.request = request;
         }
+        payload.data.request.route_path = context.route_path;
 
-        // Send to Treblle using static HTTP client
-        let start_send = Instant::now();
-        match serde_json::to_vec(&response_payload) {
-            Ok(payload_json) => {
-                log(
-                    LogLevel::Debug,
-                    &format!(
-                        "Sending response payload of size {} bytes to Treblle",
-                        payload_json.len()
-                    ),
-                );
-                log(
-                    LogLevel::Debug,
-                    &format!(
-                        "JSON serialization took: {:?}, size: {} bytes",
-                        start_send.elapsed(),
-                        payload_json.len()
-                    ),
-                );
-                if let Err(e) = HTTP_CLIENT.send(&payload_json, &CONFIG.core.api_key) {
-                    log(LogLevel::Error, &format!("Failed to send response data to Treblle: {e}"));
-                } else {
-                    log(LogLevel::Debug, "Successfully sent response data to Treblle");
-                }
+        // Add error information if needed
+        if is_error != 0 || payload.data.response.code >= 400 {
+            if let Some(errors) = WasmExtractor::extract_error_info(&limit) {
+                payload.data.errors.extend(errors);
             }
-            Err(e) => log(LogLevel::Error, &format!("Failed to serialize response payload: {e}")),
         }
 
+        log(
+            LogLevel::Debug,
+            &format!("Upstream latency for {}: {latency:?}", payload.data.request.url),
+        );
+        Self::enqueue_payload(&config, payload);
+        Self::flush_if_due(&config);
+
         log(LogLevel::Debug, &format!("Total response processing took: {:?}", start.elapsed()));
     }
 }
+
+/// Drive a transport future to completion.
+///
+/// The WASM guest has no async runtime and all bundled transports complete synchronously,
+/// so the future is simply polled until it is ready.
+fn block_on<F: Future>(future: F) -> F::Output {
+    const VTABLE: RawWakerVTable =
+        RawWakerVTable::new(|_| RawWaker::new(std::ptr::null(), &VTABLE), |_| {}, |_| {}, |_| {});
+
+    // SAFETY: the vtable functions never touch the (null) data pointer
+    let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::mock_host::{exchange, payload_file_path, with_host};
+    use serde_json::{json, Value};
+    use treblle_core::{RouteOverride, StatusCodes};
+
+    const JSON: (&str, &str) = ("content-type", "application/json");
+
+    fn send_request(method: &str, uri: &str, headers: &[(&str, &str)], body: &[u8]) -> i64 {
+        with_host(|host| host.set_request(method, uri, headers.iter().copied(), body));
+        TreblleMiddleware::handle_request()
+    }
+
+    fn send_response(ctx: i64, status: u32, headers: &[(&str, &str)], body: &[u8]) {
+        with_host(|host| host.set_response(status, headers.iter().copied(), body));
+        TreblleMiddleware::handle_response((ctx >> 32) as i32, 0);
+    }
+
+    /// Payloads flushed to the file transport for the given URI
+    fn sent_payloads(uri: &str) -> Vec<Value> {
+        std::fs::read_to_string(payload_file_path())
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .filter(|payload| payload["data"]["request"]["url"] == uri)
+            .collect()
+    }
+
+    #[test]
+    fn test_json_exchange_is_sent_masked() {
+        let _exchange = exchange();
+        let uri = "/e2e/users?page=1";
+        let request_body = br#"{"name":"Ada","password":"hunter2"}"#;
+
+        let ctx = send_request(
+            "POST",
+            uri,
+            &[JSON, ("host", "api.example.com"), ("x-forwarded-for", "203.0.113.7, 10.0.0.1")],
+            request_body,
+        );
+        assert_ne!(ctx, CTX_NEXT);
+
+        // The request body is handed back to the host for the next handler
+        let written = with_host(|host| host.request.written_body.clone());
+        assert_eq!(written.as_deref(), Some(request_body.as_slice()));
+
+        send_response(ctx, 201, &[JSON], br#"{"id":1,"token":"abc"}"#);
+
+        let payloads = sent_payloads(uri);
+        assert_eq!(payloads.len(), 1);
+
+        let data = &payloads[0]["data"];
+        assert_eq!(data["request"]["method"], "POST");
+        assert_eq!(data["request"]["ip"], "203.0.113.7");
+        assert_eq!(data["request"]["headers"]["host"], "api.example.com");
+        assert_eq!(data["request"]["body"]["name"], "Ada");
+        assert_eq!(data["request"]["body"]["password"], "*****");
+        assert_eq!(data["response"]["code"], 201);
+        assert_eq!(data["response"]["body"]["id"], 1);
+        assert_eq!(data["response"]["body"]["token"], "*****");
+    }
+
+    #[test]
+    fn test_ignored_route_is_not_extracted() {
+        let _exchange = exchange();
+        let uri = "/health?verbose=1";
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"probe":true}"#);
+        assert_eq!(ctx, CTX_NEXT);
+        assert!(with_host(|host| host.request.written_body.is_none()));
+
+        send_response(ctx, 200, &[JSON], br#"{"status":"ok"}"#);
+        assert!(sent_payloads(uri).is_empty());
+    }
+
+    #[test]
+    fn test_non_json_exchange_is_not_sent() {
+        let _exchange = exchange();
+        let uri = "/e2e/page";
+        let html = ("content-type", "text/html");
+
+        let ctx = send_request("GET", uri, &[html], b"");
+        send_response(ctx, 200, &[html], b"<html></html>");
+
+        assert!(sent_payloads(uri).is_empty());
+    }
+
+    #[test]
+    fn test_error_response_is_reported() {
+        let _exchange = exchange();
+        let uri = "/e2e/orders";
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"item":42}"#);
+        send_response(ctx, 500, &[JSON], br#"{"message":"boom"}"#);
+
+        let payloads = sent_payloads(uri);
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(payloads[0]["data"]["response"]["code"], 500);
+        assert_eq!(payloads[0]["data"]["errors"][0]["type"], "HTTP_500");
+    }
+
+    /// Value of a header as seen by the host after the plugin ran
+    fn header(headers: &[(String, String)], name: &str) -> Option<String> {
+        headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_request_id_and_trace_headers_are_injected() {
+        let _exchange = exchange();
+        let uri = "/e2e/traced";
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"item":1}"#);
+        let request_headers = with_host(|host| host.request.headers.clone());
+        let request_id = header(&request_headers, "X-Treblle-Request-Id").unwrap();
+        let traceparent = header(&request_headers, TRACEPARENT).unwrap();
+        assert_eq!(trace_context::trace_id(&traceparent), Some(request_id.as_str()));
+
+        send_response(ctx, 200, &[JSON], br#"{"ok":true}"#);
+        let response_headers = with_host(|host| host.response.headers.clone());
+        assert_eq!(header(&response_headers, "X-Treblle-Request-Id"), Some(request_id.clone()));
+
+        // The recorded exchange carries the ID on both sides
+        let payloads = sent_payloads(uri);
+        assert_eq!(payloads.len(), 1);
+        let data = &payloads[0]["data"];
+        assert_eq!(data["request"]["headers"]["X-Treblle-Request-Id"], request_id.as_str());
+        assert_eq!(data["response"]["headers"]["X-Treblle-Request-Id"], request_id.as_str());
+    }
+
+    #[test]
+    fn test_incoming_trace_is_kept() {
+        let _exchange = exchange();
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let traceparent = format!("00-{trace_id}-00f067aa0ba902b7-01");
+
+        let ctx =
+            send_request("GET", "/e2e/traced/child", &[JSON, (TRACEPARENT, &traceparent)], b"");
+        let request_headers = with_host(|host| host.request.headers.clone());
+        assert_eq!(header(&request_headers, TRACEPARENT), Some(traceparent.clone()));
+        assert_eq!(header(&request_headers, "X-Treblle-Request-Id").as_deref(), Some(trace_id));
+
+        send_response(ctx, 200, &[JSON], b"{}");
+        let response_headers = with_host(|host| host.response.headers.clone());
+        assert_eq!(header(&response_headers, "X-Treblle-Request-Id").as_deref(), Some(trace_id));
+    }
+
+    #[test]
+    fn test_disabled_plugin_passes_requests_through() {
+        let _exchange = exchange();
+        let uri = "/e2e/disabled";
+
+        let enabled = CONFIG.get().unwrap();
+        let mut disabled = WasmConfig::clone(&enabled);
+        disabled.core.enabled = false;
+        CONFIG.replace(disabled);
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"item":1}"#);
+        send_response(ctx, 200, &[JSON], br#"{"ok":true}"#);
+        CONFIG.replace(WasmConfig::clone(&enabled));
+
+        // Nothing is buffered, injected or sent
+        assert_eq!(ctx, CTX_NEXT);
+        assert_eq!(with_host(|host| host.features), 0);
+        let request_headers = with_host(|host| host.request.headers.clone());
+        assert!(header(&request_headers, "X-Treblle-Request-Id").is_none());
+        assert!(sent_payloads(uri).is_empty());
+    }
+
+    #[test]
+    fn test_ignored_status_codes_are_not_sent() {
+        let _exchange = exchange();
+        let uri = "/e2e/status-codes";
+
+        let current = CONFIG.get().unwrap();
+        let mut filtered = WasmConfig::clone(&current);
+        filtered.core.capture.ignored_status_codes = vec![StatusCodes::Class(4)];
+        CONFIG.replace(filtered);
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"item":1}"#);
+        send_response(ctx, 404, &[JSON], br#"{"error":"not found"}"#);
+        let ignored = sent_payloads(uri);
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"item":2}"#);
+        send_response(ctx, 201, &[JSON], br#"{"ok":true}"#);
+        CONFIG.replace(WasmConfig::clone(&current));
+
+        assert!(ignored.is_empty());
+        let sent = sent_payloads(uri);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["data"]["response"]["code"], 201);
+        assert_eq!(sent[0]["data"]["request"]["body"]["item"], 2);
+    }
+
+    #[test]
+    fn test_route_templates_are_reported_and_ignored() {
+        let _exchange = exchange();
+        let uri = "/e2e/orders/8271/items/3f2b8c1e-9a4d-4c2b-8e1f-0a1b2c3d4e5f";
+        let ignored_uri = "/e2e/internal/99";
+
+        let current = CONFIG.get().unwrap();
+        let mut config = WasmConfig::clone(&current);
+        config.core.ignored_routes.insert("/e2e/internal/{id}".to_string());
+        CONFIG.replace(config);
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"item":1}"#);
+        send_response(ctx, 200, &[JSON], br#"{"ok":true}"#);
+        let ctx = send_request("POST", ignored_uri, &[JSON], br#"{"item":1}"#);
+        CONFIG.replace(WasmConfig::clone(&current));
+
+        assert_eq!(ctx, CTX_NEXT);
+        assert!(sent_payloads(ignored_uri).is_empty());
+        let sent = sent_payloads(uri);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["data"]["request"]["route_path"], "/e2e/orders/{id}/items/{uuid}");
+    }
+
+    #[test]
+    fn test_route_overrides_apply_to_matching_requests() {
+        let _exchange = exchange();
+        let uri = "/e2e/payments/1";
+        let sampled_out_uri = "/e2e/search";
+
+        let current = CONFIG.get().unwrap();
+        let mut config = WasmConfig::clone(&current);
+        config.core.route_overrides = vec![
+            RouteOverride::new("^/e2e/payments/")
+                .unwrap()
+                .masked_fields(["iban"])
+                .capture_response_body(false),
+            RouteOverride::new("^/e2e/search$").unwrap().sample_rate(0.0),
+        ];
+        CONFIG.replace(config);
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"iban":"DE89","amount":10}"#);
+        send_response(ctx, 200, &[JSON], br#"{"iban":"DE89"}"#);
+        // The response body isn't read, so it's never written back either
+        let response_read = with_host(|host| host.response.written_body.is_some());
+        let sampled_out = send_request("GET", sampled_out_uri, &[JSON], b"{}");
+        CONFIG.replace(WasmConfig::clone(&current));
+
+        assert_eq!(sampled_out, CTX_NEXT);
+        assert!(sent_payloads(sampled_out_uri).is_empty());
+        let sent = sent_payloads(uri);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["data"]["request"]["body"]["iban"], "*****");
+        assert_eq!(sent[0]["data"]["request"]["body"]["amount"], 10);
+        assert!(sent[0]["data"]["response"]["body"].is_null());
+        assert!(!response_read);
+    }
+
+    #[test]
+    fn test_oversized_bodies_are_truncated() {
+        let _exchange = exchange();
+        let uri = "/e2e/uploads";
+        let request_body = br#"{"password":"hunter2","items":[1,2,3,4,5,6,7,8,9]}"#;
+        let response_body = br#"{"id":1,"tags":["a","b","c","d","e","f"]}"#;
+
+        let current = CONFIG.get().unwrap();
+        let mut config = WasmConfig::clone(&current);
+        config.core.max_request_body_size = 36;
+        config.core.max_response_body_size = 16;
+        CONFIG.replace(config);
+
+        let ctx = send_request("POST", uri, &[JSON], request_body);
+        // The whole body still goes on to the next handler
+        let written = with_host(|host| host.request.written_body.clone());
+        assert_eq!(written.as_deref(), Some(request_body.as_slice()));
+        send_response(ctx, 200, &[JSON], response_body);
+        CONFIG.replace(WasmConfig::clone(&current));
+
+        let sent = sent_payloads(uri);
+        assert_eq!(sent.len(), 1);
+        let request = &sent[0]["data"]["request"]["body"];
+        assert_eq!(request["truncated"], true);
+        assert_eq!(request["original_size"], request_body.len());
+        assert_eq!(request["body"]["password"], "*****");
+        assert_eq!(request["body"]["items"], json!([1, 2]));
+
+        let response = &sent[0]["data"]["response"];
+        assert_eq!(
+            response["body"],
+            json!({ "truncated": true, "original_size": response_body.len(), "body": { "id": 1, "tags": [] } })
+        );
+        assert_eq!(response["size"], response_body.len());
+    }
+
+    #[test]
+    fn test_headers_are_filtered_and_masked() {
+        let _exchange = exchange();
+        let uri = "/e2e/session";
+
+        let current = CONFIG.get().unwrap();
+        let mut config = WasmConfig::clone(&current);
+        config.core.headers.allowed_headers =
+            vec!["Content-Type".into(), "Authorization".into(), "Set-Cookie".into()];
+        CONFIG.replace(config);
+
+        let ctx = send_request(
+            "POST",
+            uri,
+            &[JSON, ("Authorization", "Basic dXNlcjpwYXNz"), ("X-Forwarded-For", "203.0.113.7")],
+            br#"{"user":"ada"}"#,
+        );
+        send_response(ctx, 200, &[JSON, ("Set-Cookie", "sid=abc; Path=/; HttpOnly")], b"{}");
+        CONFIG.replace(WasmConfig::clone(&current));
+
+        let sent = sent_payloads(uri);
+        assert_eq!(sent.len(), 1);
+        let request = &sent[0]["data"]["request"]["headers"];
+        assert_eq!(request["Authorization"], "Basic *****");
+        assert!(request.get("X-Forwarded-For").is_none());
+        let response = &sent[0]["data"]["response"]["headers"];
+        assert_eq!(response["Set-Cookie"], "sid=*****; Path=/; HttpOnly");
+    }
+
+    #[test]
+    fn test_each_set_cookie_header_is_masked() {
+        let _exchange = exchange();
+        let uri = "/e2e/cookies";
+
+        let current = CONFIG.get().unwrap();
+        let mut config = WasmConfig::clone(&current);
+        config.core.headers.masked_cookies = vec!["sid".into()];
+        CONFIG.replace(config);
+
+        let ctx = send_request("POST", uri, &[JSON], br#"{"user":"ada"}"#);
+        send_response(
+            ctx,
+            200,
+            &[JSON, ("Set-Cookie", "a=1; Path=/"), ("Set-Cookie", "sid=secret; HttpOnly")],
+            b"{}",
+        );
+        CONFIG.replace(WasmConfig::clone(&current));
+
+        let sent = sent_payloads(uri);
+        assert_eq!(sent.len(), 1);
+        let response = &sent[0]["data"]["response"]["headers"];
+        assert_eq!(response["Set-Cookie"], "a=1; Path=/, sid=*****; HttpOnly");
+    }
+
+    #[test]
+    fn test_one_exchange_builds_the_payload_it_sends() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+        use treblle_core::constants::metrics::{PAYLOADS_BUILT, PAYLOADS_SENT};
+
+        let _exchange = exchange();
+        let uri = "/e2e/metrics";
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            let ctx = send_request("POST", uri, &[JSON], br#"{"user":"ada"}"#);
+            send_response(ctx, 200, &[JSON], br#"{"id":1}"#);
+        });
+        assert_eq!(sent_payloads(uri).len(), 1);
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let counter = |name: &str| -> u64 {
+            snapshot
+                .iter()
+                .filter(|(key, ..)| key.key().name() == name)
+                .map(|(.., value)| match value {
+                    DebugValue::Counter(count) => *count,
+                    _ => 0,
+                })
+                .sum()
+        };
+        assert_eq!(counter(PAYLOADS_BUILT), 1);
+        assert_eq!(counter(PAYLOADS_SENT), 1);
+    }
+}
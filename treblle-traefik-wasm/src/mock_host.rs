@@ -0,0 +1,408 @@
+//! Native mock of the `http_handler` host functions.
+//!
+//! Traefik provides the `http_handler` imports to the plugin through http-wasm. Outside
+//! of WASM, this module implements them on top of an in-memory [`MockHost`] following
+//! the same ABI, so the middleware can be exercised end to end under `cargo test`.
+//!
+//! - <https://github.com/http-wasm/http-wasm/blob/main/content/http-handler-abi.md>
+
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use serde_json::json;
+
+use crate::constants::http::REQUEST_KIND;
+
+static HOST: Mutex<MockHost> = Mutex::new(MockHost::new());
+
+/// Held by tests driving an exchange, as the mock host only holds one at a time
+static EXCHANGE: Mutex<()> = Mutex::new(());
+
+/// Headers and body of the request or the response
+#[derive(Debug, Default)]
+pub struct MockMessage {
+    /// Header names and values, a name may appear more than once
+    pub headers: Vec<(String, String)>,
+    /// Body read by the plugin with `read_body`
+    pub body: Vec<u8>,
+    /// Body written by the plugin with `write_body`, if any
+    pub written_body: Option<Vec<u8>>,
+    body_offset: usize,
+}
+
+impl MockMessage {
+    const fn new() -> Self {
+        Self { headers: Vec::new(), body: Vec::new(), written_body: None, body_offset: 0 }
+    }
+
+    fn set<'a, H>(&mut self, headers: H, body: &[u8])
+    where
+        H: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        *self = Self::new();
+        self.headers = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.body = body.to_vec();
+    }
+
+    /// Values of the header with the given (case-insensitive) name
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Replace the values of a header
+    fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        self.add_header(name, value);
+    }
+
+    fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Distinct header names, in the order they were first set
+    fn header_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in &self.headers {
+            if !names.iter().any(|seen| seen.eq_ignore_ascii_case(name)) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+/// State of the mocked host: the plugin configuration and the current exchange
+#[derive(Debug)]
+pub struct MockHost {
+    /// Configuration returned by `get_config`, [`default_config`] if unset.
+    ///
+    /// The plugin reads its configuration once per process, so this has to be set
+    /// before the first request is handled.
+    pub config: Option<String>,
+    /// Features enabled with `enable_features`
+    pub features: u32,
+    /// Messages logged by the plugin, with their level
+    pub logs: Vec<(i32, String)>,
+    pub method: String,
+    pub uri: String,
+    pub protocol_version: String,
+    pub source_addr: String,
+    pub status_code: u32,
+    pub request: MockMessage,
+    pub response: MockMessage,
+}
+
+impl MockHost {
+    const fn new() -> Self {
+        Self {
+            config: None,
+            features: 0,
+            logs: Vec::new(),
+            method: String::new(),
+            uri: String::new(),
+            protocol_version: String::new(),
+            source_addr: String::new(),
+            status_code: 0,
+            request: MockMessage::new(),
+            response: MockMessage::new(),
+        }
+    }
+
+    /// Start a new exchange with the given request, clearing the previous one
+    pub fn set_request<'a, H>(&mut self, method: &str, uri: &str, headers: H, body: &[u8])
+    where
+        H: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        self.features = 0;
+        self.logs.clear();
+        self.method = method.to_string();
+        self.uri = uri.to_string();
+        self.protocol_version = "HTTP/1.1".to_string();
+        self.source_addr = "127.0.0.1:54321".to_string();
+        self.status_code = 0;
+        self.request.set(headers, body);
+        self.response = MockMessage::new();
+    }
+
+    /// Set the response of the current exchange.
+    ///
+    /// Like the host's response writer, headers the plugin set on the response while
+    /// handling the request are kept.
+    pub fn set_response<'a, H>(&mut self, status_code: u32, headers: H, body: &[u8])
+    where
+        H: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let set_by_plugin = std::mem::take(&mut self.response.headers);
+
+        self.status_code = status_code;
+        self.response.set(headers, body);
+        self.response.headers.splice(0..0, set_by_plugin);
+    }
+
+    fn message(&mut self, kind: u32) -> &mut MockMessage {
+        if kind == REQUEST_KIND {
+            &mut self.request
+        } else {
+            &mut self.response
+        }
+    }
+}
+
+/// Run a function with exclusive access to the mocked host
+pub fn with_host<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut MockHost) -> R,
+{
+    f(&mut HOST.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Wait for exclusive use of the mocked exchange, until the guard is dropped
+pub fn exchange() -> MutexGuard<'static, ()> {
+    EXCHANGE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// File payloads are written to by the [`default_config`]
+pub fn payload_file_path() -> PathBuf {
+    std::env::temp_dir().join(format!("treblle-mock-host-{}.ndjson", std::process::id()))
+}
+
+/// Configuration used when none is set: payloads are flushed to
+/// [`payload_file_path`] as soon as they're queued, and trace headers are injected.
+pub fn default_config() -> String {
+    json!({
+        "apiKey": "test_key",
+        "transport": "file",
+        "transportFilePath": payload_file_path(),
+        "flushIntervalMs": 0,
+        "flushBudgetMs": 1000,
+        "requestIdHeader": "X-Treblle-Request-Id",
+        "forwardTraceHeaders": true,
+    })
+    .to_string()
+}
+
+/// Copy `data` to the guest buffer if it fits, returning its length like the host does
+///
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+unsafe fn write_to_buffer(data: &[u8], buf: *mut u8, buf_limit: u32) -> u32 {
+    let len = u32::try_from(data.len()).unwrap_or(u32::MAX);
+    if len <= buf_limit {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len());
+    }
+    len
+}
+
+/// Encode values NUL-terminated, returning `count << 32 | len` like the host does
+///
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+unsafe fn write_values(values: &[&str], buf: *mut u8, buf_limit: i32) -> i64 {
+    let encoded: Vec<u8> = values.iter().flat_map(|value| value.bytes().chain([0])).collect();
+    let len = write_to_buffer(&encoded, buf, u32::try_from(buf_limit).unwrap_or(0));
+
+    (i64::try_from(values.len()).unwrap_or(0) << 32) | i64::from(len)
+}
+
+/// # Safety
+///
+/// `message` must be valid for reads of `message_len` bytes.
+pub(crate) unsafe fn log(level: i32, message: *const u8, message_len: u32) {
+    let message = read_string(message, message_len);
+    with_host(|host| host.logs.push((level, message)));
+}
+
+/// # Safety
+///
+/// Has no preconditions, it's only `unsafe` like the import it mocks.
+pub(crate) unsafe fn enable_features(features: u32) -> u32 {
+    with_host(|host| {
+        host.features |= features;
+        host.features
+    })
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn get_config(buf: *mut u8, buf_limit: i32) -> i32 {
+    let config = with_host(|host| host.config.clone()).unwrap_or_else(default_config);
+    write_string(&config, buf, buf_limit)
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn get_header_names(header_kind: u32, buf: *mut u8, buf_limit: i32) -> i64 {
+    with_host(|host| write_values(&host.message(header_kind).header_names(), buf, buf_limit))
+}
+
+/// # Safety
+///
+/// `name_ptr` must be valid for reads of `name_len` bytes and `buf` for writes of
+/// `buf_limit` bytes.
+pub(crate) unsafe fn get_header_values(
+    header_kind: u32,
+    name_ptr: *const u8,
+    name_len: u32,
+    buf: *mut u8,
+    buf_limit: i32,
+) -> i64 {
+    let name = read_string(name_ptr, name_len);
+    with_host(|host| write_values(&host.message(header_kind).header_values(&name), buf, buf_limit))
+}
+
+/// # Safety
+///
+/// `name_ptr` must be valid for reads of `name_len` bytes and `value_ptr` for reads of
+/// `value_len` bytes.
+pub(crate) unsafe fn set_header_value(
+    header_kind: u32,
+    name_ptr: *const u8,
+    name_len: u32,
+    value_ptr: *const u8,
+    value_len: u32,
+) {
+    let name = read_string(name_ptr, name_len);
+    let value = read_string(value_ptr, value_len);
+    with_host(|host| host.message(header_kind).set_header(&name, &value));
+}
+
+/// # Safety
+///
+/// `name_ptr` must be valid for reads of `name_len` bytes and `value_ptr` for reads of
+/// `value_len` bytes.
+pub(crate) unsafe fn add_header_value(
+    header_kind: u32,
+    name_ptr: *const u8,
+    name_len: u32,
+    value_ptr: *const u8,
+    value_len: u32,
+) {
+    let name = read_string(name_ptr, name_len);
+    let value = read_string(value_ptr, value_len);
+    with_host(|host| host.message(header_kind).add_header(&name, &value));
+}
+
+/// # Safety
+///
+/// `ptr` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn read_body(body_kind: u32, ptr: *mut u8, buf_limit: u32) -> i64 {
+    with_host(|host| {
+        let message = host.message(body_kind);
+        let remaining = &message.body[message.body_offset..];
+        let len = remaining.len().min(buf_limit as usize);
+
+        std::ptr::copy_nonoverlapping(remaining.as_ptr(), ptr, len);
+        message.body_offset += len;
+
+        let eof = i64::from(message.body_offset == message.body.len());
+        (eof << 32) | i64::try_from(len).unwrap_or(0)
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be valid for reads of `message_len` bytes.
+pub(crate) unsafe fn write_body(body_kind: u32, ptr: *const u8, message_len: u32) {
+    let body = std::slice::from_raw_parts(ptr, message_len as usize);
+    with_host(|host| {
+        host.message(body_kind).written_body.get_or_insert_with(Vec::new).extend_from_slice(body);
+    });
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn get_method(buf: *mut u8, buf_limit: i32) -> i32 {
+    let method = with_host(|host| host.method.clone());
+    write_string(&method, buf, buf_limit)
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn get_uri(buf: *mut u8, buf_limit: i32) -> i32 {
+    let uri = with_host(|host| host.uri.clone());
+    write_string(&uri, buf, buf_limit)
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn get_protocol_version(buf: *mut u8, buf_limit: i32) -> i32 {
+    let protocol_version = with_host(|host| host.protocol_version.clone());
+    write_string(&protocol_version, buf, buf_limit)
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+pub(crate) unsafe fn get_source_addr(buf: *mut u8, buf_limit: i32) -> i32 {
+    let source_addr = with_host(|host| host.source_addr.clone());
+    write_string(&source_addr, buf, buf_limit)
+}
+
+/// # Safety
+///
+/// Has no preconditions, it's only `unsafe` like the import it mocks.
+pub(crate) unsafe fn get_status_code() -> u32 {
+    with_host(|host| host.status_code)
+}
+
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+unsafe fn read_string(ptr: *const u8, len: u32) -> String {
+    String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len as usize)).into_owned()
+}
+
+/// # Safety
+///
+/// `buf` must be valid for writes of `buf_limit` bytes.
+unsafe fn write_string(value: &str, buf: *mut u8, buf_limit: i32) -> i32 {
+    let len = write_to_buffer(value.as_bytes(), buf, u32::try_from(buf_limit).unwrap_or(0));
+    i32::try_from(len).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_values_are_nul_terminated() {
+        let mut message = MockMessage::new();
+        message.set([("Accept", "text/html"), ("accept", "application/json")], b"");
+
+        assert_eq!(message.header_names(), ["Accept"]);
+        assert_eq!(message.header_values("ACCEPT"), ["text/html", "application/json"]);
+
+        let mut buf = [0u8; 64];
+        // SAFETY: the buffer holds 64 bytes
+        let result =
+            unsafe { write_values(&message.header_values("accept"), buf.as_mut_ptr(), 64) };
+        let len = usize::try_from(result & 0xffff_ffff).unwrap();
+
+        assert_eq!(result >> 32, 2);
+        assert_eq!(&buf[..len], b"text/html\0application/json\0");
+    }
+
+    #[test]
+    fn test_values_larger_than_the_buffer_are_not_written() {
+        let mut buf = [0u8; 4];
+        // SAFETY: the buffer holds 4 bytes
+        let len = unsafe { write_string("too long", buf.as_mut_ptr(), 4) };
+
+        assert_eq!(len, 8);
+        assert_eq!(buf, [0; 4]);
+    }
+}
@@ -0,0 +1,222 @@
+//! Guest-side queue keeping network sends off the request path.
+//!
+//! Payloads are queued instead of being sent from the hook that produced them.
+//! The queue is flushed opportunistically from `handle_request`/`handle_response`,
+//! at most once per flush interval unless enough payloads are waiting, and every
+//! flush stops once its time budget is used up. Payloads left over are sent by a
+//! later flush.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use treblle_core::schema::TrebllePayload;
+use treblle_core::{Result, TreblleError};
+
+/// Policy deciding when and for how long the queue is flushed
+#[derive(Clone, Copy, Debug)]
+pub struct FlushPolicy {
+    /// Minimum time between two flushes
+    pub interval: Duration,
+    /// Number of queued payloads that triggers a flush before the interval has passed
+    pub max_payloads: usize,
+    /// Maximum time a single flush may take
+    pub budget: Duration,
+}
+
+/// Outcome of a flush
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlushStats {
+    /// Payloads delivered
+    pub sent: usize,
+    /// Payloads dropped after failing to be delivered
+    pub failed: usize,
+}
+
+/// Bounded FIFO queue of payloads waiting to be sent
+#[derive(Debug, Default)]
+pub struct PayloadQueue {
+    payloads: VecDeque<TrebllePayload>,
+    last_flush: Option<Instant>,
+}
+
+impl PayloadQueue {
+    /// Create an empty queue
+    pub const fn new() -> Self {
+        Self { payloads: VecDeque::new(), last_flush: None }
+    }
+
+    /// Queue a payload, evicting the oldest ones if the queue holds `capacity` payloads.
+    /// Returns the number of evicted payloads.
+    pub fn push(&mut self, payload: TrebllePayload, capacity: usize) -> usize {
+        let mut evicted = 0;
+        while self.payloads.len() >= capacity.max(1) {
+            self.payloads.pop_front();
+            evicted += 1;
+        }
+
+        self.payloads.push_back(payload);
+        evicted
+    }
+
+    /// Number of queued payloads
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    /// Whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+
+    /// Whether a flush is due under the given policy
+    pub fn should_flush(&self, policy: &FlushPolicy) -> bool {
+        if self.payloads.is_empty() {
+            return false;
+        }
+
+        self.payloads.len() >= policy.max_payloads
+            || self.last_flush.is_none_or(|last| last.elapsed() >= policy.interval)
+    }
+
+    /// Send queued payloads in order until the queue is empty or the time budget is used up.
+    ///
+    /// `send` receives the deadline the send has to finish by. A payload that runs into
+    /// the deadline ([`TreblleError::Timeout`]) is put back at the front of the queue;
+    /// a payload that fails otherwise is dropped and ends this flush.
+    pub fn flush<F>(&mut self, budget: Duration, mut send: F) -> FlushStats
+    where
+        F: FnMut(&TrebllePayload, Instant) -> Result<()>,
+    {
+        let start = Instant::now();
+        let deadline = start + budget;
+        let mut stats = FlushStats::default();
+        self.last_flush = Some(start);
+
+        while Instant::now() < deadline {
+            let Some(payload) = self.payloads.pop_front() else { break };
+
+            match send(&payload, deadline) {
+                Ok(()) => stats.sent += 1,
+                Err(TreblleError::Timeout) => {
+                    self.payloads.push_front(payload);
+                    break;
+                }
+                Err(_) => {
+                    stats.failed += 1;
+                    break;
+                }
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use treblle_core::schema::PayloadData;
+
+    const CAPACITY: usize = 10;
+
+    fn payload(project_id: &str) -> TrebllePayload {
+        TrebllePayload {
+            api_key: "test_key".to_string(),
+            project_id: project_id.to_string(),
+            version: 0.1,
+            sdk: "treblle-rust".to_string(),
+            data: PayloadData::default(),
+        }
+    }
+
+    fn policy() -> FlushPolicy {
+        FlushPolicy {
+            interval: Duration::from_secs(60),
+            max_payloads: 3,
+            budget: Duration::from_millis(50),
+        }
+    }
+
+    #[test]
+    fn test_push_evicts_oldest_when_full() {
+        let mut queue = PayloadQueue::new();
+
+        assert_eq!(queue.push(payload("a"), 2), 0);
+        assert_eq!(queue.push(payload("b"), 2), 0);
+        assert_eq!(queue.push(payload("c"), 2), 1);
+
+        let mut sent = Vec::new();
+        queue.flush(Duration::from_secs(1), |p, _| {
+            sent.push(p.project_id.clone());
+            Ok(())
+        });
+        assert_eq!(sent, ["b", "c"]);
+    }
+
+    #[test]
+    fn test_should_flush() {
+        let mut queue = PayloadQueue::new();
+        assert!(!queue.should_flush(&policy()));
+
+        // The first payload is flushed right away, later ones wait for the interval
+        queue.push(payload("a"), CAPACITY);
+        assert!(queue.should_flush(&policy()));
+        queue.flush(Duration::from_secs(1), |_, _| Ok(()));
+
+        queue.push(payload("b"), CAPACITY);
+        queue.push(payload("c"), CAPACITY);
+        assert!(!queue.should_flush(&policy()));
+
+        // Enough queued payloads trigger a flush before the interval has passed
+        queue.push(payload("d"), CAPACITY);
+        assert!(queue.should_flush(&policy()));
+    }
+
+    #[test]
+    fn test_flush_keeps_payloads_hitting_the_deadline() {
+        let mut queue = PayloadQueue::new();
+        queue.push(payload("a"), CAPACITY);
+        queue.push(payload("b"), CAPACITY);
+
+        let stats = queue.flush(Duration::from_secs(1), |p, _| {
+            if p.project_id == "a" {
+                Ok(())
+            } else {
+                Err(TreblleError::Timeout)
+            }
+        });
+
+        assert_eq!(stats, FlushStats { sent: 1, failed: 0 });
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_flush_drops_failed_payload() {
+        let mut queue = PayloadQueue::new();
+        queue.push(payload("a"), CAPACITY);
+        queue.push(payload("b"), CAPACITY);
+
+        let stats = queue.flush(Duration::from_secs(1), |_, _| {
+            Err(TreblleError::Http("unavailable".to_string()))
+        });
+
+        assert_eq!(stats, FlushStats { sent: 0, failed: 1 });
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_flush_respects_budget() {
+        let mut queue = PayloadQueue::new();
+        for id in ["a", "b", "c"] {
+            queue.push(payload(id), CAPACITY);
+        }
+
+        let stats = queue.flush(Duration::from_millis(10), |_, _| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(())
+        });
+
+        assert_eq!(stats.sent, 1);
+        assert_eq!(queue.len(), 2);
+    }
+}
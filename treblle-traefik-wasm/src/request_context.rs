@@ -0,0 +1,186 @@
+//! Per-request state correlated through the http-wasm `req_ctx` value.
+//!
+//! `handle_request` stores the state of a request under a context ID and returns
+//! that ID in the high 32 bits of its result. The host passes it back to
+//! `handle_response` as `req_ctx`, which takes the state out of the store again.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use treblle_core::RequestInfo;
+
+/// Maximum number of requests tracked at once; the oldest are evicted beyond that
+pub const MAX_REQUEST_CONTEXTS: usize = 10_000;
+
+/// Value returned by `handle_request` to continue to the next handler without a context
+pub const CTX_NEXT: i64 = 1;
+
+/// State kept between `handle_request` and `handle_response`
+#[derive(Debug)]
+pub struct RequestContext {
+    /// When the request was handed to the next handler
+    pub start: Instant,
+    /// Masked request info, if the request was processed
+    pub request: Option<RequestInfo>,
+    /// Route template of the request path, if path rules are configured
+    pub route_path: Option<String>,
+    /// Path of the request, to resolve its route's settings for the response
+    pub path: String,
+    /// Method of a request whose capture depends on its response
+    pub deferred: Option<String>,
+}
+
+/// Store of in-flight request contexts keyed by context ID
+#[derive(Debug)]
+pub struct RequestContexts {
+    contexts: BTreeMap<i32, RequestContext>,
+    next_id: i32,
+    max_contexts: usize,
+    sample_credit: f64,
+}
+
+impl Default for RequestContexts {
+    fn default() -> Self {
+        Self::new(MAX_REQUEST_CONTEXTS)
+    }
+}
+
+impl RequestContexts {
+    /// Create a store tracking at most `max_contexts` requests
+    pub const fn new(max_contexts: usize) -> Self {
+        Self { contexts: BTreeMap::new(), next_id: 1, max_contexts, sample_credit: 0.0 }
+    }
+
+    /// Decide whether the next request is sampled.
+    ///
+    /// Sampling is deterministic: with a rate of `0.25` every fourth request is kept,
+    /// so the sampled share is exact even over short periods.
+    pub fn sample(&mut self, rate: f64) -> bool {
+        self.sample_credit += rate.clamp(0.0, 1.0);
+        if self.sample_credit >= 1.0 {
+            self.sample_credit -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Store a request context and return its ID
+    pub fn insert(&mut self, context: RequestContext) -> i32 {
+        let id = self.next_id;
+        self.next_id = if id == i32::MAX { 1 } else { id + 1 };
+
+        // Responses that never arrive would otherwise leak their context. IDs wrap
+        // around, so the oldest context is found by its start rather than its ID.
+        while self.contexts.len() >= self.max_contexts {
+            let Some(oldest) =
+                self.contexts.iter().min_by_key(|(_, context)| context.start).map(|(id, _)| *id)
+            else {
+                break;
+            };
+            self.contexts.remove(&oldest);
+        }
+
+        self.contexts.insert(id, context);
+        id
+    }
+
+    /// Remove and return the context stored under an ID
+    pub fn take(&mut self, id: i32) -> Option<RequestContext> {
+        self.contexts.remove(&id)
+    }
+
+    /// Number of tracked requests
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// Whether no requests are tracked
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+}
+
+/// Encode a context ID into the value returned by `handle_request`:
+/// the ID in the high 32 bits, and `1` in the low bits to continue to the next handler.
+pub fn ctx_next(id: i32) -> i64 {
+    (i64::from(id) << 32) | CTX_NEXT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn context() -> RequestContext {
+        context_at(Instant::now())
+    }
+
+    fn context_at(start: Instant) -> RequestContext {
+        RequestContext {
+            start,
+            request: None,
+            route_path: None,
+            path: "/".to_string(),
+            deferred: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_and_take() {
+        let mut contexts = RequestContexts::default();
+
+        let first = contexts.insert(context());
+        let second = contexts.insert(context());
+        assert_ne!(first, second);
+        assert_eq!(contexts.len(), 2);
+
+        assert!(contexts.take(first).is_some());
+        assert!(contexts.take(first).is_none());
+        assert_eq!(contexts.len(), 1);
+    }
+
+    #[test]
+    fn test_evicts_oldest_when_full() {
+        let mut contexts = RequestContexts::new(2);
+
+        let first = contexts.insert(context());
+        let second = contexts.insert(context());
+        let third = contexts.insert(context());
+
+        assert!(contexts.take(first).is_none());
+        assert!(contexts.take(second).is_some());
+        assert!(contexts.take(third).is_some());
+    }
+
+    #[test]
+    fn test_evicts_oldest_after_id_wraparound() {
+        let mut contexts = RequestContexts::new(2);
+        contexts.next_id = i32::MAX;
+        let now = Instant::now();
+
+        let first = contexts.insert(context_at(now));
+        let second = contexts.insert(context_at(now + Duration::from_millis(1)));
+        let third = contexts.insert(context_at(now + Duration::from_millis(2)));
+        assert_eq!((first, second), (i32::MAX, 1));
+
+        assert!(contexts.take(first).is_none());
+        assert!(contexts.take(second).is_some());
+        assert!(contexts.take(third).is_some());
+    }
+
+    #[test]
+    fn test_ctx_next_encoding() {
+        assert_eq!(ctx_next(0), 1);
+        assert_eq!(ctx_next(7) >> 32, 7);
+        assert_eq!(ctx_next(7) & 0xffff_ffff, 1);
+    }
+
+    #[test]
+    fn test_sampling_rate() {
+        let mut contexts = RequestContexts::default();
+        assert_eq!((0..100).filter(|_| contexts.sample(0.25)).count(), 25);
+        assert_eq!((0..100).filter(|_| contexts.sample(1.0)).count(), 100);
+        assert_eq!((0..100).filter(|_| contexts.sample(0.0)).count(), 0);
+    }
+}
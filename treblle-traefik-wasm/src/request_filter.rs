@@ -0,0 +1,90 @@
+//! Cheap checks deciding whether a request is captured.
+//!
+//! They only need the method, the `Host` header and the request target, so
+//! they run before any headers or bodies are extracted.
+
+/// Extract the path from a request target, dropping the query and fragment.
+///
+/// Absolute-form targets (`http://host/path`) are reduced to their path too.
+pub fn request_path(uri: &str) -> &str {
+    let path = match uri.find("://") {
+        Some(scheme_end) => {
+            let authority_start = scheme_end + 3;
+            uri[authority_start..].find('/').map_or("/", |i| &uri[authority_start + i..])
+        }
+        None => uri,
+    };
+
+    let end = path.find(['?', '#']).unwrap_or(path.len());
+    match &path[..end] {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Strip the port from a `Host` header value
+pub fn host_without_port(host: &str) -> &str {
+    let host = host.trim();
+
+    // IPv6 literals are enclosed in brackets, e.g. `[::1]:8080`
+    if let Some(rest) = host.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+/// Check if a host matches a host rule.
+///
+/// Rules match case-insensitively and ignore the port. A leading `*.` matches
+/// any subdomain, e.g. `*.example.com` matches `api.example.com` but not
+/// `example.com`.
+pub fn host_matches(rule: &str, host: &str) -> bool {
+    let host = host_without_port(host);
+
+    match rule.trim().strip_prefix("*.") {
+        Some(domain) => host
+            .len()
+            .checked_sub(domain.len() + 1)
+            .and_then(|dot| host.get(dot..))
+            .and_then(|suffix| suffix.strip_prefix('.'))
+            .is_some_and(|suffix| suffix.eq_ignore_ascii_case(domain)),
+        None => host.eq_ignore_ascii_case(rule.trim()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(request_path("/health"), "/health");
+        assert_eq!(request_path("/health?x=1"), "/health");
+        assert_eq!(request_path("/api/users#top"), "/api/users");
+        assert_eq!(request_path("?x=1"), "/");
+        assert_eq!(request_path(""), "/");
+        assert_eq!(request_path("http://example.com/api?x=1"), "/api");
+        assert_eq!(request_path("https://example.com"), "/");
+    }
+
+    #[test]
+    fn test_host_without_port() {
+        assert_eq!(host_without_port("example.com"), "example.com");
+        assert_eq!(host_without_port("example.com:8080"), "example.com");
+        assert_eq!(host_without_port("[::1]:8080"), "::1");
+        assert_eq!(host_without_port("[::1]"), "::1");
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("api.example.com", "api.example.com"));
+        assert!(host_matches("api.example.com", "API.Example.com:443"));
+        assert!(!host_matches("api.example.com", "example.com"));
+
+        assert!(host_matches("*.example.com", "api.example.com"));
+        assert!(host_matches("*.example.com", "v1.api.example.com:8080"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+    }
+}
@@ -0,0 +1,98 @@
+//! Route templates derived from request paths.
+//!
+//! Traefik doesn't know the routes of the services behind it, so the plugin
+//! approximates them by replacing the path segments that look like IDs, e.g.
+//! `/users/8271/orders/99` becomes `/users/{id}/orders/{id}`. Requests to the
+//! same endpoint then share a template, which Treblle groups them by.
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use treblle_core::{Result, TreblleError};
+
+/// Replaces the path segments matching a pattern
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
+pub struct PathRule {
+    /// Regex pattern a whole path segment is matched against
+    #[serde(serialize_with = "serialize_pattern", deserialize_with = "deserialize_pattern")]
+    #[cfg_attr(test, schemars(with = "String"))]
+    pub pattern: Regex,
+
+    /// Placeholder replacing matching segments, e.g. `{id}`
+    pub replacement: String,
+}
+
+impl PathRule {
+    /// Create a rule replacing the segments matching a regex pattern
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TreblleError::Config`] if the pattern isn't a valid regex.
+    pub fn new<T: Into<String>>(pattern: &str, replacement: T) -> Result<Self> {
+        let pattern = Regex::new(pattern)
+            .map_err(|e| TreblleError::Config(format!("Invalid path rule pattern: {e}")))?;
+
+        Ok(Self { pattern, replacement: replacement.into() })
+    }
+}
+
+/// Rules replacing UUIDs with `{uuid}` and numeric segments with `{id}`
+pub(crate) fn default_path_rules() -> Vec<PathRule> {
+    vec![
+        PathRule::new(
+            "^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$",
+            "{uuid}",
+        )
+        .expect("valid UUID pattern"),
+        PathRule::new("^[0-9]+$", "{id}").expect("valid numeric pattern"),
+    ]
+}
+
+/// Build the route template of a path, each segment is replaced by the first matching rule
+pub fn route_template(path: &str, rules: &[PathRule]) -> String {
+    path.split('/')
+        .map(|segment| {
+            rules
+                .iter()
+                .find(|rule| !segment.is_empty() && rule.pattern.is_match(segment))
+                .map_or(segment, |rule| rule.replacement.as_str())
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn serialize_pattern<S: Serializer>(pattern: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(pattern.as_str())
+}
+
+fn deserialize_pattern<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(|e| de::Error::custom(format!("Invalid path rule pattern: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_route_template() {
+        let rules = default_path_rules();
+
+        assert_eq!(route_template("/users/8271/orders/99", &rules), "/users/{id}/orders/{id}");
+        assert_eq!(
+            route_template("/carts/3f2b8c1e-9a4d-4c2b-8e1f-0a1b2c3d4e5f/items", &rules),
+            "/carts/{uuid}/items"
+        );
+        assert_eq!(route_template("/v2/users/me/", &rules), "/v2/users/me/");
+        assert_eq!(route_template("/", &rules), "/");
+        assert_eq!(route_template("/users/42", &[]), "/users/42");
+
+        let rules: Vec<PathRule> = serde_json::from_value(
+            json!([{ "pattern": "^[a-z]{2}-[A-Z]{2}$", "replacement": "{locale}" }]),
+        )
+        .unwrap();
+        assert_eq!(route_template("/en-US/docs", &rules), "/{locale}/docs");
+        assert!(PathRule::new("(", "{id}").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+//! Request IDs and W3C Trace Context headers injected by the plugin.
+//!
+//! A request's ID is the trace ID of its `traceparent` header when it has a valid one,
+//! so Treblle records line up with the client's traces, and a new random ID otherwise.
+//!
+//! - <https://www.w3.org/TR/trace-context/#traceparent-header>
+
+use treblle_core::utils::random_u64;
+
+/// Name of the W3C Trace Context header
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Extract the trace ID from a `traceparent` header value, if it's valid
+pub fn trace_id(traceparent: &str) -> Option<&str> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, parent_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+
+    // Later versions may append fields, version 00 may not
+    let valid = is_lower_hex(version, 2)
+        && version != "ff"
+        && (version != "00" || parts.next().is_none())
+        && is_lower_hex(trace_id, 32)
+        && is_lower_hex(parent_id, 16)
+        && is_lower_hex(flags, 2)
+        && trace_id.bytes().any(|b| b != b'0')
+        && parent_id.bytes().any(|b| b != b'0');
+
+    valid.then_some(trace_id)
+}
+
+/// Create a request ID in the format of a trace ID
+pub fn new_request_id() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64())
+}
+
+/// Create a sampled `traceparent` header value for the trace with the given ID
+pub fn new_traceparent(trace_id: &str) -> String {
+    format!("00-{trace_id}-{:016x}-01", random_u64() | 1)
+}
+
+fn is_lower_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    #[test]
+    fn test_trace_id() {
+        assert_eq!(trace_id(&format!("00-{TRACE_ID}-00f067aa0ba902b7-01")), Some(TRACE_ID));
+        assert_eq!(trace_id(&format!("01-{TRACE_ID}-00f067aa0ba902b7-01-extra")), Some(TRACE_ID));
+
+        assert!(trace_id(&format!("00-{TRACE_ID}-00f067aa0ba902b7-01-extra")).is_none());
+        assert!(trace_id(&format!("ff-{TRACE_ID}-00f067aa0ba902b7-01")).is_none());
+        assert!(trace_id(&format!("00-{}-00f067aa0ba902b7-01", "0".repeat(32))).is_none());
+        assert!(trace_id(&format!("00-{TRACE_ID}-0000000000000000-01")).is_none());
+        assert!(trace_id(&format!("00-{}-00f067aa0ba902b7-01", TRACE_ID.to_uppercase())).is_none());
+        assert!(trace_id("00-abc-def-01").is_none());
+        assert!(trace_id("").is_none());
+    }
+
+    #[test]
+    fn test_new_ids_are_valid() {
+        let request_id = new_request_id();
+        assert_ne!(request_id, new_request_id());
+
+        let traceparent = new_traceparent(&request_id);
+        assert_eq!(trace_id(&traceparent), Some(request_id.as_str()));
+        assert!(traceparent.ends_with("-01"));
+    }
+}